@@ -1,79 +1,696 @@
-use std::time::{Duration, Instant};
+pub mod chat_list;
+pub mod chat_view;
+pub mod input;
+pub mod search;
+pub mod session;
+pub mod user_card;
+pub mod whispers;
 
+use std::collections::HashMap;
+
+use crate::badges::BadgeRegistry;
+use crate::chat::alias;
+use crate::chat::collapse::Collapser;
+use crate::chat::color::UsernameColors;
+use crate::chat::command::{self, Input};
+use crate::chat::completion::{self, Sources};
+use crate::chat::event::UserState;
+use crate::chat::filter::{Filter, IgnoredUser};
+use crate::chat::highlight::Highlighter;
+use crate::chat::history::{History, Message, DEFAULT_CAP};
+use crate::chat::logs::Logs;
+use crate::chat::mod_log::ModLog;
+use crate::chat::room_state::RoomStates;
+use crate::chat::whispers::Whispers;
+use crate::chat::ChatEvent;
+use crate::config::Config;
+use crate::emotes::{EmoteStore, Source};
+use crate::event::Event as SessionEvent;
+use crate::eventsub;
+use crate::helix;
+use crate::images::{self, Images};
+use crate::recent_messages::RecentMessages;
+use crate::twitch::ChatConfig;
 use crate::window;
 
-pub enum Event {
-  Test,
+use self::chat_list::ChatList;
+use self::chat_view::{Action, Assets, Focus};
+use self::input::{ChatInput, Sent};
+use self::search::SearchView;
+use self::session::{Command, Session};
+use self::user_card::UserCard;
+use self::whispers::WhisperView;
+
+pub enum Event {}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+  Channel,
+  Mentions,
+  Whispers,
+  Search,
 }
 
 pub struct MainWindow {
-  test: Instant,
-  floating_button: Option<window::Id>,
+  config: Config,
+  session: Option<Session>,
+  channels: Vec<String>,
+  selected: usize,
+  view: View,
+  /// The channel and root message id of the open thread view.
+  thread: Option<(String, String)>,
+  user_card: Option<UserCard>,
+  /// The outlined message and its channel.
+  focus: Option<(String, Focus)>,
+
+  filter: Filter,
+  history: History,
+  room_states: RoomStates,
+  user_states: HashMap<String, UserState>,
+  /// Our own user id, from `GLOBALUSERSTATE`.
+  user_id: Option<String>,
+  /// The emote sets from `GLOBALUSERSTATE` which were loaded.
+  emote_sets: Vec<String>,
+  inputs: HashMap<String, ChatInput>,
+  collapsers: HashMap<String, Collapser>,
+  lists: HashMap<String, ChatList>,
+  emotes: EmoteStore,
+  badges: BadgeRegistry,
+  images: Option<Images>,
+  colors: UsernameColors,
+  whispers: Whispers,
+  whisper_view: WhisperView,
+  search_view: SearchView,
+  /// Helix users by login, loaded for user cards.
+  users: HashMap<String, helix::users::User>,
+  mod_log: ModLog,
+  logs: Logs,
 }
 
 impl MainWindow {
   pub fn new() -> Self {
+    let config = Config::load().unwrap_or_else(|e| {
+      tracing::warn!("{e}");
+      Config::default()
+    });
+    let mut history = History::new(DEFAULT_CAP);
+    history.set_highlighter(Highlighter::new(&config.highlights));
+    let emotes = EmoteStore::with_priority(config.emote_priority.clone());
+
     MainWindow {
-      test: Instant::now(),
-      floating_button: None,
+      filter: Filter::new(&config.ignores),
+      logs: Logs::start(&config.logs),
+      config,
+      session: None,
+      channels: channels_from_env(),
+      selected: 0,
+      view: View::Channel,
+      thread: None,
+      user_card: None,
+      focus: None,
+      history,
+      room_states: RoomStates::new(),
+      user_states: HashMap::new(),
+      user_id: None,
+      emote_sets: Vec::new(),
+      inputs: HashMap::new(),
+      collapsers: HashMap::new(),
+      lists: HashMap::new(),
+      emotes,
+      badges: BadgeRegistry::new(),
+      images: None,
+      colors: UsernameColors::new(),
+      whispers: Whispers::load().unwrap_or_else(|e| {
+        tracing::warn!("{e}");
+        Whispers::new()
+      }),
+      whisper_view: WhisperView::new(),
+      search_view: SearchView::new(),
+      users: HashMap::new(),
+      mod_log: ModLog::load().unwrap_or_else(|e| {
+        tracing::warn!("{e}");
+        ModLog::new()
+      }),
+    }
+  }
+
+  fn poll_session(&mut self, ui: &egui::Context) {
+    let session = self.session.get_or_insert_with(|| {
+      let config = config_from_env();
+      let helix = config.as_ref().and_then(helix_from_env);
+      let recent =
+        RecentMessages::from_config(reqwest::Client::new(), &self.config.recent_messages);
+      Session::start(config, helix, recent, self.channels.clone(), ui.clone())
+    });
+
+    while let Some(event) = session.poll() {
+      let message = match event {
+        SessionEvent::Chat(message) => message,
+        SessionEvent::Emotes {
+          room_id,
+          source,
+          emotes,
+        } => {
+          match room_id {
+            Some(room_id) => self.emotes.set_channel(&room_id, source, emotes),
+            None => self.emotes.set_global(source, emotes),
+          }
+          continue;
+        }
+        SessionEvent::Badges { room_id, sets } => {
+          match room_id {
+            Some(room_id) => self.badges.set_channel(&room_id, sets),
+            None => self.badges.set_global(sets),
+          }
+          continue;
+        }
+        SessionEvent::RecentMessages { channel, messages } => {
+          if self.channels.contains(&channel) {
+            let events = messages
+              .iter()
+              .filter_map(ChatEvent::parse)
+              .filter_map(|event| self.filter.apply(event))
+              .collect::<Vec<_>>();
+            self.history.backfill(&channel, &events);
+          }
+          continue;
+        }
+        SessionEvent::User(user) => {
+          self.users.insert(user.login.clone(), *user);
+          continue;
+        }
+        SessionEvent::System { channel, text } => {
+          self.history.push(&channel, Message::system(text));
+          continue;
+        }
+        SessionEvent::WhisperFailed { login, id, error } => {
+          self.whispers.set_error(&login, &id, error);
+          continue;
+        }
+        SessionEvent::EventSub(event) => {
+          if let eventsub::Event::Revoked { topic, status } = &*event {
+            tracing::warn!(topic, status, "eventsub subscription was revoked");
+          }
+          let channel = event
+            .broadcaster_id()
+            .and_then(|id| self.room_states.by_room_id(id))
+            .map(|room| room.channel.clone());
+          if let (Some(channel), Some(text)) = (channel, event.text()) {
+            self.history.push(&channel, Message::system(text));
+          }
+          continue;
+        }
+      };
+      let Some(event) = ChatEvent::parse(&message).and_then(|e| self.filter.apply(e)) else {
+        continue;
+      };
+      self.logs.log(&event, message.raw());
+      match &event {
+        ChatEvent::RoomState(update) => {
+          let joined =
+            !update.room_id.is_empty() && self.room_states.by_room_id(&update.room_id).is_none();
+          self.room_states.update(update);
+          if joined {
+            session.send(Command::LoadEmotes {
+              room_id: Some(update.room_id.clone()),
+            });
+            session.send(Command::LoadBadges {
+              room_id: Some(update.room_id.clone()),
+            });
+          }
+        }
+        ChatEvent::UserState(state) => {
+          self
+            .user_states
+            .insert(state.channel.clone(), state.clone());
+        }
+        ChatEvent::GlobalUserState(state) => {
+          self.user_id = Some(state.user_id.clone());
+          if state.emote_sets != self.emote_sets {
+            self.emote_sets = state.emote_sets.clone();
+            session.send(Command::LoadEmoteSets {
+              ids: state.emote_sets.clone(),
+            });
+          }
+        }
+        ChatEvent::ClearChat(event) => {
+          self.mod_log.record(event);
+        }
+        ChatEvent::Whisper(whisper) => {
+          let login = login_from_env();
+          self.whispers.handle(whisper, login.as_deref());
+        }
+        _ => {}
+      }
+      self.history.handle(&event);
     }
   }
 
   pub fn draw(&mut self, ctx: window::Context<'_, Event>) {
-    egui::CentralPanel::default().show(ctx.ui(), |ui| {
-      if self.test.elapsed() < Duration::from_secs(1) {
-        ui.label("Test");
+    self.poll_session(ctx.ui());
+    let images = self
+      .images
+      .get_or_insert_with(|| images::Config::new().start(ctx.waker()));
+    images.poll(ctx.ui());
+
+    let mut toggled_log = None;
+    egui::TopBottomPanel::top("channels").show(ctx.ui(), |ui| {
+      ui.horizontal(|ui| {
+        for (i, channel) in self.channels.iter().enumerate() {
+          let selected = self.view == View::Channel && self.selected == i;
+          let tab = ui.selectable_label(selected, channel);
+          if tab.clicked() {
+            self.selected = i;
+            self.view = View::Channel;
+          }
+          tab.context_menu(|ui| {
+            let mut enabled = self.logs.is_enabled(channel);
+            if ui.checkbox(&mut enabled, "Log to disk").changed() {
+              toggled_log = Some((channel.clone(), enabled));
+              ui.close_menu();
+            }
+          });
+        }
+        ui.separator();
+        let mentions = format!("Mentions ({})", self.history.mentions().len());
+        if ui
+          .selectable_label(self.view == View::Mentions, mentions)
+          .clicked()
+        {
+          self.view = View::Mentions;
+        }
+        let whispers = match self.whispers.unread() {
+          0 => "Whispers".to_string(),
+          n => format!("Whispers ({n})"),
+        };
+        if ui
+          .selectable_label(self.view == View::Whispers, whispers)
+          .clicked()
+        {
+          self.view = View::Whispers;
+        }
+        if ui
+          .selectable_label(self.view == View::Search, "Search")
+          .clicked()
+        {
+          self.view = View::Search;
+        }
+      });
+    });
+
+    let mut assets = Assets {
+      emotes: &self.emotes,
+      badges: &self.badges,
+      images,
+      colors: &mut self.colors,
+    };
+    let mut actions = Vec::new();
+    let mut sent = None;
+
+    if let Some((channel, root)) = &self.thread {
+      let mut open = true;
+      let action = egui::Window::new("Thread")
+        .open(&mut open)
+        .default_size([400.0, 300.0])
+        .show(ctx.ui(), |ui| {
+          let history = self.history.channel(channel)?;
+          let reply = ui.button("Reply in thread").clicked();
+          let action = chat_view::thread(ui, history, root, &mut assets);
+          if reply {
+            let first = history
+              .thread(root)
+              .find_map(|entry| match &entry.message {
+                Message::Chat(m) => Some(m),
+                _ => None,
+              })?;
+            return Some(Action::Reply {
+              channel: channel.clone(),
+              target: chat_view::reply_target(first),
+            });
+          }
+          action
+        })
+        .and_then(|response| response.inner)
+        .flatten();
+      actions.extend(action);
+      if !open {
+        self.thread = None;
+      }
+    }
+
+    if let Some(card) = &self.user_card {
+      let mut open = true;
+      let moderator = is_moderator(&self.user_states, &card.channel);
+      let action = egui::Window::new(format!("User {}", card.login))
+        .id(egui::Id::new("user_card"))
+        .open(&mut open)
+        .default_size([360.0, 400.0])
+        .show(ctx.ui(), |ui| {
+          card.show(
+            ui,
+            self.users.get(&card.login),
+            self.history.channel(&card.channel),
+            &self.mod_log,
+            moderator,
+            &mut assets,
+          )
+        })
+        .and_then(|response| response.inner)
+        .flatten();
+      actions.extend(action);
+      if !open {
+        self.user_card = None;
       }
+    }
 
-      if self.floating_button.is_none() && ui.button("open test button window").clicked() {
-        self.floating_button = Some(ctx.create_window(FloatingButton::new(ctx.id())));
+    if self.view == View::Whispers {
+      let sent = egui::CentralPanel::default()
+        .show(ctx.ui(), |ui| {
+          self.whisper_view.show(ui, &mut self.whispers)
+        })
+        .inner;
+      if let Some((to, text)) = sent {
+        let from = login_from_env().unwrap_or_default();
+        let id = self.whispers.push_outgoing(&to, &from, &text);
+        let to_id = self
+          .whispers
+          .get(&to)
+          .and_then(|c| c.user_id.clone())
+          .or_else(|| {
+            let login = to.trim_start_matches('@').to_lowercase();
+            self.users.get(&login).map(|u| u.id.clone())
+          });
+        if let Some(session) = &self.session {
+          session.send(Command::Whisper {
+            from_id: self.user_id.clone(),
+            to,
+            to_id,
+            text,
+            id,
+          });
+        }
       }
-    });
+    } else if self.view == View::Search {
+      let channel = self.channels.get(self.selected).map(String::as_str);
+      egui::CentralPanel::default().show(ctx.ui(), |ui| {
+        actions.extend(
+          self
+            .search_view
+            .show(ui, &self.history, channel, &mut assets),
+        );
+      });
+    } else if self.view == View::Mentions {
+      egui::CentralPanel::default().show(ctx.ui(), |ui| {
+        actions.extend(chat_view::mentions(
+          ui,
+          self.history.mentions(),
+          &mut assets,
+        ));
+      });
+    } else if let Some(channel) = self.channels.get(self.selected) {
+      egui::TopBottomPanel::bottom("input").show(ctx.ui(), |ui| {
+        let input = self.inputs.entry(channel.clone()).or_default();
+        let room = self.room_states.get(channel);
+        let (history, emotes) = (&self.history, &self.emotes);
+        let twitch_emotes = emotes.global(Source::Twitch);
+        let is_emote = |word: &str| twitch_emotes.iter().any(|emote| emote.name == word);
+        let user = self.user_states.get(channel);
+        let aliases = &self.config.aliases;
+        let expand = |text: &str| {
+          let login = login_from_env().unwrap_or_default();
+          let context = alias::Context {
+            channel,
+            user: &login,
+          };
+          alias::expand(aliases, text, &context)
+        };
+        let message = input.show(ui, room, user, expand, is_emote, || {
+          let room_id = room.map(|room| room.room_id.as_str()).unwrap_or_default();
+          Sources {
+            chatters: history
+              .channel(channel)
+              .map(completion::recent_chatters)
+              .unwrap_or_default(),
+            emotes: emotes
+              .all(room_id)
+              .chain(emotes.global(Source::Twitch))
+              .map(|emote| emote.name.as_str())
+              .collect(),
+            commands: command::NAMES,
+          }
+        });
+        sent = message.map(|message| (channel.clone(), message));
+      });
+
+      egui::CentralPanel::default().show(ctx.ui(), |ui| {
+        let quick_actions = match is_moderator(&self.user_states, channel) {
+          true => &self.config.quick_actions[..],
+          false => &[],
+        };
+        let focus = self
+          .focus
+          .as_mut()
+          .filter(|(c, _)| c == channel)
+          .map(|(_, focus)| focus);
+        let history = self.history.channel(channel);
+        let collapser = self
+          .collapsers
+          .entry(channel.clone())
+          .or_insert_with(|| Collapser::new(&self.config.collapse));
+        if let Some(history) = history {
+          collapser.update(history);
+        }
+        let list = self.lists.entry(channel.clone()).or_default();
+        actions.extend(chat_view::show(
+          ui,
+          history,
+          collapser.rows(),
+          list,
+          quick_actions,
+          focus,
+          &mut assets,
+        ));
+      });
+    } else {
+      egui::CentralPanel::default().show(ctx.ui(), |ui| {
+        ui.label("Set NANOCHAT_CHANNELS to a comma-separated list of channels to join.");
+      });
+    }
+
+    if let Some((channel, enabled)) = toggled_log {
+      self.set_logged(&channel, enabled);
+    }
+    if let Some((channel, message)) = sent {
+      self.on_sent(channel, message);
+    }
+    for action in actions {
+      self.on_action(action);
+    }
+    if let Some(wait) = self.whispers.save() {
+      ctx.ui().request_repaint_after(wait);
+    }
   }
-}
 
-impl window::Handler for MainWindow {
-  type Event = Event;
+  fn set_logged(&mut self, channel: &str, enabled: bool) {
+    self.logs.set_enabled(channel, enabled);
+    let channels = &mut self.config.logs.channels;
+    channels.retain(|c| c != channel);
+    if enabled {
+      channels.push(channel.into());
+    }
+    if let Err(e) = self.config.save() {
+      tracing::warn!("{e}");
+    }
+  }
 
-  fn on_event(&mut self, _: window::Id, event: Self::Event) -> bool {
-    match event {
-      Event::Test => self.test = Instant::now(),
+  fn on_sent(&mut self, channel: String, sent: Sent) {
+    // only the first message is sent as a reply
+    let mut reply_parent = sent.reply_parent;
+    for line in sent.lines {
+      self.run(channel.clone(), &line, &mut reply_parent);
     }
+  }
 
-    true
+  /// Sends a chat message, or runs a slash command.
+  fn run(&mut self, channel: String, line: &str, reply_parent: &mut Option<String>) {
+    let command = match command::parse(line) {
+      Ok(Input::Message(text)) => {
+        if let Some(session) = &self.session {
+          session.send(Command::Privmsg {
+            channel,
+            text,
+            reply_parent: reply_parent.take(),
+          });
+        }
+        return;
+      }
+      Ok(Input::Command(command)) => command,
+      Err(e) => {
+        self.history.push(&channel, Message::system(e));
+        return;
+      }
+    };
+    self.run_command(channel, command);
   }
 
-  fn update_and_draw(&mut self, ctx: window::Context<'_, Self::Event>) {
-    self.draw(ctx)
+  fn run_command(&mut self, channel: String, command: command::Command) {
+    match command {
+      command::Command::Clear => self.history.remove(&channel),
+      command::Command::Join(channel) => self.join(channel),
+      command::Command::Part(other) => self.part(other.unwrap_or(channel)),
+      command => {
+        let broadcaster_id = self
+          .room_states
+          .get(&channel)
+          .map(|room| room.room_id.clone());
+        let (Some(broadcaster_id), Some(user_id)) = (broadcaster_id, self.user_id.clone()) else {
+          let error = "cannot run commands before joining the channel while logged in";
+          self.history.push(&channel, Message::system(error));
+          return;
+        };
+        if let Some(session) = &self.session {
+          session.send(Command::Run {
+            channel,
+            broadcaster_id,
+            user_id,
+            command,
+          });
+        }
+      }
+    }
   }
-}
 
-pub struct FloatingButton {
-  parent: window::Id,
-}
+  fn join(&mut self, channel: String) {
+    match self.channels.iter().position(|c| *c == channel) {
+      Some(i) => self.selected = i,
+      None => {
+        if let Some(session) = &self.session {
+          session.send(Command::Join(channel.clone()));
+        }
+        self.channels.push(channel);
+        self.selected = self.channels.len() - 1;
+      }
+    }
+    self.view = View::Channel;
+  }
 
-impl FloatingButton {
-  pub fn new(parent: window::Id) -> Self {
-    Self { parent }
+  fn part(&mut self, channel: String) {
+    let Some(i) = self.channels.iter().position(|c| *c == channel) else {
+      return;
+    };
+    if let Some(session) = &self.session {
+      session.send(Command::Part(channel.clone()));
+    }
+    self.channels.remove(i);
+    self.selected = self.selected.min(self.channels.len().saturating_sub(1));
+    self.history.remove(&channel);
+    self.inputs.remove(&channel);
+    self.collapsers.remove(&channel);
+    self.lists.remove(&channel);
+    self.user_states.remove(&channel);
+    // dropped with the room state, so that joining again loads them again
+    if let Some(room) = self.room_states.get(&channel) {
+      self.emotes.remove_channel(&room.room_id);
+      self.badges.remove_channel(&room.room_id);
+    }
+    self.room_states.remove(&channel);
   }
 
-  pub fn draw(&mut self, ctx: window::Context<'_, Event>) {
-    egui::CentralPanel::default().show(ctx.ui(), |ui| {
-      if ui.button("Test").clicked() {
-        ctx.notify(self.parent, Event::Test);
+  fn on_action(&mut self, action: Action) {
+    match action {
+      Action::Ignore { login, block } => {
+        if !self.config.ignores.is_ignored(&login) {
+          self.config.ignores.users.push(IgnoredUser {
+            login: login.clone(),
+            blocked: block,
+          });
+        }
+        self.filter = Filter::new(&self.config.ignores);
+        if let Err(e) = self.config.save() {
+          tracing::warn!("{e}");
+        }
+        if let (true, Some(session)) = (block, self.session.as_ref()) {
+          session.send(Command::Block { login });
+        }
       }
-    });
+      Action::Reply { channel, target } => {
+        if let Some(i) = self.channels.iter().position(|c| *c == channel) {
+          self.selected = i;
+          self.view = View::Channel;
+        }
+        self.inputs.entry(channel).or_default().reply_to(target);
+      }
+      Action::OpenThread { channel, root } => {
+        self.thread = Some((channel, root));
+      }
+      Action::Moderate { channel, command } => self.run_command(channel, command),
+      Action::Jump { channel, seq } => {
+        if let Some(i) = self.channels.iter().position(|c| *c == channel) {
+          self.selected = i;
+          self.view = View::Channel;
+          self.focus = Some((channel, Focus { seq, scroll: true }));
+        }
+      }
+      Action::OpenUserCard {
+        channel,
+        login,
+        user_id,
+      } => {
+        if let (false, Some(session)) = (self.users.contains_key(&login), &self.session) {
+          session.send(Command::LoadUser {
+            login: login.clone(),
+          });
+        }
+        self.user_card = Some(UserCard {
+          channel,
+          login,
+          user_id,
+        });
+      }
+    }
   }
 }
 
-impl window::Handler for FloatingButton {
+impl window::Handler for MainWindow {
   type Event = Event;
 
-  fn on_event(&mut self, _: window::Id, _: Self::Event) -> bool {
-    false
+  fn on_event(&mut self, _: window::Id, event: Self::Event) -> bool {
+    match event {}
   }
 
   fn update_and_draw(&mut self, ctx: window::Context<'_, Self::Event>) {
     self.draw(ctx)
   }
 }
+
+fn channels_from_env() -> Vec<String> {
+  std::env::var("NANOCHAT_CHANNELS")
+    .unwrap_or_default()
+    .split(',')
+    .map(|c| c.trim().trim_start_matches('#').to_lowercase())
+    .filter(|c| !c.is_empty())
+    .collect()
+}
+
+/// Whether the last `USERSTATE` in `channel` said we are a moderator or the broadcaster.
+fn is_moderator(user_states: &HashMap<String, UserState>, channel: &str) -> bool {
+  user_states.get(channel).is_some_and(|state| state.is_mod)
+}
+
+fn login_from_env() -> Option<String> {
+  std::env::var("NANOCHAT_LOGIN").ok()
+}
+
+/// `NANOCHAT_LOGIN` and `NANOCHAT_TOKEN`, or `None` to connect anonymously.
+fn config_from_env() -> Option<ChatConfig> {
+  let login = login_from_env()?;
+  let token = std::env::var("NANOCHAT_TOKEN").ok()?;
+  let token = token.trim_start_matches("oauth:");
+  Some(ChatConfig::new(login, format!("oauth:{token}")))
+}
+
+/// Helix requires the client id the token was issued for, from `NANOCHAT_CLIENT_ID`.
+fn helix_from_env(config: &ChatConfig) -> Option<helix::Client> {
+  let client_id = std::env::var("NANOCHAT_CLIENT_ID").ok()?;
+  Some(helix::Config::new(client_id, config).build())
+}
@@ -0,0 +1,391 @@
+use std::collections::VecDeque;
+
+use egui::{pos2, vec2, FontId, Rect, TextStyle};
+
+use crate::chat::collapse::Row;
+
+/// What the measured row heights depend on, besides the rows themselves.
+#[derive(Clone, PartialEq)]
+struct Layout {
+  width: f32,
+  font: Option<FontId>,
+  pixels_per_point: f32,
+}
+
+/// A chat list which only lays out and paints the rows in view, for channels with long histories.
+///
+/// Rows are measured when they are drawn, and rows which were never in view are assumed to be as
+/// high as the average measured row. The list sticks to the bottom until the user scrolls up, then
+/// it stays on the same message and offers to jump back down.
+#[derive(Default)]
+pub struct ChatList {
+  /// Heights of the rows, measured at `layout`.
+  heights: Heights,
+  layout: Option<Layout>,
+  /// The first row in view and how far it was scrolled out of view, while scrolled up.
+  anchor: Option<(u64, f32)>,
+  /// The last row when the user scrolled up.
+  seen: Option<u64>,
+  /// Scrolls to the row with this message on the next frame.
+  scroll_to: Option<u64>,
+}
+
+impl ChatList {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Centers the row containing the message `seq` on the next frame.
+  pub fn scroll_to(&mut self, seq: u64) {
+    self.scroll_to = Some(seq);
+  }
+
+  /// Draws the rows in view with `draw_row`, and returns the first value it returned.
+  pub fn show<R>(
+    &mut self,
+    ui: &mut egui::Ui,
+    rows: &VecDeque<Row>,
+    mut draw_row: impl FnMut(&mut egui::Ui, &Row) -> Option<R>,
+  ) -> Option<R> {
+    let layout = Layout {
+      width: ui.available_width(),
+      font: ui.style().text_styles.get(&TextStyle::Body).cloned(),
+      pixels_per_point: ui.ctx().pixels_per_point(),
+    };
+    if self.layout.as_ref() != Some(&layout) {
+      self.heights.clear();
+      self.layout = Some(layout);
+    }
+    self.heights.sync(rows);
+
+    let spacing = ui.spacing().item_spacing.y;
+    let estimate = self
+      .heights
+      .estimate()
+      .unwrap_or(ui.spacing().interact_size.y);
+    let top = |heights: &Heights, i: usize| heights.top(i, estimate, spacing);
+    let height = top(&self.heights, rows.len());
+    let index_of = |seq: u64| rows.partition_point(|row| row.seq <= seq).checked_sub(1);
+
+    // the scroll area lays out the viewport before clamping the offset, so clamp it here
+    let bottom = (height - ui.available_height()).max(0.0);
+    let offset = match (self.scroll_to.take(), self.anchor) {
+      (Some(seq), _) => index_of(seq).map(|i| top(&self.heights, i) - ui.available_height() / 2.0),
+      (None, Some((seq, delta))) => Some(match index_of(seq) {
+        Some(i) => top(&self.heights, i) + delta,
+        // the row was evicted, stay at the oldest row rather than jumping to the bottom
+        None => top(&self.heights, 0),
+      }),
+      (None, None) => Some(bottom),
+    };
+    let mut area = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+    if let Some(offset) = offset {
+      area = area.vertical_scroll_offset(offset.clamp(0.0, bottom));
+    }
+
+    let mut result = None;
+    let mut remeasured = false;
+    let output = area.show_viewport(ui, |ui, viewport| {
+      ui.set_min_height(height);
+      let origin = ui.max_rect().top();
+      let first = self.heights.index_at(viewport.min.y, estimate, spacing);
+      let mut row_top = top(&self.heights, first);
+      for (i, row) in rows.iter().enumerate().skip(first) {
+        if row_top > viewport.max.y {
+          break;
+        }
+        let rect = Rect::from_min_size(
+          pos2(ui.max_rect().left(), origin + row_top),
+          vec2(ui.available_width(), f32::INFINITY),
+        );
+        let mut row_ui = ui.child_ui_with_id_source(rect, *ui.layout(), ("row", row.seq));
+        let inner = draw_row(&mut row_ui, row);
+        if result.is_none() {
+          result = inner;
+        }
+
+        // lay out the rest of this frame with the heights it started with
+        row_top += self.heights.get(i).unwrap_or(estimate) + spacing;
+        remeasured |= self.heights.set(i, row_ui.min_rect().height());
+      }
+    });
+    if remeasured {
+      // the rows below moved, lay them out again with the new heights
+      ui.ctx().request_repaint();
+    }
+
+    let offset = output.state.offset.y;
+    let at_bottom = offset >= output.content_size.y - output.inner_rect.height() - 1.0;
+    self.anchor = match at_bottom {
+      true => None,
+      false => {
+        let i = self.heights.index_at(offset, estimate, spacing);
+        let delta = offset - top(&self.heights, i);
+        rows.get(i).map(|row| (row.seq, delta))
+      }
+    };
+    self.seen = match self.anchor {
+      Some(_) => self.seen.or(rows.back().map(|row| row.seq)),
+      None => None,
+    };
+
+    let unseen = self
+      .seen
+      .map(|seen| rows.len() - rows.partition_point(|row| row.seq <= seen))
+      .unwrap_or_default();
+    if unseen > 0 {
+      let text = match unseen {
+        1 => "1 new message".to_string(),
+        n => format!("{n} new messages"),
+      };
+      let size = vec2(160.0, ui.spacing().interact_size.y);
+      let rect =
+        Rect::from_center_size(output.inner_rect.center_bottom() - vec2(0.0, size.y), size);
+      if ui.put(rect, egui::Button::new(text)).clicked() {
+        self.anchor = None;
+        self.seen = None;
+        ui.ctx().request_repaint();
+      }
+    }
+    result
+  }
+}
+
+/// The measured heights of the rows passed to `ChatList::show`, with prefix sums so that finding
+/// the top of a row doesn't add up every row above it. Kept in step with the rows as they are
+/// added and evicted.
+#[derive(Default)]
+struct Heights {
+  /// The first sequence number of each row.
+  seqs: VecDeque<u64>,
+  /// `None` for rows which were never in view.
+  heights: VecDeque<Option<f32>>,
+  /// The number of rows evicted since the trees were built, row `i` is at `base + i` in them.
+  base: usize,
+  /// Sums of the measured heights.
+  sums: Fenwick,
+  /// Sums of the number of measured rows.
+  counts: Fenwick,
+}
+
+impl Heights {
+  fn clear(&mut self) {
+    *self = Self::default();
+  }
+
+  fn len(&self) -> usize {
+    self.seqs.len()
+  }
+
+  /// Adds the rows which were added to `rows` since the last call, and removes evicted ones. Starts
+  /// over if `rows` was rebuilt.
+  fn sync(&mut self, rows: &VecDeque<Row>) {
+    let Some(front) = rows.front().map(|row| row.seq) else {
+      self.clear();
+      return;
+    };
+    while self.seqs.front().is_some_and(|seq| *seq < front) {
+      self.pop_front();
+    }
+    let kept = self.len();
+    let same = |i: usize| rows.get(i).map(|row| row.seq) == self.seqs.get(i).copied();
+    if kept > 0 && !(same(0) && same(kept - 1)) {
+      self.clear();
+    }
+    for row in rows.iter().skip(self.len()) {
+      self.seqs.push_back(row.seq);
+      self.heights.push_back(None);
+      self.sums.push(0.0);
+      self.counts.push(0.0);
+    }
+  }
+
+  fn pop_front(&mut self) {
+    self.seqs.pop_front();
+    self.heights.pop_front();
+    self.base += 1;
+    // the trees only grow, so rebuild them once most of them is evicted rows
+    if self.base > self.len().max(64) {
+      self.base = 0;
+      self.sums = self
+        .heights
+        .iter()
+        .map(|h| f64::from(h.unwrap_or(0.0)))
+        .collect();
+      self.counts = self
+        .heights
+        .iter()
+        .map(|h| h.map_or(0.0, |_| 1.0))
+        .collect();
+    }
+  }
+
+  /// The measured height of row `i`.
+  fn get(&self, i: usize) -> Option<f32> {
+    self.heights.get(i).copied().flatten()
+  }
+
+  /// Returns whether the height of row `i` changed.
+  fn set(&mut self, i: usize, height: f32) -> bool {
+    let Some(old) = self.heights.get_mut(i) else {
+      return false;
+    };
+    let slot = self.base + i;
+    let changed = !old.is_some_and(|old| (old - height).abs() < 0.5);
+    match old.replace(height) {
+      Some(old) => self.sums.add(slot, f64::from(height - old)),
+      None => {
+        self.sums.add(slot, f64::from(height));
+        self.counts.add(slot, 1.0);
+      }
+    }
+    changed
+  }
+
+  /// The average measured height, the height assumed for rows which were never in view.
+  fn estimate(&self) -> Option<f32> {
+    let count = self.counts.range(self.base, self.base + self.len());
+    let sum = self.sums.range(self.base, self.base + self.len());
+    (count > 0.0).then(|| (sum / count) as f32)
+  }
+
+  /// The top of row `i`, or the bottom of the last row if `i` is the number of rows.
+  fn top(&self, i: usize, estimate: f32, spacing: f32) -> f32 {
+    let sum = self.sums.range(self.base, self.base + i);
+    let measured = self.counts.range(self.base, self.base + i);
+    let unmeasured = i as f64 - measured;
+    (sum + unmeasured * f64::from(estimate) + i as f64 * f64::from(spacing)) as f32
+  }
+
+  /// The last row which starts at or above `y`, or the first row.
+  fn index_at(&self, y: f32, estimate: f32, spacing: f32) -> usize {
+    let (mut lo, mut hi) = (0, self.len());
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      match self.top(mid, estimate, spacing) <= y {
+        true => lo = mid + 1,
+        false => hi = mid,
+      }
+    }
+    lo.saturating_sub(1)
+  }
+}
+
+/// A Fenwick tree, for prefix sums which can be updated in `O(log n)`.
+#[derive(Default)]
+struct Fenwick(Vec<f64>);
+
+impl Fenwick {
+  fn push(&mut self, value: f64) {
+    // the new node covers the values after `len + 1 - lowest bit`
+    let len = self.0.len() + 1;
+    let start = len - (len & len.wrapping_neg());
+    let covered = self.prefix(len - 1) - self.prefix(start);
+    self.0.push(value + covered);
+  }
+
+  fn add(&mut self, i: usize, delta: f64) {
+    let mut i = i + 1;
+    while i <= self.0.len() {
+      self.0[i - 1] += delta;
+      i += i & i.wrapping_neg();
+    }
+  }
+
+  /// The sum of the first `n` values.
+  fn prefix(&self, n: usize) -> f64 {
+    let (mut i, mut sum) = (n.min(self.0.len()), 0.0);
+    while i > 0 {
+      sum += self.0[i - 1];
+      i -= i & i.wrapping_neg();
+    }
+    sum
+  }
+
+  /// The sum of the values from `start` up to `end`.
+  fn range(&self, start: usize, end: usize) -> f64 {
+    self.prefix(end) - self.prefix(start)
+  }
+}
+
+impl FromIterator<f64> for Fenwick {
+  fn from_iter<T: IntoIterator<Item = f64>>(iter: T) -> Self {
+    let mut tree = Self::default();
+    for value in iter {
+      tree.push(value);
+    }
+    tree
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rows(seqs: impl IntoIterator<Item = u64>) -> VecDeque<Row> {
+    seqs
+      .into_iter()
+      .map(|seq| Row {
+        seq,
+        repeats: Vec::new(),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn fenwick() {
+    let values = (0..100).map(|i| (i * 7 % 13) as f64).collect::<Vec<_>>();
+    let mut tree = values.iter().copied().collect::<Fenwick>();
+    for n in 0..=values.len() {
+      assert_eq!(tree.prefix(n), values[..n].iter().sum::<f64>());
+    }
+    tree.add(40, 5.0);
+    assert_eq!(tree.range(40, 41), values[40] + 5.0);
+    assert_eq!(tree.prefix(100), values.iter().sum::<f64>() + 5.0);
+  }
+
+  #[test]
+  fn tops() {
+    let mut heights = Heights::default();
+    heights.sync(&rows(0..4));
+    assert_eq!(heights.estimate(), None);
+    assert_eq!(heights.top(4, 10.0, 1.0), 44.0);
+
+    assert!(heights.set(1, 20.0));
+    assert!(!heights.set(1, 20.2));
+    heights.set(2, 30.0);
+    assert_eq!(heights.estimate(), Some(25.1));
+    // unmeasured rows are as high as the estimate
+    assert_eq!(heights.top(1, 10.0, 1.0), 11.0);
+    assert_eq!(heights.top(3, 10.0, 1.0), 11.0 + 21.2 + 31.0);
+    assert_eq!(heights.index_at(0.0, 10.0, 1.0), 0);
+    assert_eq!(heights.index_at(11.0, 10.0, 1.0), 1);
+    assert_eq!(heights.index_at(40.0, 10.0, 1.0), 2);
+    assert_eq!(heights.index_at(1000.0, 10.0, 1.0), 3);
+  }
+
+  #[test]
+  fn follows_rows() {
+    let mut heights = Heights::default();
+    heights.sync(&rows(0..10));
+    for i in 0..10 {
+      heights.set(i, i as f32);
+    }
+
+    // evict enough rows for the trees to be rebuilt
+    for end in 11..200 {
+      heights.sync(&rows(end - 10..end));
+      heights.set(9, (end - 1) as f32);
+    }
+    assert_eq!(heights.seqs, (189..199).collect::<Vec<_>>());
+    assert_eq!(heights.top(10, 0.0, 0.0), (189..199).sum::<u64>() as f32);
+    assert_eq!(heights.get(0), Some(189.0));
+
+    // rows were rebuilt, e.g. after older messages were added in front
+    heights.sync(&rows(180..199));
+    assert_eq!(heights.len(), 19);
+    assert_eq!(heights.get(18), None);
+    heights.sync(&VecDeque::new());
+    assert_eq!(heights.len(), 0);
+  }
+}
@@ -0,0 +1,474 @@
+use std::collections::VecDeque;
+
+use egui::{Color32, RichText, Vec2};
+
+use crate::badges::BadgeRegistry;
+use crate::chat::collapse::Row;
+use crate::chat::color::UsernameColors;
+use crate::chat::command::{Command, QuickAction};
+use crate::chat::event::PrivMsg;
+use crate::chat::fragment::{self, Fragment};
+use crate::chat::highlight::Highlight;
+use crate::chat::history::{ChannelHistory, Entry, Mention, Message, Moderation};
+use crate::chat::Color;
+use crate::emotes::EmoteStore;
+use crate::images::Images;
+
+use super::chat_list::ChatList;
+use super::input::ReplyTarget;
+
+/// Everything needed to draw emotes and badges.
+pub struct Assets<'a> {
+  pub emotes: &'a EmoteStore,
+  pub badges: &'a BadgeRegistry,
+  pub images: &'a mut Images,
+  pub colors: &'a mut UsernameColors,
+}
+
+/// Something the user asked for through a line's buttons or context menu.
+pub enum Action {
+  Ignore {
+    login: String,
+    block: bool,
+  },
+  Reply {
+    channel: String,
+    target: ReplyTarget,
+  },
+  OpenThread {
+    channel: String,
+    root: String,
+  },
+  /// Runs a moderation command, e.g. from a quick action.
+  Moderate {
+    channel: String,
+    command: Command,
+  },
+  OpenUserCard {
+    channel: String,
+    login: String,
+    user_id: String,
+  },
+  /// Shows the message `seq` in its channel.
+  Jump {
+    channel: String,
+    seq: u64,
+  },
+}
+
+/// A message to outline in the channel view, e.g. after jumping to a search result.
+pub struct Focus {
+  pub seq: u64,
+  /// Scrolls to the message on the next frame.
+  pub scroll: bool,
+}
+
+pub fn reply_target(message: &PrivMsg) -> ReplyTarget {
+  ReplyTarget {
+    msg_id: message.id.clone(),
+    login: message.author.login.clone(),
+    text: message.text.clone(),
+  }
+}
+
+/// `rows` are the messages of `history` as grouped by a `Collapser`, drawn through `list` so
+/// that only the ones in view are laid out. `quick_actions` are shown next to each message, which
+/// should be empty unless we moderate the channel.
+pub fn show(
+  ui: &mut egui::Ui,
+  history: Option<&ChannelHistory>,
+  rows: &VecDeque<Row>,
+  list: &mut ChatList,
+  quick_actions: &[QuickAction],
+  focus: Option<&mut Focus>,
+  assets: &mut Assets<'_>,
+) -> Option<Action> {
+  let history = history?;
+  let focus = focus.map(|focus| {
+    if focus.scroll {
+      list.scroll_to(focus.seq);
+      focus.scroll = false;
+    }
+    focus.seq
+  });
+  list.show(ui, rows, |ui, row| {
+    if !focus.is_some_and(|seq| row.contains(seq)) {
+      return row_line(ui, history, row, quick_actions, assets);
+    }
+    egui::Frame::none()
+      .stroke(ui.visuals().selection.stroke)
+      .show(ui, |ui| row_line(ui, history, row, quick_actions, assets))
+      .inner
+  })
+}
+
+/// A row with a single message, or a message with a `×N` counter which expands into the list of
+/// users who repeated it.
+fn row_line(
+  ui: &mut egui::Ui,
+  history: &ChannelHistory,
+  row: &Row,
+  quick_actions: &[QuickAction],
+  assets: &mut Assets<'_>,
+) -> Option<Action> {
+  let entry = history.get(row.seq)?;
+  if row.repeats.is_empty() {
+    return entry_line(ui, entry, quick_actions, assets);
+  }
+
+  let id = ui.id().with(("repeats", row.seq));
+  let mut expanded = ui.data_mut(|d| *d.get_temp_mut_or(id, false));
+  let action = ui
+    .horizontal(|ui| {
+      let counter = ui
+        .small_button(format!("×{}", row.count()))
+        .on_hover_text("Show who sent this");
+      if counter.clicked() {
+        expanded = !expanded;
+        ui.data_mut(|d| d.insert_temp(id, expanded));
+      }
+      entry_line(ui, entry, quick_actions, assets)
+    })
+    .inner;
+  if expanded {
+    let names = std::iter::once(row.seq)
+      .chain(row.repeats.iter().copied())
+      .filter_map(|seq| history.get(seq))
+      .filter_map(|entry| match &entry.message {
+        Message::Chat(m) => Some(m.author.name.as_str()),
+        _ => None,
+      })
+      .collect::<Vec<_>>();
+    ui.indent(id, |ui| {
+      ui.label(RichText::new(names.join(", ")).small().weak());
+    });
+  }
+  action
+}
+
+/// `line` for a message in the channel's history, dimmed if it is historical.
+fn entry_line(
+  ui: &mut egui::Ui,
+  entry: &Entry,
+  quick_actions: &[QuickAction],
+  assets: &mut Assets<'_>,
+) -> Option<Action> {
+  ui.scope(|ui| {
+    if entry.historical {
+      let weak = ui.visuals().weak_text_color();
+      ui.visuals_mut().override_text_color = Some(weak);
+    }
+    line(
+      ui,
+      &entry.message,
+      entry.moderation,
+      entry.highlight,
+      None,
+      quick_actions,
+      assets,
+    )
+  })
+  .inner
+}
+
+/// Every message of the thread starting at `root` which is still in the history.
+pub fn thread(
+  ui: &mut egui::Ui,
+  history: &ChannelHistory,
+  root: &str,
+  assets: &mut Assets<'_>,
+) -> Option<Action> {
+  let mut action = None;
+  egui::ScrollArea::vertical()
+    .id_source("thread")
+    .auto_shrink([false; 2])
+    .stick_to_bottom(true)
+    .show(ui, |ui| {
+      for entry in history.thread(root) {
+        action = action.take().or(line(
+          ui,
+          &entry.message,
+          entry.moderation,
+          entry.highlight,
+          None,
+          &[],
+          assets,
+        ));
+      }
+    });
+  action
+}
+
+/// The Mentions feed, with the channel of each message.
+pub fn mentions<'m>(
+  ui: &mut egui::Ui,
+  mentions: impl Iterator<Item = &'m Mention>,
+  assets: &mut Assets<'_>,
+) -> Option<Action> {
+  let mut action = None;
+  egui::ScrollArea::vertical()
+    .auto_shrink([false; 2])
+    .stick_to_bottom(true)
+    .show(ui, |ui| {
+      for mention in mentions {
+        action = action.take().or(line(
+          ui,
+          &mention.message,
+          None,
+          Some(mention.highlight),
+          Some(&mention.channel),
+          &[],
+          assets,
+        ));
+      }
+    });
+  action
+}
+
+/// A single message. `channel` is shown before the message if set.
+pub fn line(
+  ui: &mut egui::Ui,
+  message: &Message,
+  moderation: Option<Moderation>,
+  highlight: Option<Highlight>,
+  channel: Option<&str>,
+  quick_actions: &[QuickAction],
+  assets: &mut Assets<'_>,
+) -> Option<Action> {
+  let fill = match highlight {
+    Some(Highlight { color, .. }) => Color32::from_rgba_unmultiplied(color.r, color.g, color.b, 48),
+    None => Color32::TRANSPARENT,
+  };
+  egui::Frame::none()
+    .fill(fill)
+    .show(ui, |ui| {
+      ui.set_min_width(ui.available_width());
+      line_contents(ui, message, moderation, channel, quick_actions, assets)
+    })
+    .inner
+}
+
+fn line_contents(
+  ui: &mut egui::Ui,
+  message: &Message,
+  moderation: Option<Moderation>,
+  channel: Option<&str>,
+  quick_actions: &[QuickAction],
+  assets: &mut Assets<'_>,
+) -> Option<Action> {
+  let Assets {
+    emotes,
+    badges,
+    images,
+    colors,
+  } = assets;
+  let moderated = moderation.is_some();
+  let text = |s: &str| {
+    let text = RichText::new(s);
+    if moderated {
+      text.weak().strikethrough()
+    } else {
+      text
+    }
+  };
+
+  let mut action = None;
+  if let Message::Chat(
+    m @ PrivMsg {
+      reply: Some(reply), ..
+    },
+  ) = message
+  {
+    let mut body = reply.msg_body.chars().take(80).collect::<String>();
+    if body.len() < reply.msg_body.len() {
+      body.push('…');
+    }
+    let header = format!("↪ replying to @{}: {body}", reply.user_login);
+    let header = ui.add(
+      egui::Label::new(RichText::new(header).small().weak())
+        .wrap(false)
+        .sense(egui::Sense::click()),
+    );
+    if header.on_hover_text("Open thread").clicked() {
+      action = Some(Action::OpenThread {
+        channel: m.channel.clone(),
+        root: reply.root().into(),
+      });
+    }
+  }
+
+  ui.horizontal_wrapped(|ui| {
+    ui.spacing_mut().item_spacing.x = 4.0;
+
+    let timestamp = message.timestamp().with_timezone(&chrono::Local);
+    ui.label(RichText::new(timestamp.format("%H:%M").to_string()).weak());
+    if let Some(channel) = channel {
+      ui.label(RichText::new(format!("#{channel}")).weak());
+    }
+
+    match message {
+      Message::Chat(m) => {
+        for quick_action in quick_actions {
+          let button = ui
+            .small_button(quick_action.label())
+            .on_hover_text(quick_action.description());
+          if button.clicked() {
+            action = Some(Action::Moderate {
+              channel: m.channel.clone(),
+              command: quick_action.command(&m.author.login, &m.id),
+            });
+          }
+        }
+        for badge in badges.resolve(&m.room_id, &m.author.badges, &m.author.badge_info) {
+          let response = match image(ui, images, &badge.image.url_1x, BADGE_HEIGHT, moderated) {
+            Some(response) => response,
+            None => {
+              let short = badge.image.title.chars().next().unwrap_or('?');
+              ui.label(RichText::new(short).small().strong())
+            }
+          };
+          response.on_hover_text(badge.tooltip());
+        }
+        let mut name = RichText::new(format!("{}:", m.author.name)).strong();
+        if !moderated {
+          let [r, g, b, _] = ui.visuals().panel_fill.to_array();
+          let color = colors.get(&m.author, Color { r, g, b });
+          name = name.color(Color32::from_rgb(color.r, color.g, color.b));
+        }
+        let name = ui.add(egui::Label::new(name).sense(egui::Sense::click()));
+        if name.clicked() {
+          action = Some(Action::OpenUserCard {
+            channel: m.channel.clone(),
+            login: m.author.login.clone(),
+            user_id: m.author.id.clone(),
+          });
+        }
+        name.context_menu(|ui| {
+          if ui.button("Reply").clicked() {
+            action = Some(Action::Reply {
+              channel: m.channel.clone(),
+              target: reply_target(m),
+            });
+            ui.close_menu();
+          }
+          if let Some(reply) = &m.reply {
+            if ui.button("View thread").clicked() {
+              action = Some(Action::OpenThread {
+                channel: m.channel.clone(),
+                root: reply.root().into(),
+              });
+              ui.close_menu();
+            }
+          }
+          ui.separator();
+          let ignore = |block| Action::Ignore {
+            login: m.author.login.clone(),
+            block,
+          };
+          if ui.button("Ignore").clicked() {
+            action = Some(ignore(false));
+            ui.close_menu();
+          }
+          if ui.button("Ignore and block").clicked() {
+            action = Some(ignore(true));
+            ui.close_menu();
+          }
+        });
+        for fragment in fragment::tokenize(m, emotes) {
+          match fragment {
+            Fragment::Text(t) if t.trim().is_empty() => {}
+            Fragment::Text(t) => {
+              ui.label(text(t.trim()));
+            }
+            Fragment::Emote(emote) => {
+              let response = match image(ui, images, &emote.url, EMOTE_HEIGHT, moderated) {
+                Some(response) => response,
+                None => ui.label(text(&emote.name).italics()),
+              };
+              response.on_hover_text(format!("{} ({})", emote.name, emote.source.name()));
+            }
+            Fragment::Mention(login) => {
+              ui.label(text(&format!("@{login}")).strong());
+            }
+            Fragment::Url(url) if !moderated => {
+              let target = match url.contains("://") {
+                true => url.clone(),
+                false => format!("https://{url}"),
+              };
+              ui.hyperlink_to(url, target);
+            }
+            Fragment::Url(url) => {
+              ui.label(text(&url));
+            }
+            Fragment::Cheer { prefix, amount } => {
+              ui.label(text(&format!("{prefix}{amount}")).strong());
+            }
+          }
+        }
+        if !m.removed.is_empty() {
+          let removed = m
+            .removed
+            .iter()
+            .map(|c| format!("U+{:04X}", u32::from(*c)))
+            .collect::<Vec<_>>()
+            .join(" ");
+          ui.label(RichText::new("⚠").small().weak())
+            .on_hover_text(format!(
+              "Invisible or layout-breaking characters were removed from this message:\n{removed}"
+            ));
+        }
+      }
+      Message::UserNotice(m) => {
+        if let Some(system_msg) = &m.system_msg {
+          ui.label(RichText::new(system_msg).italics());
+        }
+        if let Some(t) = &m.text {
+          ui.label(text(t));
+        }
+      }
+      Message::System { text, .. } => {
+        ui.label(RichText::new(text).italics().weak());
+      }
+    }
+
+    if let Some(moderation) = moderation {
+      ui.label(RichText::new(moderation_label(moderation)).small().weak());
+    }
+  });
+  action
+}
+
+const EMOTE_HEIGHT: f32 = 28.0;
+const BADGE_HEIGHT: f32 = 18.0;
+
+/// Draws the image at `url` scaled down to `max_height`, or returns `None` if it isn't loaded yet.
+fn image(
+  ui: &mut egui::Ui,
+  images: &mut Images,
+  url: &str,
+  max_height: f32,
+  dim: bool,
+) -> Option<egui::Response> {
+  let image = images.get(url)?;
+  let (texture, next_frame) = image.frame(ui.input(|i| i.time));
+  if let Some(next_frame) = next_frame {
+    ui.ctx().request_repaint_after(next_frame);
+  }
+
+  let size = image.size();
+  let scale = (max_height / size.y).min(1.0);
+  let mut widget = egui::Image::new(texture.id(), Vec2::new(size.x * scale, size.y * scale));
+  if dim {
+    widget = widget.tint(Color32::from_white_alpha(96));
+  }
+  Some(ui.add(widget))
+}
+
+fn moderation_label(moderation: Moderation) -> String {
+  match moderation {
+    Moderation::Deleted => "(deleted)".into(),
+    Moderation::TimedOut(duration) => format!("(timed out for {}s)", duration.as_secs()),
+    Moderation::Banned => "(banned)".into(),
+    Moderation::Cleared => "(cleared)".into(),
+  }
+}
@@ -0,0 +1,336 @@
+use std::time::Instant;
+
+use egui::text::{CCursor, CCursorRange};
+use egui::{Key, Modifiers};
+
+use crate::chat::alias;
+use crate::chat::completion::{Completion, Sources};
+use crate::chat::event::UserState;
+use crate::chat::room_state::RoomState;
+
+#[derive(Clone, Debug)]
+pub struct ReplyTarget {
+  pub msg_id: String,
+  pub login: String,
+  pub text: String,
+}
+
+pub struct Sent {
+  /// The input after expanding aliases, each line is a chat message or a command.
+  pub lines: Vec<String>,
+  /// Sent as the `reply-parent-msg-id` tag.
+  pub reply_parent: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ChatInput {
+  text: String,
+  reply: Option<ReplyTarget>,
+  completion: Option<Completion>,
+  last_sent: Option<Instant>,
+  error: Option<String>,
+}
+
+impl ChatInput {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The next message will be sent as a reply to `target`.
+  pub fn reply_to(&mut self, target: ReplyTarget) {
+    self.reply = Some(target);
+  }
+
+  /// Returns the message once the user presses enter, unless the room would reject it.
+  /// `expand` expands aliases in the input, and the room's restrictions are checked against the
+  /// result. `is_emote` tells whether a word is a Twitch emote we can use, for emote-only mode.
+  ///
+  /// Tab and shift-tab cycle through completions for the word before the cursor, with
+  /// candidates from `sources`, which is only called when a completion starts.
+  pub fn show<'a>(
+    &mut self,
+    ui: &mut egui::Ui,
+    room: Option<&RoomState>,
+    user: Option<&UserState>,
+    expand: impl FnOnce(&str) -> Result<Vec<String>, alias::Error>,
+    is_emote: impl Fn(&str) -> bool,
+    sources: impl FnOnce() -> Sources<'a>,
+  ) -> Option<Sent> {
+    let mut sent = None;
+
+    if let Some(reply) = &self.reply {
+      let mut cancel = false;
+      ui.horizontal(|ui| {
+        cancel = ui.small_button("✖").on_hover_text("Cancel reply").clicked();
+        ui.label(
+          egui::RichText::new(format!("Replying to @{}: {}", reply.login, reply.text))
+            .small()
+            .weak(),
+        );
+      });
+      if cancel {
+        self.reply = None;
+      }
+    }
+
+    ui.horizontal(|ui| {
+      if let Some(room) = room {
+        for label in room.labels() {
+          ui.label(egui::RichText::new(label).small().weak());
+        }
+      }
+
+      let id = ui.make_persistent_id("chat_input");
+      let stepped = self.complete(ui, id, sources);
+
+      let response = ui.add(
+        egui::TextEdit::singleline(&mut self.text)
+          .id(id)
+          .hint_text("Send a message")
+          .desired_width(f32::INFINITY)
+          .lock_focus(true),
+      );
+      if response.changed() || ui.input(|i| i.key_pressed(Key::Escape)) {
+        self.completion = None;
+      }
+      if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+        sent = self.submit(room, user, expand, is_emote);
+        response.request_focus();
+      }
+      if let Some(i) = self.completion_popup(ui, &response, stepped) {
+        if let Some(completion) = &mut self.completion {
+          let cursor = completion.select(i, &mut self.text);
+          set_cursor(ui.ctx(), id, &self.text, cursor);
+          response.request_focus();
+        }
+      }
+    });
+
+    if let Some(error) = &self.error {
+      ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+
+    sent
+  }
+
+  /// Handles tab and shift-tab while the input with `id` is focused. Returns `true` if a
+  /// different candidate was selected.
+  fn complete<'a>(
+    &mut self,
+    ui: &egui::Ui,
+    id: egui::Id,
+    sources: impl FnOnce() -> Sources<'a>,
+  ) -> bool {
+    if !ui.memory(|m| m.has_focus(id)) {
+      return false;
+    }
+    let prev = ui.input_mut(|i| i.consume_key(Modifiers::SHIFT, Key::Tab));
+    let next = ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Tab));
+    if !prev && !next {
+      return false;
+    }
+
+    let cursor = get_cursor(ui.ctx(), id, &self.text);
+    // the cursor moved away from the completed word since the last tab
+    if self.completion.as_ref().is_some_and(|c| c.end() != cursor) {
+      self.completion = None;
+    }
+    if self.completion.is_none() {
+      self.completion = Completion::new(&self.text, cursor, &sources());
+    }
+    let Some(completion) = &mut self.completion else {
+      return false;
+    };
+    let cursor = match prev {
+      true => completion.prev(&mut self.text),
+      false => completion.next(&mut self.text),
+    };
+    set_cursor(ui.ctx(), id, &self.text, cursor);
+    true
+  }
+
+  /// Lists the candidates above the input. Returns the index of the clicked candidate.
+  fn completion_popup(
+    &self,
+    ui: &egui::Ui,
+    input: &egui::Response,
+    scroll_to_selected: bool,
+  ) -> Option<usize> {
+    let completion = self.completion.as_ref()?;
+    if completion.candidates().len() < 2 {
+      return None;
+    }
+
+    let row_height = ui.spacing().interact_size.y;
+    let mut clicked = None;
+    egui::Area::new(input.id.with("completions"))
+      .order(egui::Order::Foreground)
+      .fixed_pos(input.rect.left_top())
+      .pivot(egui::Align2::LEFT_BOTTOM)
+      .show(ui.ctx(), |ui| {
+        egui::Frame::popup(ui.style()).show(ui, |ui| {
+          ui.set_width(input.rect.width().min(300.0));
+          let mut scroll = egui::ScrollArea::vertical().max_height(200.0);
+          if let (true, Some(selected)) = (scroll_to_selected, completion.selected()) {
+            let offset = (selected as f32 - 3.0).max(0.0) * row_height;
+            scroll = scroll.vertical_scroll_offset(offset);
+          }
+          let candidates = completion.candidates();
+          scroll.show_rows(ui, row_height, candidates.len(), |ui, rows| {
+            for i in rows {
+              let candidate = &candidates[i];
+              let selected = completion.selected() == Some(i);
+              let label = ui.add_sized(
+                [ui.available_width(), row_height],
+                egui::SelectableLabel::new(selected, &candidate.text),
+              );
+              if label.clicked() {
+                clicked = Some(i);
+              }
+            }
+          });
+        });
+      });
+    clicked
+  }
+
+  fn submit(
+    &mut self,
+    room: Option<&RoomState>,
+    user: Option<&UserState>,
+    expand: impl FnOnce(&str) -> Result<Vec<String>, alias::Error>,
+    is_emote: impl Fn(&str) -> bool,
+  ) -> Option<Sent> {
+    let text = self.text.trim();
+    if text.is_empty() {
+      return None;
+    }
+
+    let lines = match expand(text) {
+      Ok(lines) => lines,
+      Err(e) => {
+        self.error = Some(e.to_string());
+        return None;
+      }
+    };
+    // every chat message an alias expands into counts, commands don't, except for `/me`
+    let mut last_sent = self.last_sent;
+    for line in &lines {
+      if line.starts_with('/') && !line.starts_with("/me ") {
+        continue;
+      }
+      let check = room.map(|room| room.check_send(line, user, last_sent, &is_emote));
+      if let Some(Err(e)) = check {
+        self.error = Some(e.to_string());
+        return None;
+      }
+      last_sent = Some(Instant::now());
+    }
+
+    self.text.clear();
+    self.completion = None;
+    self.error = None;
+    self.last_sent = last_sent;
+    Some(Sent {
+      lines,
+      reply_parent: self.reply.take().map(|reply| reply.msg_id),
+    })
+  }
+}
+
+/// The cursor position of the input with `id` in bytes, at the end if it was never focused.
+fn get_cursor(ctx: &egui::Context, id: egui::Id, text: &str) -> usize {
+  let chars = egui::TextEdit::load_state(ctx, id)
+    .and_then(|state| state.ccursor_range())
+    .map(|range| range.primary.index);
+  match chars {
+    Some(chars) => text
+      .char_indices()
+      .nth(chars)
+      .map(|(i, _)| i)
+      .unwrap_or(text.len()),
+    None => text.len(),
+  }
+}
+
+/// Moves the cursor of the input with `id` to the byte offset `cursor`.
+fn set_cursor(ctx: &egui::Context, id: egui::Id, text: &str, cursor: usize) {
+  let mut state = egui::TextEdit::load_state(ctx, id).unwrap_or_default();
+  let ccursor = CCursor::new(text[..cursor].chars().count());
+  state.set_ccursor_range(Some(CCursorRange::one(ccursor)));
+  state.store(ctx, id);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+  use crate::chat::alias::{Alias, Context};
+
+  fn submit(input: &mut ChatInput, room: &RoomState, text: &str) -> Option<Vec<String>> {
+    let aliases = [
+      Alias {
+        name: "hi".into(),
+        template: "hello {channel}".into(),
+      },
+      Alias {
+        name: "warn".into(),
+        template: "/timeout {1} 60 | @{1} please read the rules".into(),
+      },
+      Alias {
+        name: "twice".into(),
+        template: "{1+} | {1+}".into(),
+      },
+      Alias {
+        name: "b".into(),
+        template: "/ban {1}".into(),
+      },
+    ];
+    let context = Context {
+      channel: "forsen",
+      user: "me",
+    };
+    input.text = text.into();
+    let expand = |text: &str| alias::expand(&aliases, text, &context);
+    let sent = input.submit(Some(room), None, expand, |_| false);
+    sent.map(|sent| sent.lines)
+  }
+
+  #[test]
+  fn checks_expanded_aliases() {
+    let slow = RoomState {
+      slow: Some(Duration::from_secs(30)),
+      ..Default::default()
+    };
+    let mut input = ChatInput::new();
+    assert_eq!(submit(&mut input, &slow, "/hi").unwrap(), ["hello forsen"]);
+    // the alias expands into a chat message, which slow mode applies to
+    assert_eq!(submit(&mut input, &slow, "/hi"), None);
+    assert!(input.error.as_ref().unwrap().contains("slow mode"));
+    assert_eq!(input.text, "/hi");
+    // but not to commands
+    assert_eq!(
+      submit(&mut input, &slow, "/b someone").unwrap(),
+      ["/ban someone"]
+    );
+
+    // the second message of an alias would be sent too soon
+    let mut input = ChatInput::new();
+    assert_eq!(submit(&mut input, &slow, "/twice hi"), None);
+    assert_eq!(
+      submit(&mut input, &slow, "/warn a").unwrap(),
+      ["/timeout a 60", "@a please read the rules"]
+    );
+    let subs_only = RoomState {
+      subs_only: true,
+      ..Default::default()
+    };
+    let mut input = ChatInput::new();
+    assert_eq!(submit(&mut input, &subs_only, "/hi"), None);
+    assert!(input.error.as_ref().unwrap().contains("subscribers-only"));
+    // errors in the alias itself are shown the same way
+    assert_eq!(submit(&mut input, &subs_only, "/warn"), None);
+    assert!(input.error.as_ref().unwrap().contains("warn"));
+  }
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use egui::RichText;
+
+use crate::chat::history::History;
+use crate::chat::search::{Query, Search};
+
+use super::chat_view::{self, Action, Assets};
+
+/// Time spent searching per frame.
+const STEP_BUDGET: Duration = Duration::from_millis(4);
+/// More results than this are found, but not shown.
+const MAX_SHOWN: usize = 500;
+
+/// The Search tab.
+#[derive(Default)]
+pub struct SearchView {
+  query: Query,
+  from: String,
+  to: String,
+  all_channels: bool,
+  /// The query `search` was started with.
+  running: Option<Query>,
+  search: Option<Search>,
+  error: Option<String>,
+}
+
+impl SearchView {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Searches `channel`, or every channel if the user asked for that. Clicking a result returns
+  /// `Action::Jump`.
+  pub fn show(
+    &mut self,
+    ui: &mut egui::Ui,
+    history: &History,
+    channel: Option<&str>,
+    assets: &mut Assets<'_>,
+  ) -> Option<Action> {
+    self.form(ui);
+
+    self.query.channel = match self.all_channels {
+      true => None,
+      false => channel.map(String::from),
+    };
+    let dates = (parse_date(&self.from), parse_date(&self.to));
+    let (Ok(from), Ok(to)) = dates else {
+      ui.colored_label(ui.visuals().error_fg_color, "dates must be YYYY-MM-DD");
+      return None;
+    };
+    self.query.from = from;
+    self.query.to = to;
+
+    if self.running.as_ref() != Some(&self.query) {
+      self.restart(history);
+    }
+    if let Some(error) = &self.error {
+      ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+    let search = self.search.as_mut()?;
+    // keeps searching new messages after the older ones are done
+    if !search.step(history, STEP_BUDGET) {
+      ui.ctx().request_repaint();
+    }
+
+    ui.horizontal(|ui| {
+      let hits = search.hits().len();
+      ui.label(format!("{hits} results in {} messages", search.searched()));
+      if !search.is_done() {
+        ui.spinner();
+      }
+      if hits > MAX_SHOWN {
+        ui.label(RichText::new(format!("(showing the newest {MAX_SHOWN})")).weak());
+      }
+    });
+    ui.separator();
+
+    let mut action = None;
+    egui::ScrollArea::vertical()
+      .id_source("search_results")
+      .auto_shrink([false; 2])
+      .show(ui, |ui| {
+        for (i, hit) in search.hits().iter().take(MAX_SHOWN).enumerate() {
+          let Some(entry) = history.channel(&hit.channel).and_then(|h| h.get(hit.seq)) else {
+            continue;
+          };
+          let line = ui.scope(|ui| {
+            chat_view::line(
+              ui,
+              &entry.message,
+              entry.moderation,
+              entry.highlight,
+              Some(&hit.channel),
+              &[],
+              assets,
+            )
+          });
+          action = action.take().or(line.inner);
+          let row = ui.interact(
+            line.response.rect,
+            ui.id().with(("hit", i)),
+            egui::Sense::click(),
+          );
+          if row.on_hover_text("Show in channel").clicked() {
+            action = Some(Action::Jump {
+              channel: hit.channel.clone(),
+              seq: hit.seq,
+            });
+          }
+        }
+      });
+    action
+  }
+
+  fn form(&mut self, ui: &mut egui::Ui) {
+    egui::Grid::new("search_form")
+      .num_columns(2)
+      .show(ui, |ui| {
+        ui.label("Text");
+        ui.horizontal(|ui| {
+          ui.text_edit_singleline(&mut self.query.text);
+          ui.checkbox(&mut self.query.regex, "Regex");
+        });
+        ui.end_row();
+
+        ui.label("Author");
+        ui.text_edit_singleline(&mut self.query.author);
+        ui.end_row();
+
+        ui.label("Badge");
+        ui.add(egui::TextEdit::singleline(&mut self.query.badge).hint_text("e.g. subscriber"));
+        ui.end_row();
+
+        ui.label("Date");
+        ui.horizontal(|ui| {
+          let date = |text| {
+            egui::TextEdit::singleline(text)
+              .hint_text("YYYY-MM-DD")
+              .desired_width(90.0)
+          };
+          ui.add(date(&mut self.from));
+          ui.label("to");
+          ui.add(date(&mut self.to));
+        });
+        ui.end_row();
+
+        ui.label("");
+        ui.horizontal(|ui| {
+          ui.checkbox(&mut self.query.has_link, "Has link");
+          ui.checkbox(&mut self.all_channels, "All channels");
+        });
+        ui.end_row();
+      });
+  }
+
+  fn restart(&mut self, history: &History) {
+    self.running = Some(self.query.clone());
+    self.error = None;
+    self.search = None;
+    if self.query.is_empty() {
+      return;
+    }
+    match Search::new(&self.query, history) {
+      Ok(search) => self.search = Some(search),
+      Err(e) => self.error = Some(e.to_string()),
+    }
+  }
+}
+
+fn parse_date(s: &str) -> Result<Option<NaiveDate>, chrono::ParseError> {
+  match s.trim() {
+    "" => Ok(None),
+    s => NaiveDate::parse_from_str(s, "%Y-%m-%d").map(Some),
+  }
+}
@@ -0,0 +1,769 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use tokio::sync::mpsc;
+
+use crate::chat::command;
+use crate::emotes::{self, Provider};
+use crate::event::{self, Event, Receiver, Sender};
+use crate::eventsub::{self, Topic};
+use crate::helix;
+use crate::recent_messages::RecentMessages;
+use crate::twitch::{ChatConfig, Client, ConnectionError, DualClient, ReadError, WriteError};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub enum Command {
+  Join(String),
+  Part(String),
+  Privmsg {
+    channel: String,
+    text: String,
+    reply_parent: Option<String>,
+  },
+  /// Loads third-party emotes for `room_id`, or the global ones if it is `None`.
+  LoadEmotes {
+    room_id: Option<String>,
+  },
+  /// Loads the Twitch emotes in the user's emote sets, reported as the global Twitch set.
+  LoadEmoteSets {
+    ids: Vec<String>,
+  },
+  /// Loads the badge sets of `room_id`, or the global ones if it is `None`.
+  LoadBadges {
+    room_id: Option<String>,
+  },
+  /// Sends a whisper through Helix, since IRC doesn't support them anymore. `id` is the local id
+  /// of the message, reported back if sending fails. The user ids are looked up if they aren't
+  /// known yet.
+  Whisper {
+    /// Our own id, from `GLOBALUSERSTATE`.
+    from_id: Option<String>,
+    to: String,
+    to_id: Option<String>,
+    text: String,
+    id: String,
+  },
+  /// Runs a slash command in `channel` through Helix. Errors are reported as system messages.
+  Run {
+    channel: String,
+    broadcaster_id: String,
+    /// The user running the command.
+    user_id: String,
+    command: command::Command,
+  },
+  /// Loads the Helix user `login`.
+  LoadUser {
+    login: String,
+  },
+  /// Blocks `login` on Twitch.
+  Block {
+    login: String,
+  },
+}
+
+type Providers = Arc<Vec<Box<dyn Provider>>>;
+
+/// Owns the chat connection on a background thread.
+pub struct Session {
+  events: Receiver,
+  commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Session {
+  /// Connects anonymously if `config` is `None`. `ui` is repainted whenever an event arrives.
+  ///
+  /// Anything which goes through Helix, such as badges and EventSub, is unavailable without
+  /// `helix`. Channels are backfilled from `recent` when they are joined and after reconnecting.
+  pub fn start(
+    config: Option<ChatConfig>,
+    helix: Option<helix::Client>,
+    recent: Option<RecentMessages>,
+    channels: Vec<String>,
+    ui: egui::Context,
+  ) -> Self {
+    let (events, rx) = event::channel();
+    let (commands, command_rx) = mpsc::unbounded_channel();
+
+    thread::Builder::new()
+      .name("session".into())
+      .spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+          .enable_all()
+          .build()
+          .expect("failed to build session runtime");
+        if let Err(e) =
+          runtime.block_on(run(config, helix, recent, channels, events, command_rx, ui))
+        {
+          tracing::error!("session ended: {e}");
+        }
+      })
+      .expect("failed to spawn session thread");
+
+    Self {
+      events: rx,
+      commands,
+    }
+  }
+
+  pub fn poll(&mut self) -> Option<Event> {
+    self.events.try_recv().ok()
+  }
+
+  pub fn send(&self, command: Command) {
+    let _ = self.commands.send(command);
+  }
+}
+
+async fn run(
+  config: Option<ChatConfig>,
+  helix: Option<helix::Client>,
+  recent: Option<RecentMessages>,
+  mut channels: Vec<String>,
+  events: Sender,
+  mut commands: mpsc::UnboundedReceiver<Command>,
+  ui: egui::Context,
+) -> Result<(), ConnectionError> {
+  let providers: Providers = Arc::new(emotes::default_providers(reqwest::Client::new()));
+  load_emotes(&providers, None, &events, &ui);
+  load_badges(helix.as_ref(), None, &events, &ui);
+  let eventsub = start_eventsub(helix.as_ref(), &channels, &events, &ui);
+
+  let mut conn = Connection::connect(config).await?;
+  conn.join_all(&channels).await?;
+  load_recent_messages(recent.as_ref(), &channels, &events, &ui);
+
+  loop {
+    tokio::select! {
+      message = conn.message() => {
+        let message = match message {
+          Ok(message) => message,
+          Err(e) => {
+            tracing::warn!("{e}");
+            conn.reconnect(&channels).await?;
+            load_recent_messages(recent.as_ref(), &channels, &events, &ui);
+            continue;
+          }
+        };
+        match message.command() {
+          twitch::Command::Ping => {
+            let pong = format!("PONG {}\r\n", message.params().unwrap_or(":tmi.twitch.tv"));
+            if conn.send(&pong).await.is_err() {
+              conn.reconnect(&channels).await?;
+              load_recent_messages(recent.as_ref(), &channels, &events, &ui);
+            }
+            continue;
+          }
+          twitch::Command::Reconnect => {
+            conn.reconnect(&channels).await?;
+            load_recent_messages(recent.as_ref(), &channels, &events, &ui);
+          }
+          _ => {}
+        }
+        if events.send(message.into()).is_err() {
+          return Ok(());
+        }
+        ui.request_repaint();
+      }
+      command = commands.recv() => {
+        let line = match command {
+          None => return Ok(()),
+          Some(Command::Join(channel)) => {
+            let line = format!("JOIN #{channel}\r\n");
+            load_recent_messages(recent.as_ref(), std::slice::from_ref(&channel), &events, &ui);
+            if let Some(eventsub) = &eventsub {
+              let _ = eventsub.send(Membership::Join(channel.clone()));
+            }
+            channels.push(channel);
+            line
+          }
+          Some(Command::Part(channel)) => {
+            let line = format!("PART #{channel}\r\n");
+            if let Some(eventsub) = &eventsub {
+              let _ = eventsub.send(Membership::Part(channel.clone()));
+            }
+            channels.retain(|c| *c != channel);
+            line
+          }
+          Some(Command::Privmsg {
+            channel,
+            text,
+            reply_parent,
+          }) => match reply_parent {
+            Some(id) => format!("@reply-parent-msg-id={id} PRIVMSG #{channel} :{text}\r\n"),
+            None => format!("PRIVMSG #{channel} :{text}\r\n"),
+          },
+          Some(Command::LoadEmotes { room_id }) => {
+            load_emotes(&providers, room_id, &events, &ui);
+            continue;
+          }
+          Some(Command::LoadEmoteSets { ids }) => {
+            load_emote_sets(helix.as_ref(), ids, &events, &ui);
+            continue;
+          }
+          Some(Command::LoadBadges { room_id }) => {
+            load_badges(helix.as_ref(), room_id, &events, &ui);
+            continue;
+          }
+          Some(Command::Whisper {
+            from_id,
+            to,
+            to_id,
+            text,
+            id,
+          }) => {
+            let ids = (from_id, to_id);
+            whisper(helix.as_ref(), ids, to, text, id, &events, &ui);
+            continue;
+          }
+          Some(Command::Run {
+            channel,
+            broadcaster_id,
+            user_id,
+            command,
+          }) => {
+            let target = Target {
+              channel,
+              broadcaster_id,
+              user_id,
+            };
+            run_command(helix.as_ref(), target, command, &events, &ui);
+            continue;
+          }
+          Some(Command::LoadUser { login }) => {
+            load_user(helix.as_ref(), login, &events, &ui);
+            continue;
+          }
+          Some(Command::Block { login }) => {
+            block(helix.as_ref(), login);
+            continue;
+          }
+        };
+        if let Err(e) = conn.send(&line).await {
+          tracing::warn!("{e}");
+          conn.reconnect(&channels).await?;
+          load_recent_messages(recent.as_ref(), &channels, &events, &ui);
+        }
+      }
+    }
+  }
+}
+
+fn load_emotes(
+  providers: &Providers,
+  room_id: Option<String>,
+  events: &Sender,
+  ui: &egui::Context,
+) {
+  let (providers, events, ui) = (providers.clone(), events.clone(), ui.clone());
+  tokio::spawn(async move {
+    let results = join_all(providers.iter().map(|provider| async {
+      let result = match &room_id {
+        Some(room_id) => provider.channel(room_id).await,
+        None => provider.global().await,
+      };
+      (provider.source(), result)
+    }))
+    .await;
+
+    for (source, result) in results {
+      match result {
+        Ok(emotes) => {
+          let room_id = room_id.clone();
+          let _ = events.send(Event::Emotes {
+            room_id,
+            source,
+            emotes,
+          });
+        }
+        Err(e) => tracing::warn!(?source, "{e}"),
+      }
+    }
+    ui.request_repaint();
+  });
+}
+
+fn load_recent_messages(
+  recent: Option<&RecentMessages>,
+  channels: &[String],
+  events: &Sender,
+  ui: &egui::Context,
+) {
+  let Some(recent) = recent.cloned() else {
+    return;
+  };
+  let (channels, events, ui) = (channels.to_vec(), events.clone(), ui.clone());
+  tokio::spawn(async move {
+    let results = join_all(channels.iter().map(|channel| recent.get(channel))).await;
+    for (channel, result) in channels.into_iter().zip(results) {
+      match result {
+        Ok(messages) => {
+          let _ = events.send(Event::RecentMessages { channel, messages });
+        }
+        Err(e) => tracing::warn!(channel, "{e}"),
+      }
+    }
+    ui.request_repaint();
+  });
+}
+
+/// A channel joined or parted after EventSub was started.
+enum Membership {
+  Join(String),
+  Part(String),
+}
+
+/// Connects to EventSub and subscribes to the topics of `channels`. Channels sent to the returned
+/// sender are subscribed to or unsubscribed from later on.
+fn start_eventsub(
+  helix: Option<&helix::Client>,
+  channels: &[String],
+  events: &Sender,
+  ui: &egui::Context,
+) -> Option<mpsc::UnboundedSender<Membership>> {
+  let helix = helix.cloned()?;
+  let (joins, joins_rx) = mpsc::unbounded_channel();
+  let (channels, events, ui) = (channels.to_vec(), events.clone(), ui.clone());
+  tokio::spawn(async move {
+    if let Err(e) = run_eventsub(helix, channels, joins_rx, events, ui).await {
+      tracing::warn!("{e}");
+    }
+  });
+  Some(joins)
+}
+
+async fn run_eventsub(
+  helix: helix::Client,
+  channels: Vec<String>,
+  mut joins: mpsc::UnboundedReceiver<Membership>,
+  events: Sender,
+  ui: egui::Context,
+) -> Result<(), eventsub::Error> {
+  let user_id = helix.get_current_user().await?.id;
+  let mut client = eventsub::Config::new(&user_id)
+    .connect(helix.clone(), TIMEOUT)
+    .await?;
+  // broadcaster ids by login, to unsubscribe when a channel is parted
+  let mut broadcasters = HashMap::new();
+  subscribe(&mut client, &helix, &user_id, &channels, &mut broadcasters).await;
+
+  loop {
+    tokio::select! {
+      event = client.next() => {
+        let event = match event {
+          Ok(event) => event,
+          // the client keeps trying to reconnect on the next call
+          Err(e) => {
+            tracing::warn!("{e}");
+            continue;
+          }
+        };
+        if events.send(event.into()).is_err() {
+          return Ok(());
+        }
+        ui.request_repaint();
+      }
+      Some(membership) = joins.recv() => match membership {
+        Membership::Join(channel) => {
+          subscribe(&mut client, &helix, &user_id, &[channel], &mut broadcasters).await;
+        }
+        Membership::Part(channel) => {
+          if let Some(broadcaster_id) = broadcasters.remove(&channel) {
+            client.unsubscribe(&broadcaster_id).await;
+          }
+        }
+      },
+    }
+  }
+}
+
+/// Subscribes to every topic `user_id` may read in `channels`, and adds their ids to
+/// `broadcasters`. Failures are only logged, since e.g. follows are only available to moderators.
+async fn subscribe(
+  client: &mut eventsub::Client,
+  helix: &helix::Client,
+  user_id: &str,
+  channels: &[String],
+  broadcasters: &mut HashMap<String, String>,
+) {
+  for chunk in channels.chunks(100) {
+    let logins = chunk.iter().map(String::as_str).collect::<Vec<_>>();
+    let users = match helix.get_users(&[], &logins).await {
+      Ok(users) => users,
+      Err(e) => {
+        tracing::warn!(?logins, "failed to subscribe to eventsub: {e}");
+        continue;
+      }
+    };
+    for user in users {
+      for topic in topics(&user.id, user_id) {
+        if let Err(e) = client.subscribe(*topic, &user.id).await {
+          tracing::debug!(topic = topic.name(), user.login, "{e}");
+        }
+      }
+      broadcasters.insert(user.login, user.id);
+    }
+  }
+}
+
+/// Polls, predictions and redemptions can only be read by the broadcaster.
+fn topics(broadcaster_id: &str, user_id: &str) -> &'static [Topic] {
+  match broadcaster_id == user_id {
+    true => Topic::ALL,
+    false => &[
+      Topic::Follow,
+      Topic::Raid,
+      Topic::StreamOnline,
+      Topic::StreamOffline,
+    ],
+  }
+}
+
+fn load_emote_sets(
+  helix: Option<&helix::Client>,
+  ids: Vec<String>,
+  events: &Sender,
+  ui: &egui::Context,
+) {
+  let Some(helix) = helix.cloned() else {
+    return;
+  };
+  let (events, ui) = (events.clone(), ui.clone());
+  tokio::spawn(async move {
+    match helix.get_emote_sets(&ids).await {
+      Ok(set) => {
+        let emotes = set
+          .emotes
+          .iter()
+          .map(|emote| emotes::Emote::twitch(&emote.id, &emote.name))
+          .collect();
+        let _ = events.send(Event::Emotes {
+          room_id: None,
+          source: emotes::Source::Twitch,
+          emotes,
+        });
+        ui.request_repaint();
+      }
+      Err(e) => tracing::warn!("failed to load emote sets: {e}"),
+    }
+  });
+}
+
+fn load_badges(
+  helix: Option<&helix::Client>,
+  room_id: Option<String>,
+  events: &Sender,
+  ui: &egui::Context,
+) {
+  let Some(helix) = helix.cloned() else {
+    return;
+  };
+  let (events, ui) = (events.clone(), ui.clone());
+  tokio::spawn(async move {
+    let result = match &room_id {
+      Some(room_id) => helix.get_channel_chat_badges(room_id).await,
+      None => helix.get_global_chat_badges().await,
+    };
+    match result {
+      Ok(sets) => {
+        let _ = events.send(Event::Badges { room_id, sets });
+        ui.request_repaint();
+      }
+      Err(e) => tracing::warn!(?room_id, "failed to load badges: {e}"),
+    }
+  });
+}
+
+fn whisper(
+  helix: Option<&helix::Client>,
+  (from_id, to_id): (Option<String>, Option<String>),
+  to: String,
+  text: String,
+  id: String,
+  events: &Sender,
+  ui: &egui::Context,
+) {
+  let (helix, events, ui) = (helix.cloned(), events.clone(), ui.clone());
+  tokio::spawn(async move {
+    let result = match helix {
+      Some(helix) => {
+        let from_id = async {
+          match from_id {
+            Some(id) => Ok(id),
+            None => helix.get_current_user().await.map(|user| user.id),
+          }
+        };
+        let to_id = async {
+          match to_id {
+            Some(id) => Ok(id),
+            None => helix.get_user_by_login(&to).await.map(|user| user.id),
+          }
+        };
+        match tokio::try_join!(from_id, to_id) {
+          Ok((from_id, to_id)) => helix.send_whisper(&from_id, &to_id, &text).await,
+          Err(e) => Err(e),
+        }
+      }
+      None => Err(helix::Error::MissingToken),
+    };
+    if let Err(e) = result {
+      tracing::warn!(to, "failed to send whisper: {e}");
+      let error = e.to_string();
+      let _ = events.send(Event::WhisperFailed {
+        login: to,
+        id,
+        error,
+      });
+      ui.request_repaint();
+    }
+  });
+}
+
+struct Target {
+  channel: String,
+  broadcaster_id: String,
+  user_id: String,
+}
+
+fn run_command(
+  helix: Option<&helix::Client>,
+  target: Target,
+  command: command::Command,
+  events: &Sender,
+  ui: &egui::Context,
+) {
+  let (helix, events, ui) = (helix.cloned(), events.clone(), ui.clone());
+  tokio::spawn(async move {
+    let result = match helix {
+      Some(helix) => execute(&helix, &target, &command).await,
+      None => Err(helix::Error::MissingToken),
+    };
+    let text = match result {
+      Ok(Some(text)) => text,
+      Ok(None) => return,
+      Err(e) => {
+        tracing::warn!(?command, "{e}");
+        e.to_string()
+      }
+    };
+    let _ = events.send(Event::System {
+      channel: target.channel,
+      text,
+    });
+    ui.request_repaint();
+  });
+}
+
+/// Returns a confirmation for commands which have no visible effect in chat.
+async fn execute(
+  helix: &helix::Client,
+  target: &Target,
+  command: &command::Command,
+) -> Result<Option<String>, helix::Error> {
+  use command::Command as C;
+
+  let Target {
+    broadcaster_id,
+    user_id,
+    ..
+  } = target;
+  let settings = |settings: helix::chat::ChatSettings| async move {
+    helix
+      .update_chat_settings(broadcaster_id, user_id, &settings)
+      .await
+      .map(|_| None)
+  };
+
+  match command {
+    C::Clear | C::Join(_) | C::Part(_) => Ok(None),
+    C::Ban { user, reason } => {
+      let id = user_id_of(helix, user).await?;
+      helix
+        .ban_user(broadcaster_id, user_id, &id, None, reason)
+        .await?;
+      Ok(None)
+    }
+    C::Timeout {
+      user,
+      seconds,
+      reason,
+    } => {
+      let id = user_id_of(helix, user).await?;
+      helix
+        .ban_user(broadcaster_id, user_id, &id, Some(*seconds), reason)
+        .await?;
+      Ok(None)
+    }
+    C::Unban { user } | C::Untimeout { user } => {
+      let id = user_id_of(helix, user).await?;
+      helix.unban_user(broadcaster_id, user_id, &id).await?;
+      Ok(Some(format!("{user} is no longer banned or timed out")))
+    }
+    C::Delete { msg_id } => {
+      helix
+        .delete_chat_messages(broadcaster_id, user_id, Some(msg_id))
+        .await?;
+      Ok(None)
+    }
+    C::Slow(seconds) => {
+      settings(helix::chat::ChatSettings {
+        slow_mode: Some(seconds.is_some()),
+        slow_mode_wait_time: *seconds,
+        ..Default::default()
+      })
+      .await
+    }
+    C::Followers(minutes) => {
+      settings(helix::chat::ChatSettings {
+        follower_mode: Some(minutes.is_some()),
+        follower_mode_duration: *minutes,
+        ..Default::default()
+      })
+      .await
+    }
+    C::Subscribers(on) => {
+      settings(helix::chat::ChatSettings {
+        subscriber_mode: Some(*on),
+        ..Default::default()
+      })
+      .await
+    }
+    C::EmoteOnly(on) => {
+      settings(helix::chat::ChatSettings {
+        emote_mode: Some(*on),
+        ..Default::default()
+      })
+      .await
+    }
+    C::UniqueChat(on) => {
+      settings(helix::chat::ChatSettings {
+        unique_chat_mode: Some(*on),
+        ..Default::default()
+      })
+      .await
+    }
+    C::Color(color) => {
+      helix.update_user_chat_color(user_id, color).await?;
+      Ok(Some(format!("Your color was changed to {color}")))
+    }
+    C::Announce { color, message } => {
+      helix
+        .send_chat_announcement(broadcaster_id, user_id, message, color.as_str())
+        .await?;
+      Ok(None)
+    }
+    C::Raid { user } => {
+      let id = user_id_of(helix, user).await?;
+      helix.start_raid(broadcaster_id, &id).await?;
+      Ok(Some(format!("Raiding {user}")))
+    }
+    C::Unraid => {
+      helix.cancel_raid(broadcaster_id).await?;
+      Ok(Some("The raid was cancelled".into()))
+    }
+    C::Mod { user } => {
+      let id = user_id_of(helix, user).await?;
+      helix.add_channel_moderator(broadcaster_id, &id).await?;
+      Ok(Some(format!("{user} is now a moderator")))
+    }
+    C::Unmod { user } => {
+      let id = user_id_of(helix, user).await?;
+      helix.remove_channel_moderator(broadcaster_id, &id).await?;
+      Ok(Some(format!("{user} is no longer a moderator")))
+    }
+    C::Vip { user } => {
+      let id = user_id_of(helix, user).await?;
+      helix.add_channel_vip(broadcaster_id, &id).await?;
+      Ok(Some(format!("{user} is now a VIP")))
+    }
+    C::Unvip { user } => {
+      let id = user_id_of(helix, user).await?;
+      helix.remove_channel_vip(broadcaster_id, &id).await?;
+      Ok(Some(format!("{user} is no longer a VIP")))
+    }
+  }
+}
+
+async fn user_id_of(helix: &helix::Client, login: &str) -> Result<String, helix::Error> {
+  Ok(helix.get_user_by_login(login).await?.id)
+}
+
+fn load_user(helix: Option<&helix::Client>, login: String, events: &Sender, ui: &egui::Context) {
+  let Some(helix) = helix.cloned() else {
+    return;
+  };
+  let (events, ui) = (events.clone(), ui.clone());
+  tokio::spawn(async move {
+    match helix.get_user_by_login(&login).await {
+      Ok(user) => {
+        let _ = events.send(Event::User(Box::new(user)));
+        ui.request_repaint();
+      }
+      Err(e) => tracing::warn!(login, "failed to load user: {e}"),
+    }
+  });
+}
+
+fn block(helix: Option<&helix::Client>, login: String) {
+  let Some(helix) = helix.cloned() else {
+    tracing::warn!(login, "cannot block user without a Helix client");
+    return;
+  };
+  tokio::spawn(async move {
+    let result = match helix.get_user_by_login(&login).await {
+      Ok(user) => helix.block_user(&user.id).await,
+      Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+      tracing::warn!(login, "failed to block user: {e}");
+    }
+  });
+}
+
+enum Connection {
+  Anon(Box<Client>),
+  Dual(Box<DualClient>),
+}
+
+impl Connection {
+  async fn connect(config: Option<ChatConfig>) -> Result<Self, ConnectionError> {
+    Ok(match config {
+      Some(config) => Connection::Dual(Box::new(config.connect_dual(TIMEOUT).await?)),
+      None => Connection::Anon(Box::new(ChatConfig::anon().connect(TIMEOUT).await?)),
+    })
+  }
+
+  async fn message(&mut self) -> Result<twitch::Message, ReadError> {
+    match self {
+      Connection::Anon(client) => client.message().await,
+      Connection::Dual(client) => client.message().await,
+    }
+  }
+
+  async fn send(&mut self, s: &str) -> Result<(), WriteError> {
+    match self {
+      Connection::Anon(client) => client.send(s).await,
+      Connection::Dual(client) => client.send(s).await,
+    }
+  }
+
+  async fn join_all(&mut self, channels: &[String]) -> Result<(), ConnectionError> {
+    for channel in channels {
+      if let Err(WriteError::Io(e)) = self.send(&format!("JOIN #{channel}\r\n")).await {
+        return Err(e.into());
+      }
+    }
+    Ok(())
+  }
+
+  async fn reconnect(&mut self, channels: &[String]) -> Result<(), ConnectionError> {
+    match self {
+      Connection::Anon(client) => client.reconnect(TIMEOUT).await?,
+      Connection::Dual(client) => client.reconnect(TIMEOUT).await?,
+    }
+    self.join_all(channels).await
+  }
+}
@@ -0,0 +1,127 @@
+use chrono::Utc;
+use egui::RichText;
+
+use crate::chat::command::{format_duration, Command};
+use crate::chat::history::ChannelHistory;
+use crate::chat::mod_log::ModLog;
+use crate::helix::users::User;
+
+use super::chat_view::{self, Action, Assets};
+
+/// Messages shown on the card, older ones are only in the channel view.
+const RECENT_MESSAGES: usize = 50;
+
+/// The user card opened by clicking a name.
+pub struct UserCard {
+  pub channel: String,
+  pub login: String,
+  pub user_id: String,
+}
+
+impl UserCard {
+  /// `user` is `None` until it was loaded through Helix. The moderation buttons are only shown
+  /// if `moderator` is set.
+  pub fn show(
+    &self,
+    ui: &mut egui::Ui,
+    user: Option<&User>,
+    history: Option<&ChannelHistory>,
+    mod_log: &ModLog,
+    moderator: bool,
+    assets: &mut Assets<'_>,
+  ) -> Option<Action> {
+    let mut action = None;
+
+    match user {
+      Some(user) => {
+        ui.heading(&user.display_name);
+        let age = Utc::now().signed_duration_since(user.created_at);
+        let age = match age.num_days() {
+          days if days >= 365 => format!("{} years", days / 365),
+          days => format!("{days} days"),
+        };
+        ui.label(format!(
+          "Account created {} ({age} ago)",
+          user.created_at.format("%Y-%m-%d")
+        ));
+      }
+      None => {
+        ui.heading(&self.login);
+        ui.label(RichText::new("Loading…").weak());
+      }
+    }
+
+    if moderator {
+      ui.horizontal_wrapped(|ui| {
+        let user = || self.login.clone();
+        let buttons = [
+          (
+            "Ban",
+            Command::Ban {
+              user: user(),
+              reason: String::new(),
+            },
+          ),
+          ("Unban", Command::Unban { user: user() }),
+          ("VIP", Command::Vip { user: user() }),
+          ("Unvip", Command::Unvip { user: user() }),
+          ("Mod", Command::Mod { user: user() }),
+          ("Unmod", Command::Unmod { user: user() }),
+        ];
+        for (label, command) in buttons {
+          if ui.button(label).clicked() {
+            action = Some(Action::Moderate {
+              channel: self.channel.clone(),
+              command,
+            });
+          }
+        }
+      });
+    }
+
+    ui.separator();
+    ui.label(RichText::new("Timeouts and bans").strong());
+    let mut timeouts = mod_log
+      .for_user(&self.channel, &self.user_id)
+      .rev()
+      .peekable();
+    if timeouts.peek().is_none() {
+      ui.label(RichText::new("None").weak());
+    }
+    for entry in timeouts {
+      let timestamp = entry.timestamp.with_timezone(&chrono::Local);
+      let what = match entry.duration {
+        Some(seconds) => format!("timed out for {}", format_duration(seconds as u32)),
+        None => "banned".into(),
+      };
+      ui.label(format!("{} {what}", timestamp.format("%Y-%m-%d %H:%M")));
+    }
+
+    ui.separator();
+    ui.label(RichText::new("Recent messages").strong());
+    egui::ScrollArea::vertical()
+      .id_source("user_card_messages")
+      .auto_shrink([false; 2])
+      .stick_to_bottom(true)
+      .show(ui, |ui| {
+        let entries = history
+          .into_iter()
+          .flat_map(|history| history.by_user(&self.user_id))
+          .collect::<Vec<_>>();
+        let recent = &entries[entries.len().saturating_sub(RECENT_MESSAGES)..];
+        for entry in recent {
+          action = action.take().or(chat_view::line(
+            ui,
+            &entry.message,
+            entry.moderation,
+            None,
+            None,
+            &[],
+            assets,
+          ));
+        }
+      });
+
+    action
+  }
+}
@@ -0,0 +1,97 @@
+use egui::RichText;
+
+use crate::chat::whispers::Whispers;
+
+/// The Whispers tab: a list of conversations, and the selected one.
+#[derive(Default)]
+pub struct WhisperView {
+  selected: Option<String>,
+  new_login: String,
+  text: String,
+}
+
+impl WhisperView {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns the recipient and text of a whisper once the user presses enter.
+  pub fn show(&mut self, ui: &mut egui::Ui, whispers: &mut Whispers) -> Option<(String, String)> {
+    egui::SidePanel::left("whisper_list")
+      .resizable(true)
+      .default_width(160.0)
+      .show_inside(ui, |ui| {
+        ui.horizontal(|ui| {
+          let response = ui.add(
+            egui::TextEdit::singleline(&mut self.new_login)
+              .hint_text("User")
+              .desired_width(100.0),
+          );
+          let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+          if (ui.button("Open").clicked() || submitted) && !self.new_login.trim().is_empty() {
+            whispers.open(&self.new_login);
+            self.selected = Some(self.new_login.trim().trim_start_matches('@').to_lowercase());
+            self.new_login.clear();
+          }
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+          for conversation in whispers.conversations() {
+            let label = match conversation.unread {
+              0 => conversation.name.clone(),
+              n => format!("{} ({n})", conversation.name),
+            };
+            let selected = self.selected.as_deref() == Some(&conversation.login);
+            if ui.selectable_label(selected, label).clicked() {
+              self.selected = Some(conversation.login.clone());
+            }
+          }
+        });
+      });
+
+    let login = self.selected.clone()?;
+    whispers.mark_read(&login);
+    let conversation = whispers.get(&login)?;
+
+    let mut sent = None;
+    egui::TopBottomPanel::bottom("whisper_input").show_inside(ui, |ui| {
+      let response = ui.add(
+        egui::TextEdit::singleline(&mut self.text)
+          .hint_text(format!("Whisper to {}", conversation.name))
+          .desired_width(f32::INFINITY),
+      );
+      if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+        let text = self.text.trim();
+        if !text.is_empty() {
+          sent = Some((login.clone(), text.to_string()));
+          self.text.clear();
+        }
+        response.request_focus();
+      }
+    });
+
+    egui::CentralPanel::default().show_inside(ui, |ui| {
+      egui::ScrollArea::vertical()
+        .auto_shrink([false; 2])
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+          for message in &conversation.messages {
+            ui.horizontal_wrapped(|ui| {
+              ui.spacing_mut().item_spacing.x = 4.0;
+              let timestamp = message.timestamp.with_timezone(&chrono::Local);
+              ui.label(RichText::new(timestamp.format("%H:%M").to_string()).weak());
+              let name = RichText::new(format!("{}:", message.from)).strong();
+              ui.label(if message.outgoing { name.weak() } else { name });
+              ui.label(&message.text);
+              if let Some(error) = &message.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+              }
+            });
+          }
+        });
+    });
+
+    sent
+  }
+}
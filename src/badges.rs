@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use crate::chat::Badge;
+use crate::helix::chat::BadgeSet;
+
+#[derive(Clone, Debug)]
+pub struct BadgeImage {
+  pub set_id: String,
+  pub version: String,
+  pub title: String,
+  pub description: String,
+  pub url_1x: String,
+  pub url_2x: String,
+  pub url_4x: String,
+}
+
+type Key = (String, String);
+
+/// Global and per-channel badge sets from Helix, keyed by `set_id/version` as they appear in
+/// the `badges` tag.
+#[derive(Default)]
+pub struct BadgeRegistry {
+  global: HashMap<Key, BadgeImage>,
+  channels: HashMap<String, HashMap<Key, BadgeImage>>,
+}
+
+impl BadgeRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn set_global(&mut self, sets: Vec<BadgeSet>) {
+    self.global = index(sets);
+  }
+
+  pub fn set_channel(&mut self, room_id: &str, sets: Vec<BadgeSet>) {
+    self.channels.insert(room_id.into(), index(sets));
+  }
+
+  pub fn remove_channel(&mut self, room_id: &str) {
+    self.channels.remove(room_id);
+  }
+
+  /// Channel badges (custom sub and bits badges) take precedence over global ones.
+  pub fn get(&self, room_id: &str, badge: &Badge) -> Option<&BadgeImage> {
+    let key = (badge.name.clone(), badge.version.clone());
+    self
+      .channels
+      .get(room_id)
+      .and_then(|badges| badges.get(&key))
+      .or_else(|| self.global.get(&key))
+  }
+
+  /// Resolves every badge of a message. Unknown badges are skipped.
+  pub fn resolve<'a>(
+    &'a self,
+    room_id: &str,
+    badges: &[Badge],
+    badge_info: &[Badge],
+  ) -> Vec<ResolvedBadge<'a>> {
+    badges
+      .iter()
+      .filter_map(|badge| {
+        let image = self.get(room_id, badge)?;
+        let info = badge_info
+          .iter()
+          .find(|info| info.name == badge.name)
+          .map(|info| info.version.clone());
+        Some(ResolvedBadge { image, info })
+      })
+      .collect()
+  }
+}
+
+fn index(sets: Vec<BadgeSet>) -> HashMap<Key, BadgeImage> {
+  let mut index = HashMap::new();
+  for set in sets {
+    for version in set.versions {
+      let key = (set.set_id.clone(), version.id.clone());
+      index.insert(
+        key,
+        BadgeImage {
+          set_id: set.set_id.clone(),
+          version: version.id,
+          title: version.title,
+          description: version.description,
+          url_1x: version.image_url_1x,
+          url_2x: version.image_url_2x,
+          url_4x: version.image_url_4x,
+        },
+      );
+    }
+  }
+  index
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedBadge<'a> {
+  pub image: &'a BadgeImage,
+  /// The matching `badge-info` value, e.g. the exact number of subscribed months.
+  pub info: Option<String>,
+}
+
+impl ResolvedBadge<'_> {
+  pub fn months(&self) -> Option<u32> {
+    match self.image.set_id.as_str() {
+      "subscriber" | "founder" => self.info.as_deref()?.parse().ok(),
+      _ => None,
+    }
+  }
+
+  pub fn tooltip(&self) -> String {
+    match (self.months(), self.info.as_deref()) {
+      (Some(1), _) => format!("{} (1 month)", self.image.title),
+      (Some(months), _) => format!("{} ({months} months)", self.image.title),
+      // e.g. the outcome of a prediction
+      (None, Some(info)) if !info.is_empty() => format!("{}: {info}", self.image.title),
+      _ => self.image.title.clone(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::helix::chat::BadgeVersion;
+
+  fn set(set_id: &str, versions: &[(&str, &str)]) -> BadgeSet {
+    BadgeSet {
+      set_id: set_id.into(),
+      versions: versions
+        .iter()
+        .map(|(id, title)| BadgeVersion {
+          id: id.to_string(),
+          image_url_1x: format!("https://cdn.test/{set_id}/{id}/1"),
+          image_url_2x: format!("https://cdn.test/{set_id}/{id}/2"),
+          image_url_4x: format!("https://cdn.test/{set_id}/{id}/3"),
+          title: title.to_string(),
+          description: String::new(),
+        })
+        .collect(),
+    }
+  }
+
+  fn badge(name: &str, version: &str) -> Badge {
+    Badge {
+      name: name.into(),
+      version: version.into(),
+    }
+  }
+
+  fn registry() -> BadgeRegistry {
+    let mut registry = BadgeRegistry::new();
+    registry.set_global(vec![
+      set(
+        "subscriber",
+        &[("0", "Subscriber"), ("3", "3-Month Subscriber")],
+      ),
+      set("moderator", &[("1", "Moderator")]),
+      set("predictions", &[("blue-1", "Predicted Blue (1)")]),
+    ]);
+    registry.set_channel("1", vec![set("subscriber", &[("0", "Channel Subscriber")])]);
+    registry
+  }
+
+  #[test]
+  fn channel_badges_first() {
+    let registry = registry();
+    let title = |room_id: &str, name: &str, version: &str| {
+      let badge = badge(name, version);
+      registry
+        .get(room_id, &badge)
+        .map(|image| image.title.clone())
+    };
+    assert_eq!(title("1", "subscriber", "0").unwrap(), "Channel Subscriber");
+    assert_eq!(title("2", "subscriber", "0").unwrap(), "Subscriber");
+    // versions the channel doesn't have fall back to the global ones
+    assert_eq!(title("1", "subscriber", "3").unwrap(), "3-Month Subscriber");
+    assert_eq!(title("1", "moderator", "1").unwrap(), "Moderator");
+    assert_eq!(title("1", "moderator", "2"), None);
+
+    let mut registry = registry;
+    registry.remove_channel("1");
+    let image = registry.get("1", &badge("subscriber", "0")).unwrap();
+    assert_eq!(image.title, "Subscriber");
+  }
+
+  #[test]
+  fn tooltips() {
+    let registry = registry();
+    let badges = [
+      badge("moderator", "1"),
+      badge("unknown", "1"),
+      badge("subscriber", "3"),
+      badge("predictions", "blue-1"),
+    ];
+    let info = [badge("subscriber", "5"), badge("predictions", "Yes")];
+    let resolved = registry.resolve("2", &badges, &info);
+    let tooltips = resolved
+      .iter()
+      .map(ResolvedBadge::tooltip)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      tooltips,
+      [
+        "Moderator",
+        "3-Month Subscriber (5 months)",
+        "Predicted Blue (1): Yes"
+      ]
+    );
+    assert_eq!(resolved[1].months(), Some(5));
+    assert_eq!(resolved[0].months(), None);
+
+    let resolved = registry.resolve(
+      "2",
+      &[badge("subscriber", "0")],
+      &[badge("subscriber", "1")],
+    );
+    assert_eq!(resolved[0].tooltip(), "Subscriber (1 month)");
+  }
+}
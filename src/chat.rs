@@ -0,0 +1,21 @@
+pub mod alias;
+pub mod collapse;
+pub mod color;
+pub mod command;
+pub mod completion;
+pub mod event;
+pub mod filter;
+pub mod fragment;
+pub mod highlight;
+pub mod history;
+pub mod logs;
+pub mod mod_log;
+pub mod phrase;
+pub mod room_state;
+pub mod sanitize;
+pub mod search;
+pub mod tags;
+pub mod whispers;
+
+pub use self::event::ChatEvent;
+pub use self::tags::{Badge, Color, Emote};
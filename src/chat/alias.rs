@@ -0,0 +1,259 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Splits an alias template into several commands.
+pub const SEPARATOR: &str = " | ";
+
+/// Aliases may expand into other aliases, up to this depth.
+const MAX_DEPTH: usize = 8;
+/// Limit on the number of lines a single input may expand into.
+const MAX_LINES: usize = 32;
+/// Limit on the number of template parts expanded for a single input, including ones which
+/// expand into nothing, so that a few nested aliases can't fan out exponentially.
+const MAX_STEPS: usize = 256;
+
+/// A custom command, e.g. `warn` with the template `/timeout {1} 1 {2+}`.
+///
+/// Templates may contain:
+/// - `{1}`, `{2}`, ... for single arguments
+/// - `{1+}`, `{2+}`, ... for an argument and everything after it
+/// - `{channel}` and `{streamer}` for the channel the command runs in
+/// - `{user}` for our own login
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Alias {
+  /// Without the leading `/`.
+  pub name: String,
+  pub template: String,
+}
+
+/// Values for the placeholders which don't come from arguments.
+pub struct Context<'a> {
+  pub channel: &'a str,
+  pub user: &'a str,
+}
+
+/// Expands `text` into the lines to run in order. Text which doesn't start with an alias is
+/// returned as-is.
+pub fn expand(aliases: &[Alias], text: &str, context: &Context<'_>) -> Result<Vec<String>, Error> {
+  let mut expansion = Expansion {
+    stack: Vec::new(),
+    lines: Vec::new(),
+    steps: 0,
+  };
+  expand_into(aliases, text, context, &mut expansion)?;
+  Ok(expansion.lines)
+}
+
+struct Expansion<'a> {
+  /// The aliases being expanded.
+  stack: Vec<&'a str>,
+  lines: Vec<String>,
+  steps: usize,
+}
+
+fn expand_into<'a>(
+  aliases: &'a [Alias],
+  text: &str,
+  context: &Context<'_>,
+  expansion: &mut Expansion<'a>,
+) -> Result<(), Error> {
+  let alias = text.trim().strip_prefix('/').and_then(|rest| {
+    let name = rest.split_whitespace().next()?;
+    aliases
+      .iter()
+      .find(|alias| alias.name.eq_ignore_ascii_case(name))
+  });
+  let Some(alias) = alias else {
+    if expansion.lines.len() == MAX_LINES {
+      return Err(Error::TooManyLines);
+    }
+    expansion.lines.push(text.trim().into());
+    return Ok(());
+  };
+
+  if expansion
+    .stack
+    .iter()
+    .any(|name| name.eq_ignore_ascii_case(&alias.name))
+  {
+    return Err(Error::Recursive(alias.name.clone()));
+  }
+  if expansion.stack.len() == MAX_DEPTH {
+    return Err(Error::TooDeep(alias.name.clone()));
+  }
+
+  let args = text.split_whitespace().skip(1).collect::<Vec<_>>();
+  expansion.stack.push(&alias.name);
+  // split before substituting, so that arguments can't add more commands
+  for part in alias.template.split(SEPARATOR) {
+    if expansion.steps == MAX_STEPS {
+      return Err(Error::TooManySteps);
+    }
+    expansion.steps += 1;
+    let line = substitute(part, &args, context)
+      .map_err(|n| Error::MissingArgument(alias.name.clone(), n))?;
+    if !line.trim().is_empty() {
+      expand_into(aliases, &line, context, expansion)?;
+    }
+  }
+  expansion.stack.pop();
+  Ok(())
+}
+
+/// Replaces the placeholders in `template`. Unknown placeholders are left alone. `{n+}` may be
+/// empty, but `{n}` is required, otherwise `n` is returned.
+fn substitute(template: &str, args: &[&str], context: &Context<'_>) -> Result<String, usize> {
+  let mut out = String::with_capacity(template.len());
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    out.push_str(&rest[..start]);
+    let Some(len) = rest[start..].find('}') else {
+      rest = &rest[start..];
+      break;
+    };
+    let placeholder = &rest[start + 1..start + len];
+    match placeholder {
+      "channel" | "streamer" => out.push_str(context.channel),
+      "user" => out.push_str(context.user),
+      _ => match parse_arg(placeholder) {
+        Some((n, true)) => out.push_str(&args.get(n..).unwrap_or_default().join(" ")),
+        Some((n, false)) => out.push_str(args.get(n).ok_or(n + 1)?),
+        None => out.push_str(&rest[start..=start + len]),
+      },
+    }
+    rest = &rest[start + len + 1..];
+  }
+  out.push_str(rest);
+  Ok(out)
+}
+
+/// `1` is `(0, false)` and `2+` is `(1, true)`.
+fn parse_arg(placeholder: &str) -> Option<(usize, bool)> {
+  let (digits, and_after) = match placeholder.strip_suffix('+') {
+    Some(digits) => (digits, true),
+    None => (placeholder, false),
+  };
+  let n = digits.parse::<usize>().ok()?;
+  Some((n.checked_sub(1)?, and_after))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+  /// The alias expands into itself.
+  Recursive(String),
+  TooDeep(String),
+  TooManyLines,
+  TooManySteps,
+  /// The alias and the number of the argument.
+  MissingArgument(String, usize),
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Recursive(name) => write!(f, "alias /{name} expands into itself"),
+      Error::TooDeep(name) => write!(f, "alias /{name} is nested too deeply"),
+      Error::TooManyLines => write!(f, "alias expands into more than {MAX_LINES} commands"),
+      Error::TooManySteps => write!(f, "alias expands into more than {MAX_STEPS} templates"),
+      Error::MissingArgument(name, n) => write!(f, "alias /{name} requires argument {{{n}}}"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CONTEXT: Context<'static> = Context {
+    channel: "forsen",
+    user: "me",
+  };
+
+  fn alias(name: &str, template: &str) -> Alias {
+    Alias {
+      name: name.into(),
+      template: template.into(),
+    }
+  }
+
+  #[test]
+  fn placeholders() {
+    let aliases = [
+      alias(
+        "warn",
+        "/timeout {1} 60 {2+} | @{1} please read the rules of {channel}",
+      ),
+      alias("hi", "hello from {user} {unknown} {0} {"),
+      alias("w", "/WARN {1} spam"),
+    ];
+    assert_eq!(
+      expand(&aliases, "/warn someone being rude", &CONTEXT).unwrap(),
+      [
+        "/timeout someone 60 being rude",
+        "@someone please read the rules of forsen"
+      ]
+    );
+    assert_eq!(
+      expand(&aliases, "/HI", &CONTEXT).unwrap(),
+      ["hello from me {unknown} {0} {"]
+    );
+    assert_eq!(
+      expand(&aliases, "/w x", &CONTEXT).unwrap(),
+      ["/timeout x 60 spam", "@x please read the rules of forsen"]
+    );
+    // arguments can't add commands
+    assert_eq!(
+      expand(&aliases, "/warn a | /ban b", &CONTEXT).unwrap()[0],
+      "/timeout a 60 | /ban b"
+    );
+    assert_eq!(
+      expand(&aliases, "/warn", &CONTEXT),
+      Err(Error::MissingArgument("warn".into(), 1))
+    );
+    assert_eq!(
+      expand(&aliases, "just text", &CONTEXT).unwrap(),
+      ["just text"]
+    );
+  }
+
+  #[test]
+  fn limits() {
+    let aliases = [alias("a", "/b"), alias("b", "/a")];
+    assert_eq!(
+      expand(&aliases, "/a", &CONTEXT),
+      Err(Error::Recursive("a".into()))
+    );
+
+    let aliases = (0..10)
+      .map(|i| alias(&format!("a{i}"), &format!("/a{}", i + 1)))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      expand(&aliases, "/a0", &CONTEXT),
+      Err(Error::TooDeep("a8".into()))
+    );
+
+    let aliases = [alias("spam", &["x"; 40].join(SEPARATOR))];
+    assert_eq!(
+      expand(&aliases, "/spam", &CONTEXT),
+      Err(Error::TooManyLines)
+    );
+  }
+
+  #[test]
+  fn fan_out() {
+    // each level expands into the next one 8 times, and the last one into nothing
+    let mut aliases = (0..7)
+      .map(|i| {
+        alias(
+          &format!("a{i}"),
+          &vec![format!("/a{}", i + 1); 8].join(SEPARATOR),
+        )
+      })
+      .collect::<Vec<_>>();
+    aliases.push(alias("a7", "{1+}"));
+    assert_eq!(expand(&aliases, "/a0", &CONTEXT), Err(Error::TooManySteps));
+  }
+}
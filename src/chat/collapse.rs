@@ -0,0 +1,325 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::history::{ChannelHistory, Entry, Message};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollapseConfig {
+  pub enabled: bool,
+  /// Repeats are only merged while they are at most this many seconds after the first message.
+  pub window_secs: u64,
+}
+
+impl Default for CollapseConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      window_secs: 30,
+    }
+  }
+}
+
+/// A line in the chat view: a message, and the repeats of it which were merged into the line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+  pub seq: u64,
+  /// Sequence numbers of the repeats, oldest first.
+  pub repeats: Vec<u64>,
+}
+
+impl Row {
+  fn single(seq: u64) -> Self {
+    Self {
+      seq,
+      repeats: Vec::new(),
+    }
+  }
+
+  /// The number of messages in the row, including the first one.
+  pub fn count(&self) -> usize {
+    1 + self.repeats.len()
+  }
+
+  pub fn contains(&self, seq: u64) -> bool {
+    self.seq == seq || self.repeats.contains(&seq)
+  }
+}
+
+/// The row which repeats can still be merged into.
+struct Open {
+  key: String,
+  since: DateTime<Utc>,
+  logins: HashSet<String>,
+}
+
+/// Groups the messages of a channel into rows, merging consecutive near-identical messages from
+/// different users during raids and copypasta waves. Rows are kept up to date incrementally as
+/// messages are added to the history.
+pub struct Collapser {
+  /// `None` if collapsing is disabled, then every message gets its own row.
+  window: Option<Duration>,
+  rows: VecDeque<Row>,
+  /// The last message added to `rows` and its timestamp, to notice when the history was
+  /// replaced.
+  last: Option<(u64, DateTime<Utc>)>,
+  /// The oldest message in the history during the last `update`, to notice when historical
+  /// messages were added in front of it.
+  front: Option<u64>,
+  open: Option<Open>,
+}
+
+impl Collapser {
+  pub fn new(config: &CollapseConfig) -> Self {
+    Self {
+      window: config
+        .enabled
+        .then(|| Duration::from_secs(config.window_secs)),
+      rows: VecDeque::new(),
+      last: None,
+      front: None,
+      open: None,
+    }
+  }
+
+  /// Oldest first. Rows may refer to messages which were evicted since the last `update`.
+  pub fn rows(&self) -> &VecDeque<Row> {
+    &self.rows
+  }
+
+  /// Adds the messages which were pushed to `history` since the last call.
+  pub fn update(&mut self, history: &ChannelHistory) {
+    let front = history.iter().next().map(|entry| entry.seq);
+    let unchanged = self.last.iter().all(|(seq, timestamp)| {
+      history
+        .get(*seq)
+        .is_some_and(|entry| entry.message.timestamp() == *timestamp)
+    });
+    let prepended = self.front.zip(front).is_some_and(|(old, new)| new < old);
+    if !unchanged || prepended {
+      self.rows.clear();
+      self.last = None;
+      self.open = None;
+    }
+    self.front = front;
+
+    let Some(front) = front else {
+      return;
+    };
+    // rows whose first message was evicted move on to their oldest repeat which is left
+    while let Some(row) = self.rows.front_mut().filter(|row| row.seq < front) {
+      row.repeats.retain(|seq| *seq >= front);
+      if row.repeats.is_empty() {
+        self.rows.pop_front();
+      } else {
+        row.seq = row.repeats.remove(0);
+        break;
+      }
+    }
+    let next = self
+      .last
+      .map(|(seq, _)| seq + 1)
+      .unwrap_or(front)
+      .max(front);
+    for seq in next.. {
+      let Some(entry) = history.get(seq) else {
+        break;
+      };
+      self.push(entry);
+    }
+  }
+
+  fn push(&mut self, entry: &Entry) {
+    let timestamp = entry.message.timestamp();
+    self.last = Some((entry.seq, timestamp));
+
+    // highlighted messages always get their own row, so that they can't be missed
+    let mergeable = self
+      .window
+      .zip(key(&entry.message))
+      .zip(entry.message.login());
+    let (Some(((window, key), login)), None) = (mergeable, entry.highlight) else {
+      self.open = None;
+      self.rows.push_back(Row::single(entry.seq));
+      return;
+    };
+
+    if let (Some(open), Some(row)) = (&mut self.open, self.rows.back_mut()) {
+      let within = (timestamp - open.since)
+        .to_std()
+        .is_ok_and(|elapsed| elapsed <= window);
+      if open.key == key && within && open.logins.insert(login.into()) {
+        row.repeats.push(entry.seq);
+        return;
+      }
+    }
+    self.open = Some(Open {
+      key,
+      since: timestamp,
+      logins: HashSet::from([login.into()]),
+    });
+    self.rows.push_back(Row::single(entry.seq));
+  }
+}
+
+/// What near-identical chat messages have in common: their lowercase words without punctuation,
+/// with repeated words counted once. For example, `LUL LUL lul!` and `lul` are both `lul`.
+pub fn key(message: &Message) -> Option<String> {
+  let Message::Chat(m) = message else {
+    return None;
+  };
+  let mut words = m
+    .text
+    .split_whitespace()
+    .map(|word| {
+      word
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+    })
+    .filter(|word| !word.is_empty())
+    .collect::<Vec<_>>();
+  words.dedup();
+  match words.is_empty() {
+    // only punctuation, e.g. `???`
+    true => Some(m.text.trim().to_lowercase()).filter(|text| !text.is_empty()),
+    false => Some(words.join(" ")),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::event::ChatEvent;
+  use super::super::highlight::{Highlighter, Matcher, Rule};
+  use super::super::history::History;
+  use super::super::tags::Color;
+  use super::*;
+
+  fn chat(login: &str, text: &str, timestamp_secs: u64) -> Message {
+    let line = format!(
+      "@badges=;color=;display-name={login};emotes=;id={login}{timestamp_secs};room-id=1;tmi-sent-ts={timestamp_secs}000;user-id={login} :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #forsen :{text}"
+    );
+    match ChatEvent::parse(&twitch::parse(line).unwrap()).unwrap() {
+      ChatEvent::PrivMsg(m) => Message::Chat(m),
+      _ => panic!("expected a PRIVMSG"),
+    }
+  }
+
+  fn collapser() -> Collapser {
+    Collapser::new(&CollapseConfig {
+      enabled: true,
+      window_secs: 30,
+    })
+  }
+
+  /// Pushes `messages` to a new history and collapses it.
+  fn rows(cap: usize, messages: &[(&str, &str, u64)]) -> Vec<Vec<u64>> {
+    let mut history = ChannelHistory::new(cap);
+    let mut collapser = collapser();
+    for (login, text, timestamp) in messages {
+      history.push(chat(login, text, *timestamp));
+      collapser.update(&history);
+    }
+    seqs(&collapser)
+  }
+
+  /// Each row as the sequence numbers it contains, relative to the first live message.
+  fn seqs(collapser: &Collapser) -> Vec<Vec<u64>> {
+    let first = 1 << 32;
+    collapser
+      .rows()
+      .iter()
+      .map(|row| {
+        [row.seq]
+          .iter()
+          .chain(&row.repeats)
+          .map(|seq| seq - first)
+          .collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn normalises_keys() {
+    let key = |text: &str| super::key(&chat("a", text, 1));
+    assert_eq!(key("LUL LUL lul!").as_deref(), Some("lul"));
+    assert_eq!(key("lul"), key("LUL LUL lul!"));
+    assert_eq!(key("  Hello,   World  ").as_deref(), Some("hello world"));
+    // only consecutive repeats count once
+    assert_eq!(key("a b a").as_deref(), Some("a b a"));
+    assert_eq!(key("ÄÖ äö").as_deref(), Some("äö"));
+    assert_eq!(key("???").as_deref(), Some("???"));
+    assert_eq!(key(" ?!? ").as_deref(), Some("?!?"));
+    assert_eq!(super::key(&Message::system("hi")), None);
+  }
+
+  #[test]
+  fn merges_within_the_window() {
+    let rows = rows(
+      10,
+      &[
+        ("a", "LUL", 100),
+        ("b", "lul!", 110),
+        // the same user again starts a new row
+        ("b", "LUL", 120),
+        ("c", "LUL", 150),
+        // more than 30 seconds after the first message of the row
+        ("d", "LUL", 151),
+        ("e", "other", 152),
+        ("f", "LUL", 153),
+      ],
+    );
+    assert_eq!(rows, [vec![0, 1], vec![2, 3], vec![4], vec![5], vec![6]]);
+
+    let mut history = ChannelHistory::new(10);
+    let mut disabled = Collapser::new(&CollapseConfig::default());
+    history.push(chat("a", "LUL", 100));
+    history.push(chat("b", "LUL", 101));
+    disabled.update(&history);
+    assert_eq!(seqs(&disabled), [vec![0], vec![1]]);
+  }
+
+  #[test]
+  fn keeps_highlights_apart() {
+    let mut history = History::new(10);
+    let rule = Rule::new(
+      Matcher::User { login: "b".into() },
+      Color { r: 255, g: 0, b: 0 },
+    );
+    history.set_highlighter(Highlighter::new(&[rule]));
+    history.push("forsen", chat("a", "LUL", 100));
+    history.push("forsen", chat("b", "LUL", 101));
+    history.push("forsen", chat("c", "LUL", 102));
+    history.push("forsen", chat("d", "LUL", 103));
+    let mut collapser = collapser();
+    collapser.update(history.channel("forsen").unwrap());
+    assert_eq!(seqs(&collapser), [vec![0], vec![1], vec![2, 3]]);
+  }
+
+  #[test]
+  fn moves_evicted_rows() {
+    let mut history = ChannelHistory::new(3);
+    let mut collapser = collapser();
+    for (login, text, timestamp) in [("a", "LUL", 100), ("b", "LUL", 101), ("c", "LUL", 102)] {
+      history.push(chat(login, text, timestamp));
+    }
+    collapser.update(&history);
+    assert_eq!(seqs(&collapser), [vec![0, 1, 2]]);
+
+    // the row moves to its oldest repeat which is left, and repeats can still be merged into it
+    history.push(chat("d", "LUL", 103));
+    collapser.update(&history);
+    assert_eq!(seqs(&collapser), [vec![1, 2, 3]]);
+    assert_eq!(collapser.rows()[0].count(), 3);
+
+    history.push(chat("e", "other", 104));
+    history.push(chat("f", "other", 105));
+    history.push(chat("g", "LUL", 106));
+    collapser.update(&history);
+    assert_eq!(seqs(&collapser), [vec![4, 5], vec![6]]);
+  }
+}
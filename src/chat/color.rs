@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use super::event::Author;
+use super::tags::Color;
+
+/// WCAG AA for normal text.
+pub const MIN_CONTRAST: f32 = 4.5;
+
+/// Twitch assigns one of these to users who never picked a color.
+const DEFAULT_COLORS: [Color; 15] = [
+  rgb(0xFF, 0x00, 0x00), // Red
+  rgb(0x00, 0x00, 0xFF), // Blue
+  rgb(0x00, 0x80, 0x00), // Green
+  rgb(0xB2, 0x22, 0x22), // FireBrick
+  rgb(0xFF, 0x7F, 0x50), // Coral
+  rgb(0x9A, 0xCD, 0x32), // YellowGreen
+  rgb(0xFF, 0x45, 0x00), // OrangeRed
+  rgb(0x2E, 0x8B, 0x57), // SeaGreen
+  rgb(0xDA, 0xA5, 0x20), // GoldenRod
+  rgb(0xD2, 0x69, 0x1E), // Chocolate
+  rgb(0x5F, 0x9E, 0xA0), // CadetBlue
+  rgb(0x1E, 0x90, 0xFF), // DodgerBlue
+  rgb(0xFF, 0x69, 0xB4), // HotPink
+  rgb(0x8A, 0x2B, 0xE2), // BlueViolet
+  rgb(0x00, 0xFF, 0x7F), // SpringGreen
+];
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+  Color { r, g, b }
+}
+
+/// The color Twitch shows for `login` if the user has no `color` tag, picked from the first and
+/// last character of the login like the web client does.
+pub fn default_color(login: &str) -> Color {
+  let login = login.as_bytes();
+  let (Some(first), Some(last)) = (login.first(), login.last()) else {
+    return DEFAULT_COLORS[0];
+  };
+  DEFAULT_COLORS[(*first as usize + *last as usize) % DEFAULT_COLORS.len()]
+}
+
+/// Usernames adjusted to be readable, cached by color and background since they are needed for
+/// every visible line on every frame.
+#[derive(Default)]
+pub struct UsernameColors {
+  colors: HashMap<(Color, Color), Color>,
+}
+
+impl UsernameColors {
+  /// Users can pick any color, so the cache is cleared once it holds this many.
+  const CAP: usize = 4096;
+
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The author's color, or their default one, adjusted to be readable on `background`.
+  pub fn get(&mut self, author: &Author, background: Color) -> Color {
+    let color = author.color.unwrap_or_else(|| default_color(&author.login));
+    if let Some(readable) = self.colors.get(&(color, background)) {
+      return *readable;
+    }
+    if self.colors.len() >= Self::CAP {
+      self.colors.clear();
+    }
+    let readable = readable(color, background, MIN_CONTRAST);
+    self.colors.insert((color, background), readable);
+    readable
+  }
+}
+
+/// WCAG relative luminance.
+pub fn luminance(color: Color) -> f32 {
+  let linear = |c: u8| {
+    let c = c as f32 / 255.0;
+    if c <= 0.03928 {
+      c / 12.92
+    } else {
+      ((c + 0.055) / 1.055).powf(2.4)
+    }
+  };
+  0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
+
+pub fn contrast_ratio(a: Color, b: Color) -> f32 {
+  let (a, b) = (luminance(a), luminance(b));
+  let (light, dark) = if a > b { (a, b) } else { (b, a) };
+  (light + 0.05) / (dark + 0.05)
+}
+
+/// Lightens `color` on dark backgrounds, or darkens it on light ones, just enough to reach
+/// `min_ratio`. Hue and saturation are kept.
+pub fn readable(color: Color, background: Color, min_ratio: f32) -> Color {
+  if contrast_ratio(color, background) >= min_ratio {
+    return color;
+  }
+
+  let (h, s, l) = to_hsl(color);
+  let dark_background = luminance(background) < 0.5;
+  let target = if dark_background { 1.0 } else { 0.0 };
+  if contrast_ratio(from_hsl(h, s, target), background) < min_ratio {
+    return from_hsl(h, s, target);
+  }
+
+  // the lightness between `l` and `target` closest to `l` which is still readable
+  let (mut lo, mut hi) = (l, target);
+  for _ in 0..16 {
+    let mid = (lo + hi) / 2.0;
+    if contrast_ratio(from_hsl(h, s, mid), background) >= min_ratio {
+      hi = mid;
+    } else {
+      lo = mid;
+    }
+  }
+  from_hsl(h, s, hi)
+}
+
+fn to_hsl(color: Color) -> (f32, f32, f32) {
+  let (r, g, b) = (
+    color.r as f32 / 255.0,
+    color.g as f32 / 255.0,
+    color.b as f32 / 255.0,
+  );
+  let max = r.max(g).max(b);
+  let min = r.min(g).min(b);
+  let l = (max + min) / 2.0;
+  let d = max - min;
+  if d == 0.0 {
+    return (0.0, 0.0, l);
+  }
+
+  let s = d / (1.0 - (2.0 * l - 1.0).abs());
+  let h = if max == r {
+    ((g - b) / d).rem_euclid(6.0)
+  } else if max == g {
+    (b - r) / d + 2.0
+  } else {
+    (r - g) / d + 4.0
+  };
+  (h * 60.0, s, l)
+}
+
+fn from_hsl(h: f32, s: f32, l: f32) -> Color {
+  let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+  let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+  let m = l - c / 2.0;
+  let (r, g, b) = match h as u32 {
+    0..=59 => (c, x, 0.0),
+    60..=119 => (x, c, 0.0),
+    120..=179 => (0.0, c, x),
+    180..=239 => (0.0, x, c),
+    240..=299 => (x, 0.0, c),
+    _ => (c, 0.0, x),
+  };
+  let channel = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+  rgb(channel(r), channel(g), channel(b))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DARK: Color = rgb(0x1B, 0x1B, 0x1B);
+  const LIGHT: Color = rgb(0xF8, 0xF8, 0xF8);
+
+  fn hue(color: Color) -> f32 {
+    to_hsl(color).0
+  }
+
+  #[test]
+  fn contrast() {
+    assert!((contrast_ratio(rgb(0, 0, 0), rgb(255, 255, 255)) - 21.0).abs() < 1e-4);
+    assert_eq!(contrast_ratio(DARK, DARK), 1.0);
+    assert_eq!(contrast_ratio(DARK, LIGHT), contrast_ratio(LIGHT, DARK));
+  }
+
+  #[test]
+  fn dark_background() {
+    let blue = rgb(0x00, 0x00, 0xFF);
+    let adjusted = readable(blue, DARK, MIN_CONTRAST);
+    assert!(contrast_ratio(adjusted, DARK) >= MIN_CONTRAST);
+    // just enough, and the same hue
+    assert!(contrast_ratio(adjusted, DARK) < MIN_CONTRAST + 0.2);
+    assert!(luminance(adjusted) > luminance(blue));
+    assert!((hue(adjusted) - hue(blue)).abs() < 2.0);
+
+    let coral = rgb(0xFF, 0x7F, 0x50);
+    assert_eq!(readable(coral, DARK, MIN_CONTRAST), coral);
+    // grey stays grey
+    let grey = readable(DARK, DARK, MIN_CONTRAST);
+    assert!(grey.r == grey.g && grey.g == grey.b);
+    assert!(contrast_ratio(grey, DARK) >= MIN_CONTRAST);
+  }
+
+  #[test]
+  fn light_background() {
+    let yellow = rgb(0xFF, 0xFF, 0x00);
+    let adjusted = readable(yellow, LIGHT, MIN_CONTRAST);
+    assert!(contrast_ratio(adjusted, LIGHT) >= MIN_CONTRAST);
+    assert!(luminance(adjusted) < luminance(yellow));
+    assert!((hue(adjusted) - hue(yellow)).abs() < 2.0);
+
+    let blue = rgb(0x00, 0x00, 0xFF);
+    assert_eq!(readable(blue, LIGHT, MIN_CONTRAST), blue);
+    // unreachable ratios give the darkest color
+    assert_eq!(readable(yellow, LIGHT, 30.0), rgb(0, 0, 0));
+  }
+
+  #[test]
+  fn hsl_round_trip() {
+    for color in DEFAULT_COLORS {
+      let (h, s, l) = to_hsl(color);
+      assert_eq!(from_hsl(h, s, l), color);
+    }
+  }
+
+  #[test]
+  fn usernames() {
+    assert_eq!(default_color(""), DEFAULT_COLORS[0]);
+    assert_eq!(default_color("a"), rgb(0x00, 0xFF, 0x7F));
+
+    let mut author = Author {
+      id: "1".into(),
+      login: "a".into(),
+      name: "A".into(),
+      color: None,
+      badges: Vec::new(),
+      badge_info: Vec::new(),
+    };
+    let mut colors = UsernameColors::new();
+    let spring_green = default_color("a");
+    assert_eq!(colors.get(&author, DARK), spring_green);
+    assert_eq!(
+      colors.get(&author, LIGHT),
+      readable(spring_green, LIGHT, MIN_CONTRAST)
+    );
+    author.color = Some(rgb(0x00, 0x00, 0xFF));
+    assert_eq!(
+      colors.get(&author, DARK),
+      readable(rgb(0x00, 0x00, 0xFF), DARK, MIN_CONTRAST)
+    );
+    assert_eq!(colors.colors.len(), 3);
+  }
+}
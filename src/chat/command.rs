@@ -0,0 +1,502 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::tags::Color;
+
+/// Every command recognized by `parse`, for completion.
+pub const NAMES: &[&str] = &[
+  "/announce",
+  "/announceblue",
+  "/announcegreen",
+  "/announceorange",
+  "/announcepurple",
+  "/ban",
+  "/clear",
+  "/color",
+  "/delete",
+  "/emoteonly",
+  "/emoteonlyoff",
+  "/followers",
+  "/followersoff",
+  "/join",
+  "/me",
+  "/mod",
+  "/part",
+  "/raid",
+  "/slow",
+  "/slowoff",
+  "/subscribers",
+  "/subscribersoff",
+  "/timeout",
+  "/unban",
+  "/uniquechat",
+  "/uniquechatoff",
+  "/unmod",
+  "/unraid",
+  "/untimeout",
+  "/unvip",
+  "/vip",
+];
+
+/// Longest timeout Twitch allows, two weeks.
+pub const MAX_TIMEOUT: u32 = 1_209_600;
+/// Longest follow age for followers-only mode, three months in minutes.
+pub const MAX_FOLLOWERS: u32 = 129_600;
+
+/// What the user typed into the input box.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+  /// Sent to chat as-is, including `/me`.
+  Message(String),
+  Command(Command),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+  /// Clears the local view of the channel.
+  Clear,
+  Join(String),
+  /// Leaves the given channel, or the current one.
+  Part(Option<String>),
+
+  Ban {
+    user: String,
+    reason: String,
+  },
+  Unban {
+    user: String,
+  },
+  Timeout {
+    user: String,
+    seconds: u32,
+    reason: String,
+  },
+  Untimeout {
+    user: String,
+  },
+  /// Deletes a single message.
+  Delete {
+    msg_id: String,
+  },
+  Slow(Option<u32>),
+  /// Minimum follow age in minutes, `None` to turn followers-only mode off.
+  Followers(Option<u32>),
+  Subscribers(bool),
+  EmoteOnly(bool),
+  UniqueChat(bool),
+  /// A named color like `blue_violet`, or `#RRGGBB`.
+  Color(String),
+  Announce {
+    color: AnnouncementColor,
+    message: String,
+  },
+  Raid {
+    user: String,
+  },
+  Unraid,
+  Mod {
+    user: String,
+  },
+  Unmod {
+    user: String,
+  },
+  Vip {
+    user: String,
+  },
+  Unvip {
+    user: String,
+  },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnouncementColor {
+  Primary,
+  Blue,
+  Green,
+  Orange,
+  Purple,
+}
+
+impl AnnouncementColor {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AnnouncementColor::Primary => "primary",
+      AnnouncementColor::Blue => "blue",
+      AnnouncementColor::Green => "green",
+      AnnouncementColor::Orange => "orange",
+      AnnouncementColor::Purple => "purple",
+    }
+  }
+}
+
+/// A button next to each message in channels we moderate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuickAction {
+  Delete,
+  Timeout { seconds: u32 },
+  Ban,
+}
+
+impl QuickAction {
+  pub fn defaults() -> Vec<QuickAction> {
+    vec![
+      QuickAction::Delete,
+      QuickAction::Timeout { seconds: 60 },
+      QuickAction::Timeout { seconds: 600 },
+      QuickAction::Ban,
+    ]
+  }
+
+  pub fn label(&self) -> String {
+    match self {
+      QuickAction::Delete => "🗑".into(),
+      QuickAction::Timeout { seconds } => format_duration(*seconds),
+      QuickAction::Ban => "🚫".into(),
+    }
+  }
+
+  pub fn description(&self) -> String {
+    match self {
+      QuickAction::Delete => "Delete message".into(),
+      QuickAction::Timeout { seconds } => format!("Timeout for {}", format_duration(*seconds)),
+      QuickAction::Ban => "Ban".into(),
+    }
+  }
+
+  /// The command which performs this action on the message `msg_id` from `login`.
+  pub fn command(&self, login: &str, msg_id: &str) -> Command {
+    match self {
+      QuickAction::Delete => Command::Delete {
+        msg_id: msg_id.into(),
+      },
+      QuickAction::Timeout { seconds } => Command::Timeout {
+        user: login.into(),
+        seconds: *seconds,
+        reason: String::new(),
+      },
+      QuickAction::Ban => Command::Ban {
+        user: login.into(),
+        reason: String::new(),
+      },
+    }
+  }
+}
+
+/// Formats seconds in the largest unit which divides them, e.g. `10m` or `90s`.
+pub fn format_duration(seconds: u32) -> String {
+  const UNITS: [(u32, &str); 4] = [
+    (7 * 24 * 60 * 60, "w"),
+    (24 * 60 * 60, "d"),
+    (60 * 60, "h"),
+    (60, "m"),
+  ];
+  for (unit, suffix) in UNITS {
+    if seconds >= unit && seconds / unit * unit == seconds {
+      return format!("{}{suffix}", seconds / unit);
+    }
+  }
+  format!("{seconds}s")
+}
+
+/// Colors anyone can pick, everything else requires Turbo or Prime.
+const NAMED_COLORS: &[&str] = &[
+  "blue",
+  "blue_violet",
+  "cadet_blue",
+  "chocolate",
+  "coral",
+  "dodger_blue",
+  "firebrick",
+  "golden_rod",
+  "green",
+  "hot_pink",
+  "orange_red",
+  "red",
+  "sea_green",
+  "spring_green",
+  "yellow_green",
+];
+
+/// Parses a line from the input box. Anything which doesn't start with `/` is a message.
+pub fn parse(text: &str) -> Result<Input, Error> {
+  let text = text.trim();
+  let Some(rest) = text.strip_prefix('/') else {
+    return Ok(Input::Message(text.into()));
+  };
+  let (name, args) = match rest.split_once(char::is_whitespace) {
+    Some((name, args)) => (name, args.trim()),
+    None => (rest, ""),
+  };
+  let mut words = args.split_whitespace();
+
+  let command = match name.to_lowercase().as_str() {
+    "me" => return Ok(Input::Message(text.into())),
+    "clear" => Command::Clear,
+    "join" => Command::Join(channel(words.next()).ok_or(Error::Usage("/join <channel>"))?),
+    "part" => Command::Part(channel(words.next())),
+
+    "ban" => {
+      let user = user(words.next()).ok_or(Error::Usage("/ban <user> [reason]"))?;
+      Command::Ban {
+        user,
+        reason: rest_of(args, 1),
+      }
+    }
+    "unban" => Command::Unban {
+      user: user(words.next()).ok_or(Error::Usage("/unban <user>"))?,
+    },
+    "timeout" => {
+      let usage = Error::Usage("/timeout <user> [duration] [reason]");
+      let user = user(words.next()).ok_or(usage)?;
+      // the duration is optional, so the second word may already be the reason
+      match words.next().map(|word| (word, parse_duration(word, 1))) {
+        Some((_, Some(seconds))) => {
+          if seconds == 0 || seconds > MAX_TIMEOUT {
+            return Err(Error::OutOfRange("timeout", "1s", "2w"));
+          }
+          Command::Timeout {
+            user,
+            seconds,
+            reason: rest_of(args, 2),
+          }
+        }
+        _ => Command::Timeout {
+          user,
+          seconds: 600,
+          reason: rest_of(args, 1),
+        },
+      }
+    }
+    "untimeout" => Command::Untimeout {
+      user: user(words.next()).ok_or(Error::Usage("/untimeout <user>"))?,
+    },
+    "delete" => Command::Delete {
+      msg_id: words
+        .next()
+        .ok_or(Error::Usage("/delete <message id>"))?
+        .into(),
+    },
+    "slow" => {
+      let seconds = match words.next() {
+        Some(word) => parse_duration(word, 1).ok_or(Error::InvalidDuration(word.into()))?,
+        None => 30,
+      };
+      if !(3..=120).contains(&seconds) {
+        return Err(Error::OutOfRange("slow mode", "3s", "120s"));
+      }
+      Command::Slow(Some(seconds))
+    }
+    "slowoff" => Command::Slow(None),
+    "followers" => {
+      // Twitch only takes whole minutes, so e.g. `30s` means at least one minute
+      let minutes = match words.next() {
+        Some(word) => parse_duration(word, 60)
+          .ok_or(Error::InvalidDuration(word.into()))?
+          .div_ceil(60),
+        None => 0,
+      };
+      if minutes > MAX_FOLLOWERS {
+        return Err(Error::OutOfRange("followers-only mode", "0m", "3 months"));
+      }
+      Command::Followers(Some(minutes))
+    }
+    "followersoff" => Command::Followers(None),
+    "subscribers" => Command::Subscribers(true),
+    "subscribersoff" => Command::Subscribers(false),
+    "emoteonly" => Command::EmoteOnly(true),
+    "emoteonlyoff" => Command::EmoteOnly(false),
+    "uniquechat" => Command::UniqueChat(true),
+    "uniquechatoff" => Command::UniqueChat(false),
+    "color" => {
+      let color = words.next().ok_or(Error::Usage("/color <color>"))?;
+      Command::Color(parse_color(color).ok_or_else(|| Error::InvalidColor(color.into()))?)
+    }
+    "announce" | "announceblue" | "announcegreen" | "announceorange" | "announcepurple" => {
+      let color = match &name.to_lowercase()["announce".len()..] {
+        "blue" => AnnouncementColor::Blue,
+        "green" => AnnouncementColor::Green,
+        "orange" => AnnouncementColor::Orange,
+        "purple" => AnnouncementColor::Purple,
+        _ => AnnouncementColor::Primary,
+      };
+      if args.is_empty() {
+        return Err(Error::Usage("/announce <message>"));
+      }
+      Command::Announce {
+        color,
+        message: args.into(),
+      }
+    }
+    "raid" => Command::Raid {
+      user: user(words.next()).ok_or(Error::Usage("/raid <channel>"))?,
+    },
+    "unraid" => Command::Unraid,
+    "mod" => Command::Mod {
+      user: user(words.next()).ok_or(Error::Usage("/mod <user>"))?,
+    },
+    "unmod" => Command::Unmod {
+      user: user(words.next()).ok_or(Error::Usage("/unmod <user>"))?,
+    },
+    "vip" => Command::Vip {
+      user: user(words.next()).ok_or(Error::Usage("/vip <user>"))?,
+    },
+    "unvip" => Command::Unvip {
+      user: user(words.next()).ok_or(Error::Usage("/unvip <user>"))?,
+    },
+    _ => return Err(Error::Unknown(name.into())),
+  };
+  Ok(Input::Command(command))
+}
+
+fn user(word: Option<&str>) -> Option<String> {
+  let user = word?.trim_start_matches('@');
+  (!user.is_empty()).then(|| user.to_lowercase())
+}
+
+fn channel(word: Option<&str>) -> Option<String> {
+  let channel = word?.trim_start_matches('#');
+  (!channel.is_empty()).then(|| channel.to_lowercase())
+}
+
+/// `args` without its first `n` words.
+fn rest_of(args: &str, n: usize) -> String {
+  let mut rest = args;
+  for _ in 0..n {
+    rest = match rest.split_once(char::is_whitespace) {
+      Some((_, rest)) => rest.trim_start(),
+      None => "",
+    };
+  }
+  rest.into()
+}
+
+/// Parses durations like `90`, `10m` or `1h30m` into seconds. A number without a unit is
+/// multiplied by `unit`.
+pub fn parse_duration(s: &str, unit: u32) -> Option<u32> {
+  if let Ok(n) = s.parse::<u32>() {
+    return n.checked_mul(unit);
+  }
+
+  let mut total = 0u32;
+  let mut number = String::new();
+  for c in s.chars() {
+    if c.is_ascii_digit() {
+      number.push(c);
+      continue;
+    }
+    let multiplier = match c.to_ascii_lowercase() {
+      's' => 1,
+      'm' => 60,
+      'h' => 60 * 60,
+      'd' => 24 * 60 * 60,
+      'w' => 7 * 24 * 60 * 60,
+      _ => return None,
+    };
+    let n = std::mem::take(&mut number).parse::<u32>().ok()?;
+    total = total.checked_add(n.checked_mul(multiplier)?)?;
+  }
+  number.is_empty().then_some(total)
+}
+
+/// Normalizes `BlueViolet`, `blue_violet` and `#8A2BE2` to what Helix expects.
+fn parse_color(s: &str) -> Option<String> {
+  if s.starts_with('#') {
+    return s.parse::<Color>().ok().map(|color| color.to_string());
+  }
+  let normalized = s.to_lowercase().replace('_', "");
+  NAMED_COLORS
+    .iter()
+    .find(|name| name.replace('_', "") == normalized)
+    .map(|name| name.to_string())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+  Unknown(String),
+  Usage(&'static str),
+  InvalidDuration(String),
+  InvalidColor(String),
+  /// The setting, and the minimum and maximum values.
+  OutOfRange(&'static str, &'static str, &'static str),
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Unknown(name) => write!(f, "unknown command: /{name}"),
+      Error::Usage(usage) => write!(f, "usage: {usage}"),
+      Error::InvalidDuration(s) => write!(f, "invalid duration: {s}"),
+      Error::InvalidColor(s) => write!(
+        f,
+        "invalid color: {s}, expected #RRGGBB or one of {}",
+        NAMED_COLORS.join(", ")
+      ),
+      Error::OutOfRange(setting, min, max) => {
+        write!(f, "{setting} must be between {min} and {max}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn command(text: &str) -> Result<Command, Error> {
+    match parse(text)? {
+      Input::Command(command) => Ok(command),
+      Input::Message(message) => panic!("not a command: {message}"),
+    }
+  }
+
+  #[test]
+  fn durations() {
+    assert_eq!(parse_duration("90", 1), Some(90));
+    assert_eq!(parse_duration("10", 60), Some(600));
+    assert_eq!(parse_duration("1h30m", 1), Some(5400));
+    assert_eq!(parse_duration("2W", 1), Some(MAX_TIMEOUT));
+    assert_eq!(parse_duration("10x", 1), None);
+    assert_eq!(parse_duration("m", 1), None);
+    assert_eq!(parse_duration("10m5", 1), None);
+    assert_eq!(parse_duration("99999999999", 1), None);
+  }
+
+  #[test]
+  fn followers() {
+    assert_eq!(command("/followers").unwrap(), Command::Followers(Some(0)));
+    assert_eq!(
+      command("/followers 10").unwrap(),
+      Command::Followers(Some(10))
+    );
+    assert_eq!(
+      command("/followers 1h").unwrap(),
+      Command::Followers(Some(60))
+    );
+    // rounded up to whole minutes
+    assert_eq!(
+      command("/followers 30s").unwrap(),
+      Command::Followers(Some(1))
+    );
+    assert_eq!(
+      command("/followers 90s").unwrap(),
+      Command::Followers(Some(2))
+    );
+    assert_eq!(
+      command("/followers 0s").unwrap(),
+      Command::Followers(Some(0))
+    );
+    assert!(matches!(
+      command("/followers 13w"),
+      Err(Error::OutOfRange(..))
+    ));
+    assert!(matches!(
+      command("/followers soon"),
+      Err(Error::InvalidDuration(_))
+    ));
+    assert_eq!(command("/followersoff").unwrap(), Command::Followers(None));
+  }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+
+use super::history::{ChannelHistory, Message};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+  User,
+  Emote,
+  Command,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+  /// Replaces the word being completed.
+  pub text: String,
+  pub kind: Kind,
+}
+
+/// Where candidates come from.
+#[derive(Default)]
+pub struct Sources<'a> {
+  /// Most recent first.
+  pub chatters: Vec<&'a str>,
+  pub emotes: Vec<&'a str>,
+  pub commands: &'a [&'a str],
+}
+
+/// Names of the users who spoke in `history`, most recent first, without duplicates.
+///
+/// The display name is used unless it differs from the login by more than case.
+pub fn recent_chatters(history: &ChannelHistory) -> Vec<&str> {
+  let mut seen = HashSet::new();
+  history
+    .iter()
+    .rev()
+    .filter_map(|entry| match &entry.message {
+      Message::Chat(m) => Some(&m.author),
+      _ => None,
+    })
+    .filter(|author| seen.insert(author.login.as_str()))
+    .map(
+      |author| match author.name.eq_ignore_ascii_case(&author.login) {
+        true => author.name.as_str(),
+        false => author.login.as_str(),
+      },
+    )
+    .collect()
+}
+
+/// The byte range of the word which ends at `cursor`.
+pub fn word_before(text: &str, cursor: usize) -> (usize, usize) {
+  let start = text[..cursor]
+    .char_indices()
+    .rev()
+    .find(|(_, c)| c.is_whitespace())
+    .map(|(i, c)| i + c.len_utf8())
+    .unwrap_or(0);
+  (start, cursor)
+}
+
+/// Candidates for `word`, best first. `first` is whether it is the first word of the input.
+///
+/// - `@name` completes chatters, in the order they spoke
+/// - `/command` completes commands, if it is the first word
+/// - anything else completes emotes, then chatters
+pub fn candidates(word: &str, first: bool, sources: &Sources<'_>) -> Vec<Candidate> {
+  if let Some(prefix) = word.strip_prefix('@') {
+    return matching(&sources.chatters, prefix)
+      .map(|name| Candidate {
+        text: format!("@{name}"),
+        kind: Kind::User,
+      })
+      .collect();
+  }
+
+  if word.starts_with('/') && first {
+    let mut commands = matching(sources.commands, word).collect::<Vec<_>>();
+    commands.sort_unstable();
+    return commands
+      .into_iter()
+      .map(|command| Candidate {
+        text: command.into(),
+        kind: Kind::Command,
+      })
+      .collect();
+  }
+
+  if word.is_empty() {
+    return Vec::new();
+  }
+
+  // exact-case matches first, then the rest, each sorted by name
+  let mut emotes = matching(&sources.emotes, word).collect::<Vec<_>>();
+  emotes.sort_unstable_by_key(|name| (!name.starts_with(word), name.to_lowercase()));
+  emotes.dedup();
+  let emotes = emotes.into_iter().map(|name| Candidate {
+    text: name.into(),
+    kind: Kind::Emote,
+  });
+  let chatters = matching(&sources.chatters, word).map(|name| Candidate {
+    text: name.into(),
+    kind: Kind::User,
+  });
+  emotes.chain(chatters).collect()
+}
+
+/// Case-insensitive prefix matches of `prefix` in `names`.
+fn matching<'a, 'b>(names: &'b [&'a str], prefix: &'b str) -> impl Iterator<Item = &'a str> + 'b {
+  names.iter().copied().filter(move |name| {
+    name.len() >= prefix.len()
+      && name.is_char_boundary(prefix.len())
+      && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+  })
+}
+
+/// An in-progress completion, cycled through by repeatedly pressing tab.
+#[derive(Clone, Debug)]
+pub struct Completion {
+  /// Byte offset of the completed word in the text.
+  start: usize,
+  /// Length of the text currently occupying the word's place.
+  len: usize,
+  /// Appended after the candidate, unless the word is already followed by whitespace.
+  suffix: &'static str,
+  candidates: Vec<Candidate>,
+  selected: Option<usize>,
+}
+
+impl Completion {
+  /// Completes the word before `cursor` in `text`, or returns `None` if nothing matches.
+  pub fn new(text: &str, cursor: usize, sources: &Sources<'_>) -> Option<Self> {
+    let (start, end) = word_before(text, cursor);
+    let first = text[..start].trim().is_empty();
+    let candidates = candidates(&text[start..end], first, sources);
+    if candidates.is_empty() {
+      return None;
+    }
+    let followed_by_space = text[end..].starts_with(char::is_whitespace);
+    Some(Self {
+      start,
+      len: end - start,
+      suffix: if followed_by_space { "" } else { " " },
+      candidates,
+      selected: None,
+    })
+  }
+
+  pub fn candidates(&self) -> &[Candidate] {
+    &self.candidates
+  }
+
+  pub fn selected(&self) -> Option<usize> {
+    self.selected
+  }
+
+  /// Byte offset of the end of the inserted candidate, or of the word if none was inserted yet.
+  pub fn end(&self) -> usize {
+    self.start + self.len
+  }
+
+  /// Inserts the next candidate into `text`, returning the new cursor position.
+  pub fn next(&mut self, text: &mut String) -> usize {
+    let index = match self.selected {
+      Some(i) => (i + 1) % self.candidates.len(),
+      None => 0,
+    };
+    self.select(index, text)
+  }
+
+  /// Inserts the previous candidate into `text`, returning the new cursor position.
+  pub fn prev(&mut self, text: &mut String) -> usize {
+    let index = match self.selected {
+      Some(0) | None => self.candidates.len() - 1,
+      Some(i) => i - 1,
+    };
+    self.select(index, text)
+  }
+
+  /// Inserts the candidate at `index` into `text`, returning the new cursor position.
+  pub fn select(&mut self, index: usize, text: &mut String) -> usize {
+    let replacement = format!("{}{}", self.candidates[index].text, self.suffix);
+    text.replace_range(self.start..self.start + self.len, &replacement);
+    self.len = replacement.len();
+    self.selected = Some(index);
+    self.end()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::event::ChatEvent;
+  use super::super::history::History;
+  use super::*;
+
+  const COMMANDS: &[&str] = &["/timeout", "/ban", "/me", "/mods", "/unban"];
+
+  fn sources<'a>(chatters: &[&'a str], emotes: &[&'a str]) -> Sources<'a> {
+    Sources {
+      chatters: chatters.to_vec(),
+      emotes: emotes.to_vec(),
+      commands: COMMANDS,
+    }
+  }
+
+  fn texts(candidates: &[Candidate]) -> Vec<&str> {
+    candidates.iter().map(|c| c.text.as_str()).collect()
+  }
+
+  #[test]
+  fn words() {
+    assert_eq!(word_before("", 0), (0, 0));
+    assert_eq!(word_before("hello", 5), (0, 5));
+    assert_eq!(word_before("hello wor", 9), (6, 9));
+    assert_eq!(word_before("hello wor", 3), (0, 3));
+    assert_eq!(word_before("hi ", 3), (3, 3));
+    assert_eq!(word_before("hé\u{3000}ab", 8), (6, 8));
+  }
+
+  #[test]
+  fn ranking() {
+    let sources = sources(
+      &["kappaman", "Kaiser", "xqc"],
+      &["kappa", "Kappa", "KappaPride", "Keepo"],
+    );
+    // exact case first, then by name, then chatters in the order they spoke
+    assert_eq!(
+      texts(&candidates("Ka", false, &sources)),
+      ["Kappa", "KappaPride", "kappa", "kappaman", "Kaiser"]
+    );
+    assert_eq!(candidates("Ka", false, &sources)[3].kind, Kind::User);
+    assert_eq!(
+      texts(&candidates("@k", false, &sources)),
+      ["@kappaman", "@Kaiser"]
+    );
+    assert_eq!(
+      texts(&candidates("@", false, &sources)),
+      ["@kappaman", "@Kaiser", "@xqc"]
+    );
+    assert!(candidates("", false, &sources).is_empty());
+    assert!(candidates("é", false, &sources).is_empty());
+  }
+
+  #[test]
+  fn commands() {
+    let sources = sources(&["mod"], &[]);
+    assert_eq!(texts(&candidates("/m", true, &sources)), ["/me", "/mods"]);
+    assert_eq!(candidates("/m", true, &sources)[0].kind, Kind::Command);
+    // only as the first word
+    assert!(candidates("/m", false, &sources).is_empty());
+    assert!(Completion::new("hi /b", 5, &sources).is_none());
+    let completion = Completion::new("  /b", 4, &sources).unwrap();
+    assert_eq!(texts(completion.candidates()), ["/ban"]);
+  }
+
+  #[test]
+  fn recent_chatters_first() {
+    let mut history = History::new(10);
+    for (login, name) in [("a", "A"), ("b", "비"), ("a", "A")] {
+      let line = format!(
+        "@badges=;display-name={name};emotes=;id={login};room-id=1;tmi-sent-ts=1;user-id=2 :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #c :hi"
+      );
+      history.handle(&ChatEvent::parse(&twitch::parse(line).unwrap()).unwrap());
+    }
+    // logins are used for names which differ by more than case
+    assert_eq!(recent_chatters(history.channel("c").unwrap()), ["A", "b"]);
+  }
+
+  #[test]
+  fn cycles() {
+    let sources = sources(&[], &["Kappa", "KappaPride", "Keepo"]);
+    let mut text = String::from("hi K there");
+    let mut completion = Completion::new(&text, 4, &sources).unwrap();
+    assert_eq!(completion.selected(), None);
+    assert_eq!(completion.end(), 4);
+
+    // already followed by a space, so none is added
+    assert_eq!(completion.next(&mut text), 8);
+    assert_eq!(text, "hi Kappa there");
+    completion.next(&mut text);
+    completion.next(&mut text);
+    assert_eq!(text, "hi Keepo there");
+    assert_eq!(completion.next(&mut text), 8);
+    assert_eq!(text, "hi Kappa there");
+    assert_eq!(completion.selected(), Some(0));
+    completion.prev(&mut text);
+    assert_eq!(text, "hi Keepo there");
+
+    let mut text = String::from("K");
+    let mut completion = Completion::new(&text, 1, &sources).unwrap();
+    completion.prev(&mut text);
+    assert_eq!(text, "Keepo ");
+    assert_eq!(completion.prev(&mut text), "KappaPride ".len());
+    assert_eq!(text, "KappaPride ");
+  }
+}
@@ -0,0 +1,706 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::sanitize::{sanitize, sanitize_with_emotes};
+use super::tags::{Badge, Color, Emote, Tags};
+
+#[derive(Clone, Debug)]
+pub enum ChatEvent {
+  PrivMsg(PrivMsg),
+  UserNotice(UserNotice),
+  ClearChat(ClearChat),
+  ClearMsg(ClearMsg),
+  RoomState(RoomStateUpdate),
+  UserState(UserState),
+  GlobalUserState(GlobalUserState),
+  Whisper(Whisper),
+  Notice(Notice),
+  Join { channel: String, login: String },
+  Part { channel: String, login: String },
+  Reconnect,
+}
+
+#[derive(Clone, Debug)]
+pub struct Author {
+  pub id: String,
+  pub login: String,
+  pub name: String,
+  pub color: Option<Color>,
+  pub badges: Vec<Badge>,
+  pub badge_info: Vec<Badge>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PrivMsg {
+  pub id: String,
+  pub channel: String,
+  pub room_id: String,
+  pub author: Author,
+  /// Sanitized, see `sanitize`. Emote ranges refer to this text.
+  pub text: String,
+  /// The characters sanitizing removed from the text, in order. Not the raw text, which could
+  /// show what `Filter` masked.
+  pub removed: Vec<char>,
+  /// Sent using `/me`.
+  pub is_action: bool,
+  pub emotes: Vec<Emote>,
+  pub bits: Option<u64>,
+  pub reply: Option<ReplyParent>,
+  pub first_msg: bool,
+  pub returning_chatter: bool,
+  pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplyParent {
+  pub msg_id: String,
+  pub user_id: String,
+  pub user_login: String,
+  pub user_name: String,
+  pub msg_body: String,
+  /// The first message of the thread, which may be the parent itself.
+  pub thread_msg_id: Option<String>,
+  pub thread_user_login: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UserNotice {
+  pub id: String,
+  pub channel: String,
+  pub room_id: String,
+  pub author: Author,
+  pub kind: UserNoticeKind,
+  pub system_msg: Option<String>,
+  /// The message the user attached, e.g. a resub message. Sanitized like `PrivMsg::text`.
+  pub text: Option<String>,
+  pub removed: Vec<char>,
+  pub emotes: Vec<Emote>,
+  pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub enum UserNoticeKind {
+  Sub {
+    plan: String,
+    months: u64,
+  },
+  Resub {
+    plan: String,
+    months: u64,
+    streak: Option<u64>,
+  },
+  SubGift {
+    plan: String,
+    recipient_login: String,
+    recipient_name: String,
+    months: u64,
+  },
+  SubMysteryGift {
+    plan: String,
+    count: u64,
+  },
+  GiftPaidUpgrade,
+  Raid {
+    viewers: u64,
+  },
+  Announcement {
+    color: Option<String>,
+  },
+  BitsBadgeTier {
+    threshold: u64,
+  },
+  Other(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ClearChat {
+  pub channel: String,
+  pub room_id: String,
+  /// `None` if the whole chat was cleared.
+  pub target: Option<ClearChatTarget>,
+  pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClearChatTarget {
+  pub user_id: String,
+  pub login: String,
+  /// `None` for a permanent ban.
+  pub duration: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClearMsg {
+  pub channel: String,
+  pub login: String,
+  pub target_msg_id: String,
+  pub text: String,
+  pub timestamp: DateTime<Utc>,
+}
+
+/// `ROOMSTATE` only contains the tags which changed, except on join.
+#[derive(Clone, Debug, Default)]
+pub struct RoomStateUpdate {
+  pub channel: String,
+  pub room_id: String,
+  pub emote_only: Option<bool>,
+  /// Minimum follow duration in minutes, `-1` if disabled.
+  pub followers_only: Option<i64>,
+  pub r9k: Option<bool>,
+  /// Seconds between messages, `0` if disabled.
+  pub slow: Option<u64>,
+  pub subs_only: Option<bool>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UserState {
+  pub channel: String,
+  pub name: String,
+  pub color: Option<Color>,
+  pub badges: Vec<Badge>,
+  pub badge_info: Vec<Badge>,
+  pub emote_sets: Vec<String>,
+  pub is_mod: bool,
+  /// Set when this is the response to a message we sent.
+  pub sent_msg_id: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GlobalUserState {
+  pub user_id: String,
+  pub name: String,
+  pub color: Option<Color>,
+  pub badges: Vec<Badge>,
+  pub emote_sets: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Whisper {
+  pub id: String,
+  pub thread_id: String,
+  pub author: Author,
+  pub recipient_login: String,
+  /// Sanitized like `PrivMsg::text`.
+  pub text: String,
+  pub removed: Vec<char>,
+  pub emotes: Vec<Emote>,
+  pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Notice {
+  pub channel: Option<String>,
+  pub msg_id: Option<String>,
+  pub text: String,
+}
+
+impl ChatEvent {
+  /// Returns `None` for messages which have no typed representation, e.g. `PING`.
+  pub fn parse(message: &twitch::Message) -> Option<ChatEvent> {
+    use twitch::Command;
+
+    let line = Line::parse(message.raw());
+    let tags = &line.tags;
+
+    Some(match message.command() {
+      Command::Privmsg => {
+        let (text, is_action) = match line.text?.strip_prefix("\u{1}ACTION ") {
+          Some(action) => (action.trim_end_matches('\u{1}'), true),
+          None => (line.text?, false),
+        };
+        let mut emotes = tags.emotes();
+        let (text, removed) = sanitize_with_emotes(text, &mut emotes);
+        ChatEvent::PrivMsg(PrivMsg {
+          id: tags.string("id")?,
+          channel: line.channel?.into(),
+          room_id: tags.string("room-id").unwrap_or_default(),
+          author: line.author()?,
+          text,
+          removed,
+          is_action,
+          emotes,
+          bits: tags.number("bits"),
+          reply: ReplyParent::from_tags(tags),
+          first_msg: tags.bool("first-msg"),
+          returning_chatter: tags.bool("returning-chatter"),
+          timestamp: tags.timestamp(),
+        })
+      }
+      Command::UserNotice => {
+        let mut emotes = tags.emotes();
+        let (text, removed) = match line.text {
+          Some(text) => {
+            let (text, removed) = sanitize_with_emotes(text, &mut emotes);
+            (Some(text), removed)
+          }
+          None => (None, Vec::new()),
+        };
+        ChatEvent::UserNotice(UserNotice {
+          id: tags.string("id")?,
+          channel: line.channel?.into(),
+          room_id: tags.string("room-id").unwrap_or_default(),
+          author: line.author()?,
+          kind: UserNoticeKind::from_tags(tags),
+          system_msg: tags.string("system-msg"),
+          text,
+          removed,
+          emotes,
+          timestamp: tags.timestamp(),
+        })
+      }
+      Command::ClearChat => ChatEvent::ClearChat(ClearChat {
+        channel: line.channel?.into(),
+        room_id: tags.string("room-id").unwrap_or_default(),
+        target: line.text.map(|login| ClearChatTarget {
+          user_id: tags.string("target-user-id").unwrap_or_default(),
+          login: login.into(),
+          duration: tags.number("ban-duration").map(Duration::from_secs),
+        }),
+        timestamp: tags.timestamp(),
+      }),
+      Command::ClearMsg => ChatEvent::ClearMsg(ClearMsg {
+        channel: line.channel?.into(),
+        login: tags.string("login").unwrap_or_default(),
+        target_msg_id: tags.string("target-msg-id")?,
+        text: line.text.unwrap_or_default().into(),
+        timestamp: tags.timestamp(),
+      }),
+      Command::RoomState => ChatEvent::RoomState(RoomStateUpdate {
+        channel: line.channel?.into(),
+        room_id: tags.string("room-id").unwrap_or_default(),
+        emote_only: tags.raw("emote-only").map(|v| v == "1"),
+        followers_only: tags.number("followers-only"),
+        r9k: tags.raw("r9k").map(|v| v == "1"),
+        slow: tags.number("slow"),
+        subs_only: tags.raw("subs-only").map(|v| v == "1"),
+      }),
+      Command::UserState => {
+        let badges = tags.badges("badges");
+        ChatEvent::UserState(UserState {
+          channel: line.channel?.into(),
+          name: tags.string("display-name").unwrap_or_default(),
+          color: tags.color(),
+          is_mod: tags.bool("mod") || badges.iter().any(|b| b.name == "broadcaster"),
+          badges,
+          badge_info: tags.badges("badge-info"),
+          emote_sets: tags.emote_sets(),
+          sent_msg_id: tags.string("id"),
+        })
+      }
+      Command::GlobalUserState => ChatEvent::GlobalUserState(GlobalUserState {
+        user_id: tags.string("user-id")?,
+        name: tags.string("display-name").unwrap_or_default(),
+        color: tags.color(),
+        badges: tags.badges("badges"),
+        emote_sets: tags.emote_sets(),
+      }),
+      Command::Whisper => {
+        let mut emotes = tags.emotes();
+        let (text, removed) = sanitize_with_emotes(line.text?, &mut emotes);
+        ChatEvent::Whisper(Whisper {
+          id: tags.string("message-id").unwrap_or_default(),
+          thread_id: tags.string("thread-id").unwrap_or_default(),
+          author: line.author()?,
+          recipient_login: line.middle.split(' ').next()?.into(),
+          text,
+          removed,
+          emotes,
+          timestamp: tags.timestamp(),
+        })
+      }
+      Command::Notice => ChatEvent::Notice(Notice {
+        channel: line.channel.map(String::from),
+        msg_id: tags.string("msg-id"),
+        text: line.text.unwrap_or_default().into(),
+      }),
+      Command::Join => ChatEvent::Join {
+        channel: line.channel?.into(),
+        login: line.nick?.into(),
+      },
+      Command::Part => ChatEvent::Part {
+        channel: line.channel?.into(),
+        login: line.nick?.into(),
+      },
+      Command::Reconnect => ChatEvent::Reconnect,
+      _ => return None,
+    })
+  }
+
+  pub fn channel(&self) -> Option<&str> {
+    match self {
+      ChatEvent::PrivMsg(e) => Some(&e.channel),
+      ChatEvent::UserNotice(e) => Some(&e.channel),
+      ChatEvent::ClearChat(e) => Some(&e.channel),
+      ChatEvent::ClearMsg(e) => Some(&e.channel),
+      ChatEvent::RoomState(e) => Some(&e.channel),
+      ChatEvent::UserState(e) => Some(&e.channel),
+      ChatEvent::Notice(e) => e.channel.as_deref(),
+      ChatEvent::Join { channel, .. } | ChatEvent::Part { channel, .. } => Some(channel),
+      ChatEvent::GlobalUserState(_) | ChatEvent::Whisper(_) | ChatEvent::Reconnect => None,
+    }
+  }
+}
+
+impl ReplyParent {
+  /// The id of the first message in the thread, which is the parent if this is the first reply.
+  pub fn root(&self) -> &str {
+    self.thread_msg_id.as_deref().unwrap_or(&self.msg_id)
+  }
+
+  fn from_tags(tags: &Tags<'_>) -> Option<Self> {
+    Some(ReplyParent {
+      msg_id: tags.string("reply-parent-msg-id")?,
+      user_id: tags.string("reply-parent-user-id").unwrap_or_default(),
+      user_login: tags.string("reply-parent-user-login").unwrap_or_default(),
+      user_name: tags.string("reply-parent-display-name").unwrap_or_default(),
+      msg_body: sanitize(&tags.string("reply-parent-msg-body").unwrap_or_default()).into(),
+      thread_msg_id: tags.string("reply-thread-parent-msg-id"),
+      thread_user_login: tags.string("reply-thread-parent-user-login"),
+    })
+  }
+}
+
+impl UserNoticeKind {
+  fn from_tags(tags: &Tags<'_>) -> Self {
+    let plan = || tags.string("msg-param-sub-plan").unwrap_or_default();
+    let months = || tags.number("msg-param-cumulative-months").unwrap_or(1);
+
+    match tags.raw("msg-id").unwrap_or_default() {
+      "sub" => UserNoticeKind::Sub {
+        plan: plan(),
+        months: months(),
+      },
+      "resub" => UserNoticeKind::Resub {
+        plan: plan(),
+        months: months(),
+        streak: tags
+          .bool("msg-param-should-share-streak")
+          .then(|| tags.number("msg-param-streak-months"))
+          .flatten(),
+      },
+      "subgift" | "anonsubgift" => UserNoticeKind::SubGift {
+        plan: plan(),
+        recipient_login: tags
+          .string("msg-param-recipient-user-name")
+          .unwrap_or_default(),
+        recipient_name: tags
+          .string("msg-param-recipient-display-name")
+          .unwrap_or_default(),
+        months: tags.number("msg-param-months").unwrap_or(1),
+      },
+      "submysterygift" | "anonsubmysterygift" => UserNoticeKind::SubMysteryGift {
+        plan: plan(),
+        count: tags.number("msg-param-mass-gift-count").unwrap_or(1),
+      },
+      "giftpaidupgrade" | "anongiftpaidupgrade" => UserNoticeKind::GiftPaidUpgrade,
+      "raid" => UserNoticeKind::Raid {
+        viewers: tags.number("msg-param-viewerCount").unwrap_or(0),
+      },
+      "announcement" => UserNoticeKind::Announcement {
+        color: tags.string("msg-param-color"),
+      },
+      "bitsbadgetier" => UserNoticeKind::BitsBadgeTier {
+        threshold: tags.number("msg-param-threshold").unwrap_or(0),
+      },
+      other => UserNoticeKind::Other(other.into()),
+    }
+  }
+}
+
+/// The parts of a raw IRC line which aren't exposed by `twitch::Message` in a decoded form.
+struct Line<'a> {
+  tags: Tags<'a>,
+  nick: Option<&'a str>,
+  /// Without the leading `#`.
+  channel: Option<&'a str>,
+  /// Parameters before the trailing one.
+  middle: &'a str,
+  /// The trailing parameter, without the leading `:`.
+  text: Option<&'a str>,
+}
+
+impl<'a> Line<'a> {
+  fn parse(raw: &'a str) -> Self {
+    let raw = raw.trim_end_matches(['\r', '\n']);
+    let tags = Tags::parse(raw);
+
+    let mut rest = match raw.starts_with('@') {
+      true => raw
+        .split_once(' ')
+        .map(|(_, rest)| rest)
+        .unwrap_or_default(),
+      false => raw,
+    };
+
+    let mut nick = None;
+    if let Some(prefix) = rest.strip_prefix(':') {
+      let (prefix, remainder) = prefix.split_once(' ').unwrap_or((prefix, ""));
+      nick = prefix.split_once('!').map(|(nick, _)| nick);
+      rest = remainder;
+    }
+
+    let params = rest.split_once(' ').map(|(_, p)| p).unwrap_or_default();
+    let (middle, text) = match params.strip_prefix(':') {
+      Some(text) => ("", Some(text)),
+      None => match params.split_once(" :") {
+        Some((middle, text)) => (middle, Some(text)),
+        None => (params, None),
+      },
+    };
+    let channel = middle.split(' ').find_map(|p| p.strip_prefix('#'));
+
+    Line {
+      tags,
+      nick,
+      channel,
+      middle,
+      text,
+    }
+  }
+
+  fn author(&self) -> Option<Author> {
+    let login = match (self.tags.string("login"), self.nick) {
+      (Some(login), _) => login,
+      (None, Some(nick)) => nick.into(),
+      (None, None) => return None,
+    };
+    Some(Author {
+      id: self.tags.string("user-id").unwrap_or_default(),
+      name: self
+        .tags
+        .string("display-name")
+        .unwrap_or_else(|| login.clone()),
+      login,
+      color: self.tags.color(),
+      badges: self.tags.badges("badges"),
+      badge_info: self.tags.badges("badge-info"),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(line: &str) -> Option<ChatEvent> {
+    ChatEvent::parse(&twitch::parse(line.into()).unwrap())
+  }
+
+  #[test]
+  fn privmsg() {
+    let line = "@badge-info=subscriber/8;badges=subscriber/6,premium/1;client-nonce=28e05b1c83f1e916ca1710c44b014515;color=#FF4500;display-name=Ronni;emotes=25:0-4,12-16/1902:6-10;first-msg=0;flags=;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;mod=0;returning-chatter=0;room-id=1337;subscriber=1;tmi-sent-ts=1507246572675;turbo=1;user-id=1337;user-type= :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni :Kappa Keepo Kappa";
+    let Some(ChatEvent::PrivMsg(m)) = parse(line) else {
+      panic!("expected a PRIVMSG");
+    };
+    assert_eq!(m.id, "b34ccfc7-4977-403a-8a94-33c6bac34fb8");
+    assert_eq!(m.channel, "ronni");
+    assert_eq!(m.room_id, "1337");
+    assert_eq!(m.author.login, "ronni");
+    assert_eq!(m.author.name, "Ronni");
+    assert_eq!(m.author.id, "1337");
+    assert_eq!(m.author.badge_info[0].version, "8");
+    assert_eq!(m.text, "Kappa Keepo Kappa");
+    assert!(m.removed.is_empty());
+    assert!(!m.is_action);
+    assert_eq!(m.emotes.len(), 2);
+    assert_eq!(m.bits, None);
+    assert!(m.reply.is_none());
+    assert!(!m.first_msg);
+    assert_eq!(m.timestamp.timestamp_millis(), 1507246572675);
+  }
+
+  #[test]
+  fn action_with_bits() {
+    let line = "@badge-info=;badges=bits/100;bits=100;color=;display-name=ronni;emotes=;id=a6b0da1a-1d0a-4b8f-8b37-86b9a0b8e10c;mod=0;room-id=12345678;subscriber=0;tmi-sent-ts=1507246572675;turbo=0;user-id=87654321;user-type= :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :\u{1}ACTION cheer100 Hi\u{1}";
+    let Some(ChatEvent::PrivMsg(m)) = parse(line) else {
+      panic!("expected a PRIVMSG");
+    };
+    assert!(m.is_action);
+    assert_eq!(m.text, "cheer100 Hi");
+    assert_eq!(m.bits, Some(100));
+    assert_eq!(m.author.color, None);
+    assert_eq!(m.author.name, "ronni");
+  }
+
+  #[test]
+  fn reply() {
+    let line = r"@badge-info=;badges=;color=#0000FF;display-name=Foo;emotes=;first-msg=1;id=1d1a3a4d-3b5e-45f2-b52b-d0d74c5ba14f;mod=0;reply-parent-display-name=Bar;reply-parent-msg-body=hello\sthere\:)\s\\o/;reply-parent-msg-id=6b13e51b-7ecb-43b5-ba5b-2bb5288df696;reply-parent-user-id=2;reply-parent-user-login=bar;reply-thread-parent-msg-id=dc11a3d2-8b0b-4ba5-a9e1-8a6a1a2bab84;reply-thread-parent-user-login=baz;room-id=3;subscriber=0;tmi-sent-ts=1690000000000;turbo=0;user-id=1;user-type= :foo!foo@foo.tmi.twitch.tv PRIVMSG #channel :@Bar hi";
+    let Some(ChatEvent::PrivMsg(m)) = parse(line) else {
+      panic!("expected a PRIVMSG");
+    };
+    assert!(m.first_msg);
+    let reply = m.reply.unwrap();
+    assert_eq!(reply.msg_id, "6b13e51b-7ecb-43b5-ba5b-2bb5288df696");
+    assert_eq!(reply.user_login, "bar");
+    assert_eq!(reply.user_name, "Bar");
+    assert_eq!(reply.msg_body, r"hello there;) \o/");
+    assert_eq!(reply.root(), "dc11a3d2-8b0b-4ba5-a9e1-8a6a1a2bab84");
+    assert_eq!(reply.thread_user_login.as_deref(), Some("baz"));
+  }
+
+  #[test]
+  fn resub() {
+    let line = r"@badge-info=;badges=staff/1,broadcaster/1,turbo/1;color=#008000;display-name=ronni;emotes=;id=db25007f-7a18-43eb-9379-80131e44d633;login=ronni;mod=0;msg-id=resub;msg-param-cumulative-months=6;msg-param-streak-months=2;msg-param-should-share-streak=1;msg-param-sub-plan=Prime;msg-param-sub-plan-name=Prime;room-id=12345678;subscriber=1;system-msg=ronni\shas\ssubscribed\sfor\s6\smonths!;tmi-sent-ts=1507246572675;turbo=1;user-id=87654321;user-type=staff :tmi.twitch.tv USERNOTICE #dallas :Great stream -- keep it up!";
+    let Some(ChatEvent::UserNotice(notice)) = parse(line) else {
+      panic!("expected a USERNOTICE");
+    };
+    assert_eq!(notice.channel, "dallas");
+    assert_eq!(notice.author.login, "ronni");
+    assert_eq!(
+      notice.system_msg.as_deref(),
+      Some("ronni has subscribed for 6 months!")
+    );
+    assert_eq!(notice.text.as_deref(), Some("Great stream -- keep it up!"));
+    assert!(matches!(
+      notice.kind,
+      UserNoticeKind::Resub { months: 6, streak: Some(2), ref plan } if plan == "Prime"
+    ));
+  }
+
+  #[test]
+  fn raid() {
+    let line = r"@badge-info=;badges=turbo/1;color=#9ACD32;display-name=TestChannel;emotes=;id=3d830f12-795c-447d-af3c-ea05e40fbddb;login=testchannel;mod=0;msg-id=raid;msg-param-displayName=TestChannel;msg-param-login=testchannel;msg-param-viewerCount=15;room-id=33332222;subscriber=0;system-msg=15\sraiders\sfrom\sTestChannel\shave\sjoined\n!;tmi-sent-ts=1507246572675;turbo=1;user-id=123456;user-type= :tmi.twitch.tv USERNOTICE #othertestchannel";
+    let Some(ChatEvent::UserNotice(notice)) = parse(line) else {
+      panic!("expected a USERNOTICE");
+    };
+    assert!(matches!(notice.kind, UserNoticeKind::Raid { viewers: 15 }));
+    assert_eq!(notice.text, None);
+    assert_eq!(
+      notice.system_msg.as_deref(),
+      Some("15 raiders from TestChannel have joined\n!")
+    );
+  }
+
+  #[test]
+  fn clearchat() {
+    let timeout = "@ban-duration=350;room-id=12345678;target-user-id=87654321;tmi-sent-ts=1642719320727 :tmi.twitch.tv CLEARCHAT #dallas :ronni";
+    let Some(ChatEvent::ClearChat(clear)) = parse(timeout) else {
+      panic!("expected a CLEARCHAT");
+    };
+    let target = clear.target.unwrap();
+    assert_eq!(target.login, "ronni");
+    assert_eq!(target.user_id, "87654321");
+    assert_eq!(target.duration, Some(Duration::from_secs(350)));
+
+    let ban = "@room-id=12345678;target-user-id=87654321;tmi-sent-ts=1642715756806 :tmi.twitch.tv CLEARCHAT #dallas :ronni";
+    let Some(ChatEvent::ClearChat(clear)) = parse(ban) else {
+      panic!("expected a CLEARCHAT");
+    };
+    assert_eq!(clear.target.unwrap().duration, None);
+
+    let clear = "@room-id=12345678;tmi-sent-ts=1642715695392 :tmi.twitch.tv CLEARCHAT #dallas";
+    let Some(ChatEvent::ClearChat(clear)) = parse(clear) else {
+      panic!("expected a CLEARCHAT");
+    };
+    assert!(clear.target.is_none());
+    assert_eq!(clear.timestamp.timestamp_millis(), 1642715695392);
+  }
+
+  #[test]
+  fn clearmsg() {
+    let line = "@login=foo;room-id=;target-msg-id=94e6c7ff-bf98-4faa-af5d-7ad633a158a9;tmi-sent-ts=1642720582342 :tmi.twitch.tv CLEARMSG #bar :what a great day";
+    let Some(ChatEvent::ClearMsg(clear)) = parse(line) else {
+      panic!("expected a CLEARMSG");
+    };
+    assert_eq!(clear.login, "foo");
+    assert_eq!(clear.target_msg_id, "94e6c7ff-bf98-4faa-af5d-7ad633a158a9");
+    assert_eq!(clear.text, "what a great day");
+  }
+
+  #[test]
+  fn roomstate() {
+    let join = "@emote-only=0;followers-only=-1;r9k=0;rituals=0;room-id=12345678;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #bar";
+    let Some(ChatEvent::RoomState(state)) = parse(join) else {
+      panic!("expected a ROOMSTATE");
+    };
+    assert_eq!(state.room_id, "12345678");
+    assert_eq!(state.emote_only, Some(false));
+    assert_eq!(state.followers_only, Some(-1));
+    assert_eq!(state.slow, Some(0));
+
+    let update = "@emote-only=1;room-id=12345678 :tmi.twitch.tv ROOMSTATE #bar";
+    let Some(ChatEvent::RoomState(state)) = parse(update) else {
+      panic!("expected a ROOMSTATE");
+    };
+    assert_eq!(state.emote_only, Some(true));
+    assert_eq!(state.followers_only, None);
+    assert_eq!(state.subs_only, None);
+  }
+
+  #[test]
+  fn user_states() {
+    let line = "@badge-info=;badges=moderator/1;color=;display-name=bar;emote-sets=0,33,50,237;mod=1;subscriber=0;turbo=0;user-type=mod :tmi.twitch.tv USERSTATE #bar";
+    let Some(ChatEvent::UserState(state)) = parse(line) else {
+      panic!("expected a USERSTATE");
+    };
+    assert!(state.is_mod);
+    assert_eq!(state.emote_sets, ["0", "33", "50", "237"]);
+    assert_eq!(state.sent_msg_id, None);
+
+    let broadcaster = "@badge-info=;badges=broadcaster/1;color=;display-name=bar;emote-sets=0;mod=0;subscriber=0;turbo=0;user-type= :tmi.twitch.tv USERSTATE #bar";
+    let Some(ChatEvent::UserState(state)) = parse(broadcaster) else {
+      panic!("expected a USERSTATE");
+    };
+    assert!(state.is_mod);
+
+    let global = "@badge-info=subscriber/8;badges=subscriber/6;color=#0D4200;display-name=dallas;emote-sets=0,33,50,237,793,2126,3517,4578,5569,9400,10337,12239;turbo=0;user-id=12345678;user-type=admin :tmi.twitch.tv GLOBALUSERSTATE";
+    let Some(ChatEvent::GlobalUserState(state)) = parse(global) else {
+      panic!("expected a GLOBALUSERSTATE");
+    };
+    assert_eq!(state.user_id, "12345678");
+    assert_eq!(state.name, "dallas");
+    assert_eq!(state.emote_sets.len(), 12);
+  }
+
+  #[test]
+  fn whisper() {
+    let line = "@badges=staff/1,bits-charity/1;color=#8A2BE2;display-name=PetsgomOO;emotes=;message-id=306;thread-id=12345678_87654321;turbo=0;user-id=87654321;user-type=staff :petsgomoo!petsgomoo@petsgomoo.tmi.twitch.tv WHISPER foo :hello";
+    let Some(ChatEvent::Whisper(whisper)) = parse(line) else {
+      panic!("expected a WHISPER");
+    };
+    assert_eq!(whisper.author.login, "petsgomoo");
+    assert_eq!(whisper.author.name, "PetsgomOO");
+    assert_eq!(whisper.recipient_login, "foo");
+    assert_eq!(whisper.thread_id, "12345678_87654321");
+    assert_eq!(whisper.text, "hello");
+  }
+
+  #[test]
+  fn other_commands() {
+    let notice = "@msg-id=delete_message_success :tmi.twitch.tv NOTICE #bar :The message from foo is now deleted.";
+    let Some(ChatEvent::Notice(notice)) = parse(notice) else {
+      panic!("expected a NOTICE");
+    };
+    assert_eq!(notice.channel.as_deref(), Some("bar"));
+    assert_eq!(notice.msg_id.as_deref(), Some("delete_message_success"));
+
+    let global = ":tmi.twitch.tv NOTICE * :Login authentication failed";
+    let Some(ChatEvent::Notice(notice)) = parse(global) else {
+      panic!("expected a NOTICE");
+    };
+    assert_eq!(notice.channel, None);
+    assert_eq!(notice.text, "Login authentication failed");
+
+    assert!(matches!(
+      parse(":ronni!ronni@ronni.tmi.twitch.tv JOIN #dallas"),
+      Some(ChatEvent::Join { channel, login }) if channel == "dallas" && login == "ronni"
+    ));
+    assert!(matches!(
+      parse(":ronni!ronni@ronni.tmi.twitch.tv PART #dallas"),
+      Some(ChatEvent::Part { channel, .. }) if channel == "dallas"
+    ));
+    assert!(matches!(
+      parse(":tmi.twitch.tv RECONNECT"),
+      Some(ChatEvent::Reconnect)
+    ));
+    assert!(parse("PING :tmi.twitch.tv").is_none());
+  }
+
+  #[test]
+  fn missing_required_tags() {
+    // a PRIVMSG without an id can't be replied to or deleted
+    let line = ":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi";
+    assert!(parse(line).is_none());
+  }
+}
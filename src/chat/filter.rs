@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::event::ChatEvent;
+use super::phrase::{CompiledPhrase, Phrase};
+use super::tags::Emote;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IgnoreRules {
+  pub users: Vec<IgnoredUser>,
+  pub phrases: Vec<IgnoredPhrase>,
+}
+
+impl IgnoreRules {
+  pub fn is_ignored(&self, login: &str) -> bool {
+    self
+      .users
+      .iter()
+      .any(|user| user.login.eq_ignore_ascii_case(login))
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IgnoredUser {
+  pub login: String,
+  /// Also blocked on Twitch when the user was ignored.
+  #[serde(default)]
+  pub blocked: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IgnoredPhrase {
+  #[serde(flatten)]
+  pub phrase: Phrase,
+  /// Matches are replaced by this text, otherwise the whole message is hidden.
+  #[serde(default)]
+  pub replace_with: Option<String>,
+}
+
+/// Compiled ignore rules, applied to events before they are stored anywhere.
+#[derive(Default)]
+pub struct Filter {
+  users: HashSet<String>,
+  phrases: Vec<(CompiledPhrase, Option<String>)>,
+}
+
+enum Outcome {
+  Keep,
+  Hide,
+}
+
+impl Filter {
+  /// Rules with an invalid pattern are skipped.
+  pub fn new(rules: &IgnoreRules) -> Self {
+    let users = rules
+      .users
+      .iter()
+      .map(|user| user.login.to_lowercase())
+      .collect();
+    let phrases = rules
+      .phrases
+      .iter()
+      .filter_map(|rule| match rule.phrase.compile() {
+        Ok(phrase) => Some((phrase, rule.replace_with.clone())),
+        Err(e) => {
+          tracing::warn!(?rule.phrase, "invalid ignore pattern: {e}");
+          None
+        }
+      })
+      .collect();
+    Self { users, phrases }
+  }
+
+  pub fn is_ignored(&self, login: &str) -> bool {
+    self.users.contains(&login.to_lowercase())
+  }
+
+  /// Returns `None` if the event should be dropped. Messages from ignored users are dropped,
+  /// and matching phrases either drop the message or are replaced.
+  pub fn apply(&self, event: ChatEvent) -> Option<ChatEvent> {
+    match event {
+      ChatEvent::PrivMsg(mut m) => {
+        if self.is_ignored(&m.author.login) {
+          return None;
+        }
+        match self.apply_text(&mut m.text, &mut m.emotes) {
+          Outcome::Keep => Some(ChatEvent::PrivMsg(m)),
+          Outcome::Hide => None,
+        }
+      }
+      ChatEvent::UserNotice(mut m) => {
+        if self.is_ignored(&m.author.login) {
+          return None;
+        }
+        // keep the notice itself, only the attached message is user content
+        if let Some(text) = &mut m.text {
+          if let Outcome::Hide = self.apply_text(text, &mut m.emotes) {
+            m.text = None;
+            m.emotes.clear();
+          }
+        }
+        Some(ChatEvent::UserNotice(m))
+      }
+      ChatEvent::Whisper(mut m) => {
+        if self.is_ignored(&m.author.login) {
+          return None;
+        }
+        match self.apply_text(&mut m.text, &mut m.emotes) {
+          Outcome::Keep => Some(ChatEvent::Whisper(m)),
+          Outcome::Hide => None,
+        }
+      }
+      event => Some(event),
+    }
+  }
+
+  fn apply_text(&self, text: &mut String, emotes: &mut Vec<Emote>) -> Outcome {
+    for (phrase, replacement) in &self.phrases {
+      let Some(replacement) = replacement else {
+        if phrase.is_match(text) {
+          return Outcome::Hide;
+        }
+        continue;
+      };
+
+      let matches = phrase.find_iter(text).collect::<Vec<_>>();
+      if matches.is_empty() {
+        continue;
+      }
+      shift_emotes(text, &matches, replacement, emotes);
+      let mut out = String::with_capacity(text.len());
+      let mut pos = 0;
+      for range in matches {
+        out.push_str(&text[pos..range.start]);
+        out.push_str(replacement);
+        pos = range.end;
+      }
+      out.push_str(&text[pos..]);
+      *text = out;
+    }
+    Outcome::Keep
+  }
+}
+
+/// Moves the code point ranges of `emotes` to where they will be after replacing each of the
+/// byte ranges in `matches`, and drops emotes which overlap a replaced range.
+fn shift_emotes(text: &str, matches: &[Range<usize>], replacement: &str, emotes: &mut Vec<Emote>) {
+  let char_index = |byte: usize| text[..byte].chars().count();
+  let replacement_len = replacement.chars().count() as isize;
+  // (start, end, change in length) in code points
+  let edits = matches
+    .iter()
+    .map(|range| {
+      let (start, end) = (char_index(range.start), char_index(range.end));
+      (start, end, replacement_len - (end - start) as isize)
+    })
+    .collect::<Vec<_>>();
+
+  for emote in emotes.iter_mut() {
+    emote.ranges.retain_mut(|range| {
+      let mut offset = 0;
+      for (start, end, change) in &edits {
+        if range.start < *end && *start < range.end {
+          return false;
+        }
+        if *end <= range.start {
+          offset += change;
+        }
+      }
+      *range = (range.start as isize + offset) as usize..(range.end as isize + offset) as usize;
+      true
+    });
+  }
+  emotes.retain(|emote| !emote.ranges.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+  #![allow(clippy::single_range_in_vec_init)]
+
+  use super::*;
+
+  fn filter(phrases: Vec<(Phrase, Option<&str>)>) -> Filter {
+    Filter::new(&IgnoreRules {
+      users: vec![IgnoredUser {
+        login: "Ronni".into(),
+        blocked: false,
+      }],
+      phrases: phrases
+        .into_iter()
+        .map(|(phrase, replace_with)| IgnoredPhrase {
+          phrase,
+          replace_with: replace_with.map(String::from),
+        })
+        .collect(),
+    })
+  }
+
+  fn emote(id: &str, ranges: Vec<Range<usize>>) -> Emote {
+    Emote {
+      id: id.into(),
+      ranges,
+    }
+  }
+
+  /// The text of every emote range, which should still be the emote's name.
+  fn names(text: &str, emotes: &[Emote]) -> Vec<String> {
+    emotes
+      .iter()
+      .flat_map(|emote| &emote.ranges)
+      .map(|range| text.chars().skip(range.start).take(range.len()).collect())
+      .collect()
+  }
+
+  fn apply(filter: &Filter, text: &str, emotes: &mut Vec<Emote>) -> Option<String> {
+    let mut text = text.to_string();
+    match filter.apply_text(&mut text, emotes) {
+      Outcome::Keep => Some(text),
+      Outcome::Hide => None,
+    }
+  }
+
+  #[test]
+  fn shorter_replacement() {
+    let filter = filter(vec![(Phrase::plain("darn"), Some("*"))]);
+    let mut emotes = vec![emote("25", vec![0..5, 22..27]), emote("1902", vec![11..16])];
+    let text = apply(&filter, "Kappa darn Keepo DARN Kappa", &mut emotes).unwrap();
+    assert_eq!(text, "Kappa * Keepo * Kappa");
+    assert_eq!(
+      emotes,
+      [emote("25", vec![0..5, 16..21]), emote("1902", vec![8..13])]
+    );
+    assert_eq!(names(&text, &emotes), ["Kappa", "Kappa", "Keepo"]);
+  }
+
+  #[test]
+  fn longer_replacement() {
+    let filter = filter(vec![(Phrase::plain("no"), Some("nope"))]);
+    let mut emotes = vec![emote("25", vec![3..8])];
+    let text = apply(&filter, "no Kappa no nose", &mut emotes).unwrap();
+    assert_eq!(text, "nope Kappa nope nose");
+    assert_eq!(emotes, [emote("25", vec![5..10])]);
+  }
+
+  #[test]
+  fn overlapping_emotes() {
+    let phrase = Phrase {
+      whole_word: false,
+      ..Phrase::plain("pa d")
+    };
+    let filter = filter(vec![(phrase, Some("x"))]);
+    let mut emotes = vec![emote("25", vec![0..5, 7..12]), emote("1902", vec![13..18])];
+    let text = apply(&filter, "Kappa dKappa Keepo", &mut emotes).unwrap();
+    assert_eq!(text, "KapxKappa Keepo");
+    // only the range which was cut is dropped
+    assert_eq!(
+      emotes,
+      [emote("25", vec![4..9]), emote("1902", vec![10..15])]
+    );
+
+    let filter = self::filter(vec![(Phrase::plain("Keepo"), Some("[removed]"))]);
+    let mut emotes = vec![emote("1902", vec![0..5])];
+    let text = apply(&filter, "Keepo", &mut emotes).unwrap();
+    assert_eq!(text, "[removed]");
+    assert!(emotes.is_empty());
+  }
+
+  #[test]
+  fn multibyte_text() {
+    let filter = filter(vec![(Phrase::plain("über"), Some("x"))]);
+    let mut emotes = vec![emote("25", vec![7..12])];
+    let text = apply(&filter, "😀 über Kappa Über", &mut emotes).unwrap();
+    assert_eq!(text, "😀 x Kappa x");
+    assert_eq!(emotes, [emote("25", vec![4..9])]);
+    assert_eq!(names(&text, &emotes), ["Kappa"]);
+  }
+
+  #[test]
+  fn hides_messages() {
+    let filter = filter(vec![
+      (Phrase::plain("spoiler"), None),
+      (Phrase::plain("darn"), Some("*")),
+    ]);
+    assert_eq!(apply(&filter, "big SPOILER", &mut Vec::new()), None);
+    assert_eq!(
+      apply(&filter, "darn", &mut Vec::new()).as_deref(),
+      Some("*")
+    );
+    assert!(filter.is_ignored("ronni"));
+    assert!(!filter.is_ignored("pajlada"));
+    // an invalid pattern is skipped
+    let filter = self::filter(vec![(Phrase::regex("("), None)]);
+    assert_eq!(apply(&filter, "(", &mut Vec::new()).as_deref(), Some("("));
+  }
+
+  #[test]
+  fn masks_sanitized_messages() {
+    let filter = filter(vec![(Phrase::plain("darn"), Some("*"))]);
+    let line = "@badges=;color=;display-name=a;emotes=;id=1;room-id=1;tmi-sent-ts=1;user-id=2 :a!a@a.tmi.twitch.tv PRIVMSG #forsen :darn\u{200B} it";
+    let event = ChatEvent::parse(&twitch::parse(line.into()).unwrap()).unwrap();
+    let Some(ChatEvent::PrivMsg(m)) = filter.apply(event) else {
+      panic!("expected a PRIVMSG");
+    };
+    assert_eq!(m.text, "* it");
+    // nothing else keeps the masked word around
+    assert_eq!(m.removed, ['\u{200B}']);
+    assert!(!format!("{m:?}").contains("darn"));
+  }
+}
@@ -0,0 +1,502 @@
+use std::ops::Range;
+
+use crate::emotes::{Emote, EmoteStore};
+
+use super::event::PrivMsg;
+use super::tags::Emote as TagEmote;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fragment {
+  /// Plain text, including the whitespace around it.
+  Text(String),
+  Emote(Emote),
+  /// `@login`, without the `@`.
+  Mention(String),
+  Url(String),
+  Cheer {
+    prefix: String,
+    amount: u64,
+  },
+}
+
+/// The prefixes of Twitch's global cheermotes. Channels can add their own, which are shown as
+/// plain text.
+pub const CHEERMOTES: &[&str] = &[
+  "Cheer",
+  "DoodleCheer",
+  "BibleThump",
+  "cheerwhal",
+  "Corgo",
+  "Scoops",
+  "uni",
+  "ShowLove",
+  "Party",
+  "SeemsGood",
+  "Pride",
+  "Kappa",
+  "FrankerZ",
+  "HeyGuys",
+  "DansGame",
+  "EleGiggle",
+  "TriHard",
+  "Kreygasm",
+  "4Head",
+  "SwiftRage",
+  "NotLikeThis",
+  "FailFish",
+  "VoHiYo",
+  "PJSalt",
+  "MrDestructoid",
+  "bday",
+  "RIPCheer",
+  "Shamrock",
+  "BitBoss",
+  "Streamlabs",
+  "Muxy",
+  "HolidayCheer",
+  "Goal",
+  "Anon",
+  "Charity",
+];
+
+pub fn tokenize(message: &PrivMsg, emotes: &EmoteStore) -> Vec<Fragment> {
+  tokenize_text(&message.text, &message.emotes, message.bits, |word| {
+    emotes.lookup(&message.room_id, word)
+  })
+}
+
+/// Splits `text` into fragments. `emotes` is the decoded `emotes` tag, and `bits` is the `bits`
+/// tag, which is the sum of the cheers in the message. Words which aren't covered by `emotes`
+/// are looked up in `word_emotes`, for third-party emotes.
+///
+/// Emote ranges which don't line up with the text are ignored, so this never panics.
+pub fn tokenize_text<'e>(
+  text: &str,
+  emotes: &[TagEmote],
+  bits: Option<u64>,
+  word_emotes: impl Fn(&str) -> Option<&'e Emote>,
+) -> Vec<Fragment> {
+  let mut out = Fragments::default();
+  let mut words = Words {
+    bits: bits.unwrap_or(0),
+    word_emotes,
+  };
+  let mut pos = 0;
+  for (range, id) in emote_byte_ranges(text, emotes) {
+    words.split(&text[pos..range.start], &mut out);
+    out.push(Fragment::Emote(Emote::twitch(id, &text[range.clone()])));
+    pos = range.end;
+  }
+  words.split(&text[pos..], &mut out);
+  out.0
+}
+
+/// Converts the code point ranges of `emotes` into sorted, non-overlapping byte ranges.
+fn emote_byte_ranges<'a>(text: &str, emotes: &'a [TagEmote]) -> Vec<(Range<usize>, &'a str)> {
+  if emotes.is_empty() {
+    return Vec::new();
+  }
+
+  let offsets = Offsets::new(text);
+  let mut ranges = Vec::new();
+  for emote in emotes {
+    for range in &emote.ranges {
+      match offsets.resolve(range) {
+        Some(r) => ranges.push((r, emote.id.as_str())),
+        None => tracing::debug!(id = emote.id, ?range, "emote range does not match text"),
+      }
+    }
+  }
+
+  ranges.sort_by_key(|(range, _)| range.start);
+  let mut end = 0;
+  ranges.retain(|(range, _)| {
+    let keep = range.start >= end;
+    if keep {
+      end = range.end;
+    }
+    keep
+  });
+  ranges
+}
+
+/// Resolves emote ranges, which Twitch counts in code points but some clients in UTF-16 code
+/// units, to byte ranges.
+pub struct Offsets<'a> {
+  text: &'a str,
+  by_char: Vec<usize>,
+  /// `None` for the second half of a surrogate pair, which doesn't start a character.
+  by_utf16: Vec<Option<usize>>,
+}
+
+impl<'a> Offsets<'a> {
+  pub fn new(text: &'a str) -> Self {
+    let by_char = text
+      .char_indices()
+      .map(|(i, _)| i)
+      .chain([text.len()])
+      .collect();
+    let mut by_utf16 = Vec::with_capacity(text.len() + 1);
+    for (i, c) in text.char_indices() {
+      by_utf16.push(Some(i));
+      if c.len_utf16() == 2 {
+        by_utf16.push(None);
+      }
+    }
+    by_utf16.push(Some(text.len()));
+    Self {
+      text,
+      by_char,
+      by_utf16,
+    }
+  }
+
+  /// The byte range of the emote name at `range`, counted in code points if that covers a name,
+  /// otherwise in UTF-16 code units.
+  pub fn resolve(&self, range: &Range<usize>) -> Option<Range<usize>> {
+    let is_name = |range: &Range<usize>| {
+      range.start < range.end && !self.text[range.clone()].contains(char::is_whitespace)
+    };
+    let as_chars = self
+      .by_char
+      .get(range.start)
+      .zip(self.by_char.get(range.end))
+      .map(|(start, end)| *start..*end);
+    let as_utf16 = self
+      .by_utf16
+      .get(range.start)
+      .zip(self.by_utf16.get(range.end))
+      .and_then(|(start, end)| Some((*start)?..(*end)?));
+    as_chars
+      .filter(is_name)
+      .or_else(|| as_utf16.filter(is_name))
+  }
+
+  /// The code point index of the character starting at byte `i`.
+  pub fn char_index(&self, i: usize) -> usize {
+    self.by_char.partition_point(|start| *start < i)
+  }
+}
+
+#[derive(Default)]
+struct Fragments(Vec<Fragment>);
+
+impl Fragments {
+  fn push(&mut self, fragment: Fragment) {
+    self.0.push(fragment);
+  }
+
+  fn push_text(&mut self, text: &str) {
+    if text.is_empty() {
+      return;
+    }
+    match self.0.last_mut() {
+      Some(Fragment::Text(last)) => last.push_str(text),
+      _ => self.0.push(Fragment::Text(text.into())),
+    }
+  }
+}
+
+/// Splits the text between Twitch emotes into words.
+struct Words<F> {
+  /// Bits which weren't matched to a cheer yet.
+  bits: u64,
+  word_emotes: F,
+}
+
+impl<'e, F: Fn(&str) -> Option<&'e Emote>> Words<F> {
+  fn split(&mut self, text: &str, out: &mut Fragments) {
+    let mut rest = text;
+    while !rest.is_empty() {
+      let word_start = rest
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(rest.len());
+      out.push_text(&rest[..word_start]);
+      rest = &rest[word_start..];
+
+      let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+      let word = &rest[..word_end];
+      rest = &rest[word_end..];
+
+      if let Some(emote) = (self.word_emotes)(word) {
+        out.push(Fragment::Emote(emote.clone()));
+        continue;
+      }
+
+      if let Some((prefix, amount)) = cheer(word).filter(|(_, amount)| *amount <= self.bits) {
+        self.bits -= amount;
+        out.push(Fragment::Cheer {
+          prefix: prefix.into(),
+          amount,
+        });
+        continue;
+      }
+
+      match classify(word) {
+        Some((fragment, trailing)) => {
+          out.push(fragment);
+          out.push_text(trailing);
+        }
+        None => out.push_text(word),
+      }
+    }
+  }
+}
+
+/// A cheermote prefix followed by an amount, e.g. `Cheer100`.
+fn cheer(word: &str) -> Option<(&str, u64)> {
+  CHEERMOTES.iter().find_map(|prefix| {
+    let digits = word.get(prefix.len()..)?;
+    let valid = word[..prefix.len()].eq_ignore_ascii_case(prefix)
+      && digits.starts_with(|c: char| matches!(c, '1'..='9'))
+      && digits.bytes().all(|b| b.is_ascii_digit());
+    match valid {
+      true => Some((&word[..prefix.len()], digits.parse().ok()?)),
+      false => None,
+    }
+  })
+}
+
+const TRAILING_PUNCTUATION: [char; 9] = ['.', ',', '!', '?', ')', ':', ';', '"', '\''];
+
+/// Returns the fragment and any trailing punctuation which isn't part of it.
+fn classify(word: &str) -> Option<(Fragment, &str)> {
+  if let Some(login) = word.strip_prefix('@') {
+    let end = login
+      .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+      .unwrap_or(login.len());
+    if end > 0 {
+      return Some((Fragment::Mention(login[..end].into()), &login[end..]));
+    }
+  }
+
+  let trimmed = word.trim_end_matches(TRAILING_PUNCTUATION);
+  if is_url(trimmed) {
+    return Some((Fragment::Url(trimmed.into()), &word[trimmed.len()..]));
+  }
+
+  None
+}
+
+/// Whether any word in `text` would be shown as a link.
+pub fn has_url(text: &str) -> bool {
+  text
+    .split_whitespace()
+    .any(|word| is_url(word.trim_end_matches(TRAILING_PUNCTUATION)))
+}
+
+fn is_url(word: &str) -> bool {
+  let rest = match word.split_once("://") {
+    Some(("http" | "https", rest)) => return !rest.is_empty(),
+    Some(_) => return false,
+    None => word,
+  };
+
+  let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+  let host = host.split_once(':').map(|(host, _)| host).unwrap_or(host);
+  let Some((name, tld)) = host.rsplit_once('.') else {
+    return false;
+  };
+  !name.is_empty()
+    && name
+      .split('.')
+      .all(|label| !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-'))
+    && (2..=24).contains(&tld.len())
+    && tld.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+  #![allow(clippy::single_range_in_vec_init)]
+
+  use rand::rngs::StdRng;
+  use rand::{Rng, SeedableRng};
+
+  use super::*;
+
+  fn emote(id: &str, ranges: &[Range<usize>]) -> TagEmote {
+    TagEmote {
+      id: id.into(),
+      ranges: ranges.to_vec(),
+    }
+  }
+
+  fn text(s: &str) -> Fragment {
+    Fragment::Text(s.into())
+  }
+
+  fn cheer(prefix: &str, amount: u64) -> Fragment {
+    Fragment::Cheer {
+      prefix: prefix.into(),
+      amount,
+    }
+  }
+
+  fn tokenize(text: &str, emotes: &[TagEmote], bits: Option<u64>) -> Vec<Fragment> {
+    let lul = Emote::twitch("lul", "LUL");
+    tokenize_text(text, emotes, bits, |word| (word == "LUL").then_some(&lul))
+  }
+
+  /// The text each fragment was made from.
+  fn source(fragments: &[Fragment]) -> String {
+    fragments
+      .iter()
+      .map(|fragment| match fragment {
+        Fragment::Text(text) | Fragment::Url(text) => text.clone(),
+        Fragment::Emote(emote) => emote.name.clone(),
+        Fragment::Mention(login) => format!("@{login}"),
+        Fragment::Cheer { prefix, amount } => format!("{prefix}{amount}"),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn splits_words() {
+    assert_eq!(
+      tokenize("hi @someone, see https://example.com/a. LUL", &[], None),
+      [
+        text("hi "),
+        Fragment::Mention("someone".into()),
+        text(", see "),
+        Fragment::Url("https://example.com/a".into()),
+        text(". "),
+        Fragment::Emote(Emote::twitch("lul", "LUL")),
+      ]
+    );
+    assert_eq!(
+      tokenize("@ @_ a.b", &[], None),
+      [text("@ "), Fragment::Mention("_".into()), text(" a.b")]
+    );
+  }
+
+  #[test]
+  fn emote_ranges() {
+    let kappa = |name: &str| Fragment::Emote(Emote::twitch("25", name));
+    assert_eq!(
+      tokenize("Kappa hi Kappa", &[emote("25", &[0..5, 9..14])], None),
+      [kappa("Kappa"), text(" hi "), kappa("Kappa")]
+    );
+    // counted in code points
+    assert_eq!(
+      tokenize("é 👋 Kappa", &[emote("25", &[4..9])], None),
+      [text("é 👋 "), kappa("Kappa")]
+    );
+    // counted in UTF-16 code units, where the emoji takes two
+    assert_eq!(
+      tokenize("👋 Kappa", &[emote("25", &[3..8])], None),
+      [text("👋 "), kappa("Kappa")]
+    );
+    // out of bounds, covering whitespace, and overlapping ranges are ignored
+    assert_eq!(
+      tokenize(
+        "Kappa",
+        &[emote("25", &[0..5, 2..4, 3..40]), emote("1", &[0..6])],
+        None
+      ),
+      [kappa("Kappa")]
+    );
+    assert_eq!(
+      tokenize("a Kappa", &[emote("25", &[0..3])], None),
+      [text("a Kappa")]
+    );
+  }
+
+  #[test]
+  fn cheers() {
+    assert_eq!(
+      tokenize("Cheer100 hype cheer1 Kappa50", &[], Some(151)),
+      [
+        cheer("Cheer", 100),
+        text(" hype "),
+        cheer("cheer", 1),
+        text(" "),
+        cheer("Kappa", 50)
+      ]
+    );
+    // not cheermotes
+    assert_eq!(
+      tokenize("abc2024 Cheer100", &[], Some(100)),
+      [text("abc2024 "), cheer("Cheer", 100)]
+    );
+    assert_eq!(
+      tokenize("Cheer01 Cheer1x Cheer", &[], Some(100)),
+      [text("Cheer01 Cheer1x Cheer")]
+    );
+    // more than the message's bits, or a message without bits
+    assert_eq!(
+      tokenize("Kappa123 Cheer5", &[], Some(5)),
+      [text("Kappa123 "), cheer("Cheer", 5)]
+    );
+    assert_eq!(tokenize("Kappa123", &[], None), [text("Kappa123")]);
+  }
+
+  /// Mostly in bounds, counted in UTF-16 code units which is the larger count.
+  fn offset(rng: &mut StdRng, units: usize) -> usize {
+    match rng.gen_range(0..10) {
+      0 => usize::MAX,
+      1 => units + rng.gen_range(0..4),
+      _ => rng.gen_range(0..units + 1),
+    }
+  }
+
+  #[test]
+  fn random_input() {
+    const PIECES: &[&str] = &[
+      "a",
+      "Kappa",
+      "LUL",
+      "Cheer",
+      "1",
+      "0",
+      "99",
+      " ",
+      "\t",
+      "  ",
+      "@",
+      "_",
+      "https://",
+      ".",
+      ",",
+      "com",
+      "é",
+      "👋",
+      "👨‍👩‍👧",
+      "\u{301}",
+      "\u{200d}",
+      "\u{fe0f}",
+      "中",
+    ];
+
+    let mut rng = StdRng::seed_from_u64(0x6e61_6e6f);
+    for _ in 0..5000 {
+      let len = rng.gen_range(0..24);
+      let text = (0..len)
+        .map(|_| PIECES[rng.gen_range(0..PIECES.len())])
+        .collect::<String>();
+      let units = text.encode_utf16().count();
+      let emotes = (0..rng.gen_range(0..3))
+        .map(|id| TagEmote {
+          id: id.to_string(),
+          ranges: (0..rng.gen_range(0..4))
+            .map(|_| offset(&mut rng, units)..offset(&mut rng, units))
+            .collect(),
+        })
+        .collect::<Vec<_>>();
+      let bits = rng.gen_bool(0.5).then(|| rng.gen_range(0..200));
+
+      let fragments = tokenize(&text, &emotes, bits);
+      assert_eq!(source(&fragments), text, "{emotes:?}");
+      let cheered = fragments
+        .iter()
+        .map(|fragment| match fragment {
+          Fragment::Cheer { amount, .. } => *amount,
+          _ => 0,
+        })
+        .sum::<u64>();
+      assert!(cheered <= bits.unwrap_or(0));
+      assert!(!fragments
+        .windows(2)
+        .any(|w| matches!(w, [Fragment::Text(_), Fragment::Text(_)])));
+    }
+  }
+}
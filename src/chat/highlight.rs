@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::event::{PrivMsg, UserNotice, UserNoticeKind};
+use super::history::Message;
+use super::phrase::{CompiledPhrase, Phrase};
+use super::tags::Color;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rule {
+  #[serde(flatten)]
+  pub matcher: Matcher,
+  pub color: Color,
+  /// Also adds matching messages to the Mentions feed.
+  #[serde(default)]
+  pub mentions: bool,
+  #[serde(default = "enabled")]
+  pub enabled: bool,
+  /// Overrides `enabled` in specific channels.
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub channels: HashMap<String, bool>,
+}
+
+fn enabled() -> bool {
+  true
+}
+
+impl Rule {
+  pub fn new(matcher: Matcher, color: Color) -> Self {
+    Self {
+      matcher,
+      color,
+      mentions: false,
+      enabled: true,
+      channels: HashMap::new(),
+    }
+  }
+
+  pub fn with_mentions(mut self, mentions: bool) -> Self {
+    self.mentions = mentions;
+    self
+  }
+
+  pub fn with_channel(mut self, channel: impl ToString, enabled: bool) -> Self {
+    self.channels.insert(channel.to_string(), enabled);
+    self
+  }
+
+  pub fn is_enabled_in(&self, channel: &str) -> bool {
+    self.channels.get(channel).copied().unwrap_or(self.enabled)
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Matcher {
+  Phrase(Phrase),
+  User {
+    login: String,
+  },
+  /// Any version of the badge if `version` is `None`.
+  Badge {
+    name: String,
+    #[serde(default)]
+    version: Option<String>,
+  },
+  MessageType {
+    kind: MessageType,
+  },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageType {
+  FirstMessage,
+  ReturningChatter,
+  Announcement,
+  /// Any kind of sub, resub, or gifted sub.
+  Subscription,
+  Raid,
+  Cheer,
+  Reply,
+  Action,
+}
+
+impl MessageType {
+  fn matches(&self, message: &Message) -> bool {
+    match message {
+      Message::Chat(m) => match self {
+        MessageType::FirstMessage => m.first_msg,
+        MessageType::ReturningChatter => m.returning_chatter,
+        MessageType::Cheer => m.bits.is_some(),
+        MessageType::Reply => m.reply.is_some(),
+        MessageType::Action => m.is_action,
+        _ => false,
+      },
+      Message::UserNotice(m) => match self {
+        MessageType::Announcement => matches!(m.kind, UserNoticeKind::Announcement { .. }),
+        MessageType::Raid => matches!(m.kind, UserNoticeKind::Raid { .. }),
+        MessageType::Subscription => matches!(
+          m.kind,
+          UserNoticeKind::Sub { .. }
+            | UserNoticeKind::Resub { .. }
+            | UserNoticeKind::SubGift { .. }
+            | UserNoticeKind::SubMysteryGift { .. }
+            | UserNoticeKind::GiftPaidUpgrade
+        ),
+        _ => false,
+      },
+      Message::System { .. } => false,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Highlight {
+  pub color: Color,
+  pub mention: bool,
+}
+
+/// Compiled highlight rules.
+#[derive(Default)]
+pub struct Highlighter {
+  rules: Vec<(Rule, Option<CompiledPhrase>)>,
+}
+
+impl Highlighter {
+  /// Rules with an invalid pattern are skipped, use `compile` to check them first.
+  pub fn new(rules: &[Rule]) -> Self {
+    let rules = rules
+      .iter()
+      .filter_map(|rule| match compile(&rule.matcher) {
+        Ok(phrase) => Some((rule.clone(), phrase)),
+        Err(e) => {
+          tracing::warn!(?rule.matcher, "invalid highlight pattern: {e}");
+          None
+        }
+      })
+      .collect();
+    Self { rules }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.rules.is_empty()
+  }
+
+  /// The color of the first matching rule, and whether any matching rule adds the message to
+  /// the Mentions feed.
+  pub fn check(&self, channel: &str, message: &Message) -> Option<Highlight> {
+    let mut highlight = None::<Highlight>;
+    for (rule, phrase) in &self.rules {
+      if !rule.is_enabled_in(channel) || !matches(&rule.matcher, phrase.as_ref(), message) {
+        continue;
+      }
+      match &mut highlight {
+        Some(highlight) => highlight.mention |= rule.mentions,
+        None => {
+          highlight = Some(Highlight {
+            color: rule.color,
+            mention: rule.mentions,
+          })
+        }
+      }
+    }
+    highlight
+  }
+}
+
+/// Compiles phrase matchers, and `None` for everything else.
+pub fn compile(matcher: &Matcher) -> Result<Option<CompiledPhrase>, regex::Error> {
+  match matcher {
+    Matcher::Phrase(phrase) => phrase.compile().map(Some),
+    _ => Ok(None),
+  }
+}
+
+fn matches(matcher: &Matcher, phrase: Option<&CompiledPhrase>, message: &Message) -> bool {
+  let (author, text) = match message {
+    Message::Chat(PrivMsg { author, text, .. }) => (author, Some(text.as_str())),
+    Message::UserNotice(UserNotice { author, text, .. }) => (author, text.as_deref()),
+    Message::System { .. } => return false,
+  };
+
+  match matcher {
+    Matcher::Phrase(_) => match (phrase, text) {
+      (Some(phrase), Some(text)) => phrase.is_match(text),
+      _ => false,
+    },
+    Matcher::User { login } => author.login.eq_ignore_ascii_case(login),
+    Matcher::Badge { name, version } => author
+      .badges
+      .iter()
+      .any(|badge| badge.name == *name && version.iter().all(|v| badge.version == *v)),
+    Matcher::MessageType { kind } => kind.matches(message),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::event::ChatEvent;
+  use super::super::history::History;
+  use super::*;
+
+  const RED: Color = Color { r: 255, g: 0, b: 0 };
+  const BLUE: Color = Color { r: 0, g: 0, b: 255 };
+
+  fn message(tags: &str, login: &str, text: &str) -> Message {
+    let line = format!(
+      "@badges={tags};color=;display-name={login};emotes=;id={login}-{};room-id=1;tmi-sent-ts=1507246572675;user-id=2 :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #forsen :{text}",
+      text.len()
+    );
+    match ChatEvent::parse(&twitch::parse(line).unwrap()) {
+      Some(ChatEvent::PrivMsg(m)) => Message::Chat(m),
+      _ => panic!("expected a PRIVMSG"),
+    }
+  }
+
+  fn check(rules: &[Rule], message: &Message) -> Option<Highlight> {
+    Highlighter::new(rules).check("forsen", message)
+  }
+
+  #[test]
+  fn phrase() {
+    let rules = [Rule::new(Matcher::Phrase(Phrase::plain("nanochat")), RED)];
+    let highlight = Some(Highlight {
+      color: RED,
+      mention: false,
+    });
+    assert_eq!(check(&rules, &message("", "a", "hi NanoChat!")), highlight);
+    assert_eq!(check(&rules, &message("", "a", "nanochats")), None);
+    assert_eq!(check(&rules, &message("", "nanochat", "hi")), None);
+
+    let rules = [Rule::new(Matcher::Phrase(Phrase::regex(r"^!\w+")), RED)];
+    assert!(check(&rules, &message("", "a", "!uptime")).is_some());
+    assert!(check(&rules, &message("", "a", "see !uptime")).is_none());
+    // an invalid pattern is skipped instead of matching everything
+    let rules = [Rule::new(Matcher::Phrase(Phrase::regex("(")), RED)];
+    assert!(Highlighter::new(&rules).is_empty());
+  }
+
+  #[test]
+  fn user_and_badge() {
+    let rules = [
+      Rule::new(
+        Matcher::User {
+          login: "Forsen".into(),
+        },
+        RED,
+      ),
+      Rule::new(
+        Matcher::Badge {
+          name: "subscriber".into(),
+          version: Some("12".into()),
+        },
+        BLUE,
+      ),
+      Rule::new(
+        Matcher::Badge {
+          name: "vip".into(),
+          version: None,
+        },
+        BLUE,
+      ),
+    ];
+    let color = |m: Message| check(&rules, &m).map(|h| h.color);
+    assert_eq!(color(message("", "forsen", "hi")), Some(RED));
+    assert_eq!(color(message("subscriber/12", "forsen", "hi")), Some(RED));
+    assert_eq!(color(message("subscriber/12", "a", "hi")), Some(BLUE));
+    assert_eq!(color(message("subscriber/6", "a", "hi")), None);
+    assert_eq!(color(message("vip/1,premium/1", "a", "hi")), Some(BLUE));
+    assert_eq!(color(message("", "a", "hi")), None);
+    assert_eq!(color(Message::system("forsen")), None);
+  }
+
+  #[test]
+  fn channels() {
+    let mut rule = Rule::new(Matcher::User { login: "a".into() }, RED).with_channel("xqc", true);
+    let message = message("", "a", "hi");
+    assert!(Highlighter::new(&[rule.clone()])
+      .check("xqc", &message)
+      .is_some());
+    rule.enabled = false;
+    assert!(Highlighter::new(&[rule.clone()])
+      .check("forsen", &message)
+      .is_none());
+    assert!(Highlighter::new(&[rule.clone()])
+      .check("xqc", &message)
+      .is_some());
+    rule = rule.with_channel("xqc", false);
+    assert!(Highlighter::new(&[rule]).check("xqc", &message).is_none());
+  }
+
+  #[test]
+  fn mentions_feed() {
+    let rules = [
+      Rule::new(Matcher::User { login: "a".into() }, RED),
+      Rule::new(Matcher::Phrase(Phrase::plain("me")), BLUE).with_mentions(true),
+    ];
+    let mut history = History::new(3);
+    history.set_highlighter(Highlighter::new(&rules));
+
+    history.push("forsen", message("", "b", "not a mention"));
+    let seq = history.push("forsen", message("", "a", "hi me"));
+    // the first matching rule picks the color, any of them can add the mention
+    let expected = Highlight {
+      color: RED,
+      mention: true,
+    };
+    let entry = history.channel("forsen").unwrap().get(seq).unwrap();
+    assert_eq!(entry.highlight, Some(expected));
+    assert_eq!(history.mentions().len(), 1);
+    assert_eq!(history.mentions().next().unwrap().highlight, expected);
+
+    // capped like the channels
+    for text in ["me too", "and me", "me again"] {
+      history.push("xqc", message("", "b", text));
+    }
+    let mentions = history
+      .mentions()
+      .map(|m| m.channel.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(mentions, ["xqc", "xqc", "xqc"]);
+
+    // historical messages are highlighted, but not mentions
+    let ChatEvent::PrivMsg(old) = ChatEvent::parse(
+      &twitch::parse(
+        "@badges=;id=old;room-id=1;tmi-sent-ts=1;user-id=3 :c!c@c.tmi.twitch.tv PRIVMSG #forsen :me".into(),
+      )
+      .unwrap(),
+    )
+    .unwrap() else {
+      unreachable!()
+    };
+    history.backfill("forsen", &[ChatEvent::PrivMsg(old)]);
+    let entry = history.channel("forsen").unwrap().by_id("old").unwrap();
+    assert_eq!(entry.highlight.map(|h| h.color), Some(BLUE));
+    assert_eq!(history.mentions().len(), 3);
+  }
+}
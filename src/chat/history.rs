@@ -0,0 +1,789 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::event::{ChatEvent, ClearChat, ClearMsg, PrivMsg, ReplyParent, UserNotice};
+use super::highlight::{Highlight, Highlighter};
+
+pub const DEFAULT_CAP: usize = 1000;
+
+/// Live messages are numbered from here, so that historical messages can be added in front of
+/// them without renumbering anything.
+const FIRST_LIVE_SEQ: u64 = 1 << 32;
+
+#[derive(Clone, Debug)]
+pub enum Message {
+  Chat(PrivMsg),
+  UserNotice(UserNotice),
+  /// Generated locally, e.g. an error while executing a command.
+  System {
+    text: String,
+    timestamp: DateTime<Utc>,
+  },
+}
+
+impl Message {
+  pub fn system(text: impl ToString) -> Self {
+    Message::System {
+      text: text.to_string(),
+      timestamp: Utc::now(),
+    }
+  }
+
+  pub fn id(&self) -> Option<&str> {
+    match self {
+      Message::Chat(m) => Some(&m.id),
+      Message::UserNotice(m) => Some(&m.id),
+      Message::System { .. } => None,
+    }
+  }
+
+  pub fn user_id(&self) -> Option<&str> {
+    match self {
+      Message::Chat(m) => Some(&m.author.id),
+      Message::UserNotice(m) => Some(&m.author.id),
+      Message::System { .. } => None,
+    }
+  }
+
+  pub fn login(&self) -> Option<&str> {
+    match self {
+      Message::Chat(m) => Some(&m.author.login),
+      Message::UserNotice(m) => Some(&m.author.login),
+      Message::System { .. } => None,
+    }
+  }
+
+  pub fn reply(&self) -> Option<&ReplyParent> {
+    match self {
+      Message::Chat(m) => m.reply.as_ref(),
+      _ => None,
+    }
+  }
+
+  pub fn timestamp(&self) -> DateTime<Utc> {
+    match self {
+      Message::Chat(m) => m.timestamp,
+      Message::UserNotice(m) => m.timestamp,
+      Message::System { timestamp, .. } => *timestamp,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Moderation {
+  /// Removed by `CLEARMSG`.
+  Deleted,
+  TimedOut(Duration),
+  Banned,
+  /// Removed by a `CLEARCHAT` without a target.
+  Cleared,
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+  /// Increases by one from each entry to the next, and never changes while the entry is in the
+  /// history.
+  pub seq: u64,
+  pub message: Message,
+  pub moderation: Option<Moderation>,
+  pub highlight: Option<Highlight>,
+  /// Sent before we joined the channel or while we were disconnected.
+  pub historical: bool,
+}
+
+/// A message which was added to the Mentions feed by a highlight rule.
+#[derive(Clone, Debug)]
+pub struct Mention {
+  pub channel: String,
+  pub message: Message,
+  pub highlight: Highlight,
+}
+
+/// The most recent messages of a single channel.
+pub struct ChannelHistory {
+  entries: VecDeque<Entry>,
+  next_seq: u64,
+  cap: usize,
+  by_id: HashMap<String, u64>,
+  by_user: HashMap<String, VecDeque<u64>>,
+  /// Ids of the replies in each thread, by the id of the thread's root message.
+  threads: HashMap<String, Vec<String>>,
+}
+
+impl ChannelHistory {
+  pub fn new(cap: usize) -> Self {
+    Self {
+      entries: VecDeque::with_capacity(cap.min(DEFAULT_CAP)),
+      next_seq: FIRST_LIVE_SEQ,
+      cap: cap.max(1),
+      by_id: HashMap::new(),
+      by_user: HashMap::new(),
+      threads: HashMap::new(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry> + ExactSizeIterator + '_ {
+    self.entries.iter()
+  }
+
+  pub fn get(&self, seq: u64) -> Option<&Entry> {
+    let first = self.entries.front()?.seq;
+    self.entries.get(seq.checked_sub(first)? as usize)
+  }
+
+  fn get_mut(&mut self, seq: u64) -> Option<&mut Entry> {
+    let first = self.entries.front()?.seq;
+    self.entries.get_mut(seq.checked_sub(first)? as usize)
+  }
+
+  pub fn by_id(&self, id: &str) -> Option<&Entry> {
+    self.get(*self.by_id.get(id)?)
+  }
+
+  /// Messages from `user_id` which are still in the history, oldest first.
+  pub fn by_user<'a>(&'a self, user_id: &str) -> impl Iterator<Item = &'a Entry> + 'a {
+    self
+      .by_user
+      .get(user_id)
+      .into_iter()
+      .flatten()
+      .filter_map(|seq| self.get(*seq))
+  }
+
+  /// The message `entry` replied to, if it is still in the history.
+  pub fn parent(&self, entry: &Entry) -> Option<&Entry> {
+    self.by_id(&entry.message.reply()?.msg_id)
+  }
+
+  /// The root of the thread `entry` belongs to, which may be `entry` itself.
+  pub fn thread_root<'a>(&'a self, entry: &'a Entry) -> Option<&'a str> {
+    match entry.message.reply() {
+      Some(reply) => Some(reply.root()),
+      None => entry
+        .message
+        .id()
+        .filter(|id| self.threads.contains_key(*id)),
+    }
+  }
+
+  /// The root message followed by every reply in the thread, as far as they are still in the
+  /// history.
+  pub fn thread<'a>(&'a self, root_id: &'a str) -> impl Iterator<Item = &'a Entry> + 'a {
+    let replies = self.threads.get(root_id).into_iter().flatten();
+    self
+      .by_id(root_id)
+      .into_iter()
+      .chain(replies.filter_map(|id| self.by_id(id)))
+  }
+
+  pub fn push(&mut self, message: Message) -> u64 {
+    if self.entries.len() == self.cap {
+      self.evict();
+    }
+
+    let seq = self.next_seq;
+    self.next_seq += 1;
+    if let Some(id) = message.id() {
+      self.by_id.insert(id.into(), seq);
+      if let Some(reply) = message.reply() {
+        self
+          .threads
+          .entry(reply.root().into())
+          .or_default()
+          .push(id.into());
+      }
+    }
+    if let Some(user_id) = message.user_id().filter(|id| !id.is_empty()) {
+      self
+        .by_user
+        .entry(user_id.into())
+        .or_default()
+        .push_back(seq);
+    }
+    self.entries.push_back(Entry {
+      seq,
+      message,
+      moderation: None,
+      highlight: None,
+      historical: false,
+    });
+    seq
+  }
+
+  /// Merges older messages into the history and marks them as historical. Messages which are
+  /// already in the history are skipped.
+  ///
+  /// Messages older than the oldest entry are added in front of it, and messages newer than the
+  /// newest entry after it, so the sequence numbers of existing entries stay valid. Messages which
+  /// fall between existing entries are dropped.
+  pub fn backfill(&mut self, messages: Vec<(Message, Option<Highlight>)>) {
+    let mut seen = HashSet::new();
+    let mut messages = messages
+      .into_iter()
+      .filter(|(message, _)| match message.id() {
+        Some(id) => !self.by_id.contains_key(id) && seen.insert(id.to_string()),
+        None => true,
+      })
+      .collect::<Vec<_>>();
+    // stable, so that messages with the same timestamp keep their order
+    messages.sort_by_key(|(message, _)| message.timestamp());
+
+    let range = self
+      .entries
+      .front()
+      .zip(self.entries.back())
+      .map(|(first, last)| (first.message.timestamp(), last.message.timestamp()));
+    let (older, newer) = match range {
+      Some((first, last)) => {
+        let older = messages.partition_point(|(m, _)| m.timestamp() < first);
+        let newer = messages.partition_point(|(m, _)| m.timestamp() < last);
+        if newer > older {
+          tracing::debug!(
+            "dropped {} recent messages between live ones",
+            newer - older
+          );
+        }
+        let newer = messages.split_off(newer);
+        messages.truncate(older);
+        (messages, newer)
+      }
+      None => (Vec::new(), messages),
+    };
+
+    // newest first, until the history is full
+    for (message, highlight) in older.into_iter().rev() {
+      if self.entries.len() == self.cap {
+        break;
+      }
+      let Some(seq) = self.push_front(message) else {
+        break;
+      };
+      if let Some(entry) = self.get_mut(seq) {
+        entry.highlight = highlight;
+        entry.historical = true;
+      }
+    }
+    for (message, highlight) in newer {
+      let seq = self.push(message);
+      if let Some(entry) = self.get_mut(seq) {
+        entry.highlight = highlight;
+        entry.historical = true;
+      }
+    }
+  }
+
+  /// Adds `message` before the oldest entry, unless the sequence numbers in front of it are used
+  /// up.
+  fn push_front(&mut self, message: Message) -> Option<u64> {
+    let seq = match self.entries.front() {
+      Some(first) => first.seq.checked_sub(1)?,
+      None => self.next_seq,
+    };
+    if self.entries.is_empty() {
+      self.next_seq += 1;
+    }
+    if let Some(id) = message.id() {
+      self.by_id.insert(id.into(), seq);
+      if let Some(reply) = message.reply() {
+        self
+          .threads
+          .entry(reply.root().into())
+          .or_default()
+          .insert(0, id.into());
+      }
+    }
+    if let Some(user_id) = message.user_id().filter(|id| !id.is_empty()) {
+      self
+        .by_user
+        .entry(user_id.into())
+        .or_default()
+        .push_front(seq);
+    }
+    self.entries.push_front(Entry {
+      seq,
+      message,
+      moderation: None,
+      highlight: None,
+      historical: false,
+    });
+    Some(seq)
+  }
+
+  pub fn set_cap(&mut self, cap: usize) {
+    self.cap = cap.max(1);
+    while self.entries.len() > self.cap {
+      self.evict();
+    }
+  }
+
+  fn evict(&mut self) {
+    let Some(entry) = self.entries.pop_front() else {
+      return;
+    };
+    if let Some(id) = entry.message.id() {
+      self.by_id.remove(id);
+      if let Some(reply) = entry.message.reply() {
+        if let Some(replies) = self.threads.get_mut(reply.root()) {
+          replies.retain(|reply_id| reply_id != id);
+          if replies.is_empty() {
+            self.threads.remove(reply.root());
+          }
+        }
+      }
+    }
+    if let Some(user_id) = entry.message.user_id() {
+      if let Some(seqs) = self.by_user.get_mut(user_id) {
+        seqs.retain(|seq| *seq != entry.seq);
+        if seqs.is_empty() {
+          self.by_user.remove(user_id);
+        }
+      }
+    }
+  }
+
+  pub fn clear_msg(&mut self, event: &ClearMsg) {
+    if let Some(seq) = self.by_id.get(&event.target_msg_id).copied() {
+      if let Some(entry) = self.get_mut(seq) {
+        entry.moderation = Some(Moderation::Deleted);
+      }
+    }
+  }
+
+  pub fn clear_chat(&mut self, event: &ClearChat) {
+    // backfilled events may arrive after newer messages
+    let before = |entry: &Entry| entry.message.timestamp() <= event.timestamp;
+    let Some(target) = &event.target else {
+      for entry in self.entries.iter_mut().filter(|e| before(e)) {
+        entry.moderation.get_or_insert(Moderation::Cleared);
+      }
+      return;
+    };
+
+    let moderation = match target.duration {
+      Some(duration) => Moderation::TimedOut(duration),
+      None => Moderation::Banned,
+    };
+    match self.by_user.get(&target.user_id).cloned() {
+      Some(seqs) => {
+        for seq in seqs {
+          if let Some(entry) = self.get_mut(seq).filter(|e| before(e)) {
+            entry.moderation = Some(moderation);
+          }
+        }
+      }
+      // older messages may not have a `user-id`, fall back to the login
+      None => {
+        for entry in self.entries.iter_mut().filter(|e| before(e)) {
+          if entry.message.login() == Some(target.login.as_str()) {
+            entry.moderation = Some(moderation);
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Message history of every channel.
+pub struct History {
+  channels: HashMap<String, ChannelHistory>,
+  cap: usize,
+  highlighter: Highlighter,
+  mentions: VecDeque<Mention>,
+}
+
+impl History {
+  pub fn new(cap: usize) -> Self {
+    Self {
+      channels: HashMap::new(),
+      cap,
+      highlighter: Highlighter::default(),
+      mentions: VecDeque::new(),
+    }
+  }
+
+  /// Only applies to messages pushed after this call.
+  pub fn set_highlighter(&mut self, highlighter: Highlighter) {
+    self.highlighter = highlighter;
+  }
+
+  /// Highlighted messages from every channel, oldest first.
+  pub fn mentions(&self) -> impl DoubleEndedIterator<Item = &Mention> + ExactSizeIterator + '_ {
+    self.mentions.iter()
+  }
+
+  pub fn set_cap(&mut self, cap: usize) {
+    self.cap = cap;
+    for channel in self.channels.values_mut() {
+      channel.set_cap(cap);
+    }
+  }
+
+  pub fn channel(&self, channel: &str) -> Option<&ChannelHistory> {
+    self.channels.get(channel)
+  }
+
+  pub fn channels(&self) -> impl Iterator<Item = (&str, &ChannelHistory)> + '_ {
+    self.channels.iter().map(|(k, v)| (k.as_str(), v))
+  }
+
+  pub fn push(&mut self, channel: &str, message: Message) -> u64 {
+    let highlight = self.highlighter.check(channel, &message);
+    if let Some(highlight) = highlight.filter(|h| h.mention) {
+      if self.mentions.len() == self.cap.max(1) {
+        self.mentions.pop_front();
+      }
+      self.mentions.push_back(Mention {
+        channel: channel.into(),
+        message: message.clone(),
+        highlight,
+      });
+    }
+
+    let cap = self.cap;
+    let history = self
+      .channels
+      .entry(channel.into())
+      .or_insert_with(|| ChannelHistory::new(cap));
+    let seq = history.push(message);
+    if let Some(entry) = history.get_mut(seq) {
+      entry.highlight = highlight;
+    }
+    seq
+  }
+
+  pub fn remove(&mut self, channel: &str) {
+    self.channels.remove(channel);
+  }
+
+  /// Adds messages which were sent before we joined `channel` or while we were disconnected,
+  /// then applies the `CLEARMSG` and `CLEARCHAT` events among them. Historical messages are
+  /// highlighted, but don't show up in Mentions.
+  pub fn backfill(&mut self, channel: &str, events: &[ChatEvent]) {
+    let messages = events
+      .iter()
+      .filter_map(|event| match event {
+        ChatEvent::PrivMsg(m) => Some(Message::Chat(m.clone())),
+        ChatEvent::UserNotice(m) => Some(Message::UserNotice(m.clone())),
+        _ => None,
+      })
+      .map(|message| {
+        let highlight = self.highlighter.check(channel, &message);
+        (message, highlight)
+      })
+      .collect();
+
+    let cap = self.cap;
+    let history = self
+      .channels
+      .entry(channel.into())
+      .or_insert_with(|| ChannelHistory::new(cap));
+    history.backfill(messages);
+    for event in events {
+      match event {
+        ChatEvent::ClearMsg(e) => history.clear_msg(e),
+        ChatEvent::ClearChat(e) => history.clear_chat(e),
+        _ => {}
+      }
+    }
+  }
+
+  /// Stores chat messages and applies `CLEARMSG` and `CLEARCHAT`. Other events are ignored.
+  pub fn handle(&mut self, event: &ChatEvent) {
+    match event {
+      ChatEvent::PrivMsg(m) => {
+        self.push(&m.channel, Message::Chat(m.clone()));
+      }
+      ChatEvent::UserNotice(m) => {
+        self.push(&m.channel, Message::UserNotice(m.clone()));
+      }
+      ChatEvent::ClearMsg(e) => {
+        if let Some(channel) = self.channels.get_mut(&e.channel) {
+          channel.clear_msg(e);
+        }
+      }
+      ChatEvent::ClearChat(e) => {
+        if let Some(channel) = self.channels.get_mut(&e.channel) {
+          channel.clear_chat(e);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::collapse::{CollapseConfig, Collapser};
+  use super::*;
+
+  fn event(line: &str) -> ChatEvent {
+    ChatEvent::parse(&twitch::parse(line.into()).unwrap()).unwrap()
+  }
+
+  /// A message from `login`, with `tags` added to the usual ones.
+  fn chat(login: &str, user_id: &str, id: &str, timestamp: u64, tags: &str) -> Message {
+    let line = format!(
+      "@badges=;color=;display-name={login};emotes=;id={id};room-id=1;tmi-sent-ts={timestamp};user-id={user_id}{tags} :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #forsen :{id}"
+    );
+    match event(&line) {
+      ChatEvent::PrivMsg(m) => Message::Chat(m),
+      _ => panic!("expected a PRIVMSG"),
+    }
+  }
+
+  fn message(id: &str, user_id: &str, timestamp: u64) -> Message {
+    chat("a", user_id, id, timestamp, "")
+  }
+
+  fn moderation(history: &ChannelHistory) -> Vec<(&str, Option<Moderation>)> {
+    history
+      .iter()
+      .map(|e| (e.message.id().unwrap(), e.moderation))
+      .collect()
+  }
+
+  fn ids(history: &ChannelHistory) -> Vec<(u64, &str, bool)> {
+    history
+      .iter()
+      .map(|e| (e.seq, e.message.id().unwrap(), e.historical))
+      .collect()
+  }
+
+  #[test]
+  fn backfill_keeps_seqs() {
+    let mut history = ChannelHistory::new(5);
+    let live = history.push(message("c", "1", 3000));
+    history.push(message("e", "2", 5000));
+
+    history.backfill(
+      ["f", "b", "d", "a", "c"]
+        .iter()
+        .zip([6000, 2000, 4000, 1000, 3000])
+        .map(|(id, timestamp)| (message(id, "1", timestamp), None))
+        .collect(),
+    );
+    // `d` falls between live messages, `c` is a duplicate
+    assert_eq!(
+      ids(&history),
+      [
+        (live - 2, "a", true),
+        (live - 1, "b", true),
+        (live, "c", false),
+        (live + 1, "e", false),
+        (live + 2, "f", true),
+      ]
+    );
+    assert_eq!(history.by_id("a").unwrap().seq, live - 2);
+    let seqs = history.by_user("1").map(|e| e.seq).collect::<Vec<_>>();
+    assert_eq!(seqs, [live - 2, live - 1, live, live + 2]);
+
+    // only the newest historical messages fit
+    let mut history = ChannelHistory::new(3);
+    history.push(message("c", "1", 3000));
+    history.push(message("d", "1", 4000));
+    history.backfill(vec![
+      (message("a", "1", 1000), None),
+      (message("b", "1", 2000), None),
+    ]);
+    let ids = ids(&history);
+    assert_eq!(
+      ids.iter().map(|(_, id, _)| *id).collect::<Vec<_>>(),
+      ["b", "c", "d"]
+    );
+  }
+
+  #[test]
+  fn backfill_rebuilds_rows() {
+    let mut history = ChannelHistory::new(10);
+    history.push(message("b", "1", 2000));
+    let mut collapser = Collapser::new(&CollapseConfig::default());
+    collapser.update(&history);
+
+    history.backfill(vec![(message("a", "1", 1000), None)]);
+    history.push(message("c", "1", 3000));
+    collapser.update(&history);
+    let rows = collapser
+      .rows()
+      .iter()
+      .map(|row| row.seq)
+      .collect::<Vec<_>>();
+    let seqs = history.iter().map(|e| e.seq).collect::<Vec<_>>();
+    assert_eq!(rows, seqs);
+  }
+
+  #[test]
+  fn deletes_messages() {
+    let mut history = History::new(10);
+    history.push("forsen", chat("ronni", "1", "a", 1000, ""));
+    history.push("forsen", chat("ronni", "1", "b", 2000, ""));
+    history.handle(&event(
+      "@login=ronni;room-id=;target-msg-id=a;tmi-sent-ts=3000 :tmi.twitch.tv CLEARMSG #forsen :a",
+    ));
+    // other channels are left alone
+    history.handle(&event(
+      "@login=ronni;room-id=;target-msg-id=b;tmi-sent-ts=3000 :tmi.twitch.tv CLEARMSG #nymn :b",
+    ));
+    let channel = history.channel("forsen").unwrap();
+    assert_eq!(
+      moderation(channel),
+      [("a", Some(Moderation::Deleted)), ("b", None)]
+    );
+  }
+
+  #[test]
+  fn times_out_older_messages() {
+    let mut history = History::new(10);
+    history.push("forsen", chat("ronni", "1", "a", 1000, ""));
+    history.push("forsen", chat("pajlada", "2", "b", 1500, ""));
+    history.push("forsen", chat("ronni", "1", "c", 3000, ""));
+    // without a `user-id`, e.g. from an old log
+    history.push("forsen", chat("nymn", "", "d", 1000, ""));
+    history.handle(&event(
+      "@ban-duration=600;room-id=1;target-user-id=1;tmi-sent-ts=2000 :tmi.twitch.tv CLEARCHAT #forsen :ronni",
+    ));
+    history.handle(&event(
+      "@room-id=1;target-user-id=3;tmi-sent-ts=2000 :tmi.twitch.tv CLEARCHAT #forsen :nymn",
+    ));
+    let channel = history.channel("forsen").unwrap();
+    assert_eq!(
+      moderation(channel),
+      [
+        ("a", Some(Moderation::TimedOut(Duration::from_secs(600)))),
+        ("b", None),
+        // sent after the timeout ended
+        ("c", None),
+        ("d", Some(Moderation::Banned)),
+      ]
+    );
+  }
+
+  #[test]
+  fn clears_chat() {
+    let mut history = History::new(10);
+    history.push("forsen", chat("ronni", "1", "a", 1000, ""));
+    history.push("forsen", chat("ronni", "1", "b", 2000, ""));
+    history.push("forsen", chat("ronni", "1", "c", 4000, ""));
+    history.handle(&event(
+      "@login=ronni;room-id=;target-msg-id=a;tmi-sent-ts=1500 :tmi.twitch.tv CLEARMSG #forsen :a",
+    ));
+    history.handle(&event(
+      "@room-id=1;tmi-sent-ts=3000 :tmi.twitch.tv CLEARCHAT #forsen",
+    ));
+    let channel = history.channel("forsen").unwrap();
+    assert_eq!(
+      moderation(channel),
+      [
+        // a deleted message stays deleted
+        ("a", Some(Moderation::Deleted)),
+        ("b", Some(Moderation::Cleared)),
+        ("c", None),
+      ]
+    );
+  }
+
+  #[test]
+  fn evicts_oldest() {
+    let mut history = ChannelHistory::new(3);
+    history.push(chat("ronni", "1", "a", 1000, ""));
+    history.push(chat("pajlada", "2", "b", 2000, ""));
+    history.push(chat("ronni", "1", "c", 3000, ""));
+    history.push(chat("ronni", "1", "d", 4000, ""));
+    assert_eq!(history.len(), 3);
+    assert!(history.by_id("a").is_none());
+    let ids = history
+      .by_user("1")
+      .map(|e| e.message.id().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(ids, ["c", "d"]);
+
+    history.set_cap(1);
+    assert_eq!(history.len(), 1);
+    // no index refers to evicted messages
+    assert_eq!(history.by_id.keys().collect::<Vec<_>>(), ["d"]);
+    assert_eq!(
+      history.by_user.iter().collect::<Vec<_>>(),
+      [(&"1".to_string(), &VecDeque::from([history.by_id["d"]]))]
+    );
+    assert!(history.threads.is_empty());
+  }
+
+  fn reply(id: &str, parent: &str, root: &str, timestamp: u64) -> Message {
+    let tags = format!(
+      ";reply-parent-msg-id={parent};reply-parent-user-login=a;reply-thread-parent-msg-id={root}"
+    );
+    chat("b", "2", id, timestamp, &tags)
+  }
+
+  fn thread<'a>(history: &'a ChannelHistory, root: &'a str) -> Vec<&'a str> {
+    history
+      .thread(root)
+      .map(|e| e.message.id().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn links_threads() {
+    let mut history = ChannelHistory::new(10);
+    history.push(message("r", "1", 1000));
+    history.push(message("other", "1", 1500));
+    history.push(reply("x", "r", "r", 2000));
+    history.push(reply("y", "x", "r", 3000));
+    assert_eq!(thread(&history, "r"), ["r", "x", "y"]);
+
+    let root = history.by_id("r").unwrap();
+    let y = history.by_id("y").unwrap();
+    assert_eq!(history.thread_root(root), Some("r"));
+    assert_eq!(history.thread_root(y), Some("r"));
+    assert_eq!(history.parent(y).unwrap().message.id(), Some("x"));
+    // messages without replies aren't threads
+    let other = history.by_id("other").unwrap();
+    assert_eq!(history.thread_root(other), None);
+  }
+
+  #[test]
+  fn backfills_threads() {
+    let mut history = ChannelHistory::new(10);
+    history.push(reply("x", "r", "r", 3000));
+    assert_eq!(thread(&history, "r"), ["x"]);
+    let x = history.by_id("x").unwrap();
+    assert!(history.parent(x).is_none());
+
+    // the root and an earlier reply arrive later, through recent messages
+    history.backfill(vec![
+      (message("r", "1", 1000), None),
+      (reply("w", "r", "r", 2000), None),
+    ]);
+    assert_eq!(thread(&history, "r"), ["r", "w", "x"]);
+    let x = history.by_id("x").unwrap();
+    assert_eq!(history.parent(x).unwrap().message.id(), Some("r"));
+  }
+
+  #[test]
+  fn evicts_threads() {
+    let mut history = ChannelHistory::new(2);
+    history.push(message("r", "1", 1000));
+    history.push(reply("x", "r", "r", 2000));
+    history.push(reply("y", "r", "r", 3000));
+    // the replies outlive their root
+    assert_eq!(thread(&history, "r"), ["x", "y"]);
+    let x = history.by_id("x").unwrap();
+    assert!(history.parent(x).is_none());
+    assert_eq!(history.thread_root(x), Some("r"));
+
+    history.push(message("a", "1", 4000));
+    assert_eq!(thread(&history, "r"), ["y"]);
+    history.push(message("b", "1", 5000));
+    assert!(thread(&history, "r").is_empty());
+    assert!(history.threads.is_empty());
+  }
+}
@@ -0,0 +1,562 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Days, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::command::format_duration;
+use super::event::{ChatEvent, UserNotice};
+use super::tags::Tags;
+use crate::util::data_dir;
+
+/// Lines are collected for up to this long before they are written and synced.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Larger batches are written right away.
+const MAX_BATCH: usize = 512;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+  /// `[HH:MM:SS] name: text`, like Chatterino.
+  #[default]
+  Text,
+  /// One JSON object per line, with every tag of the IRC message.
+  Jsonl,
+}
+
+impl LogFormat {
+  fn extension(self) -> &'static str {
+    match self {
+      LogFormat::Text => "log",
+      LogFormat::Jsonl => "jsonl",
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+  /// Channels which are logged, nothing is logged by default. Only messages received live are
+  /// logged, not the recent messages loaded on join or after a reconnect.
+  pub channels: Vec<String>,
+  pub format: LogFormat,
+  /// Log files older than this are deleted, they are kept forever if `None`.
+  pub retention_days: Option<u32>,
+  /// `logs` in the data directory if `None`.
+  pub dir: Option<PathBuf>,
+}
+
+impl LogConfig {
+  pub fn dir(&self) -> Option<PathBuf> {
+    self
+      .dir
+      .clone()
+      .or_else(|| data_dir().map(|dir| dir.join("logs")))
+  }
+}
+
+/// A line to append to a log file.
+struct Record {
+  path: PathBuf,
+  format: LogFormat,
+  line: String,
+}
+
+/// Writes chat messages of the logged channels to one file per channel and day, laid out like
+/// Chatterino's logs: `<dir>/Twitch/Channels/<channel>/<channel>-YYYY-MM-DD.log`.
+///
+/// Files are written on a background thread, which is joined when this is dropped.
+pub struct Logs {
+  dir: Option<PathBuf>,
+  format: LogFormat,
+  channels: HashSet<String>,
+  records: Option<mpsc::Sender<Record>>,
+  writer: Option<JoinHandle<()>>,
+}
+
+impl Logs {
+  pub fn start(config: &LogConfig) -> Self {
+    let dir = config.dir();
+    let (records, writer) = match &dir {
+      Some(dir) => {
+        let (tx, rx) = mpsc::channel();
+        let mut writer = Writer::new(dir.clone(), config.retention_days);
+        let spawned = thread::Builder::new()
+          .name("logs".into())
+          .spawn(move || writer.run(rx));
+        match spawned {
+          Ok(handle) => (Some(tx), Some(handle)),
+          Err(e) => {
+            tracing::error!("failed to spawn log thread, logging is disabled: {e}");
+            (None, None)
+          }
+        }
+      }
+      None => (None, None),
+    };
+    Self {
+      dir,
+      format: config.format,
+      channels: config.channels.iter().cloned().collect(),
+      records,
+      writer,
+    }
+  }
+
+  pub fn is_enabled(&self, channel: &str) -> bool {
+    self.channels.contains(channel)
+  }
+
+  pub fn set_enabled(&mut self, channel: &str, enabled: bool) {
+    match enabled {
+      true => self.channels.insert(channel.into()),
+      false => self.channels.remove(channel),
+    };
+  }
+
+  /// Logs `event` if its channel is logged. `raw` is the IRC line it was parsed from.
+  pub fn log(&self, event: &ChatEvent, raw: &str) {
+    let (Some(dir), Some(records)) = (&self.dir, &self.records) else {
+      return;
+    };
+    let Some(channel) = event.channel().filter(|c| self.is_enabled(c)) else {
+      return;
+    };
+    let Some((timestamp, text)) = describe(event) else {
+      return;
+    };
+    let timestamp = timestamp.with_timezone(&Local);
+    let line = match self.format {
+      LogFormat::Text => format!("[{}] {text}", timestamp.format("%H:%M:%S")),
+      LogFormat::Jsonl => match json_line(event, raw, timestamp, text) {
+        Ok(line) => line,
+        Err(e) => {
+          tracing::warn!("failed to serialize log line: {e}");
+          return;
+        }
+      },
+    };
+    let _ = records.send(Record {
+      path: path(dir, channel, timestamp.date_naive(), self.format),
+      format: self.format,
+      line,
+    });
+  }
+}
+
+impl Drop for Logs {
+  fn drop(&mut self) {
+    // the writer flushes what is left once the channel is closed
+    self.records = None;
+    if let Some(writer) = self.writer.take() {
+      let _ = writer.join();
+    }
+  }
+}
+
+fn path(dir: &Path, channel: &str, date: NaiveDate, format: LogFormat) -> PathBuf {
+  dir
+    .join("Twitch")
+    .join("Channels")
+    .join(channel)
+    .join(format!("{channel}-{date}.{}", format.extension()))
+}
+
+/// The time and a text version of the events which are logged.
+fn describe(event: &ChatEvent) -> Option<(DateTime<Utc>, String)> {
+  Some(match event {
+    ChatEvent::PrivMsg(m) => (m.timestamp, format!("{}: {}", m.author.name, m.text)),
+    ChatEvent::UserNotice(notice) => (notice.timestamp, user_notice(notice)),
+    ChatEvent::ClearChat(clear) => {
+      let text = match &clear.target {
+        Some(target) => match target.duration {
+          Some(duration) => format!(
+            "{} has been timed out for {}.",
+            target.login,
+            format_duration(duration.as_secs() as u32)
+          ),
+          None => format!("{} has been permanently banned.", target.login),
+        },
+        None => "Chat has been cleared by a moderator.".into(),
+      };
+      (clear.timestamp, text)
+    }
+    ChatEvent::ClearMsg(clear) => (
+      clear.timestamp,
+      format!("A message from {} was deleted: {}", clear.login, clear.text),
+    ),
+    ChatEvent::Notice(notice) => (Utc::now(), notice.text.clone()),
+    _ => return None,
+  })
+}
+
+fn user_notice(notice: &UserNotice) -> String {
+  let author = &notice.author.name;
+  match (&notice.system_msg, &notice.text) {
+    (Some(system), Some(text)) => format!("{system} {author}: {text}"),
+    (Some(system), None) => system.clone(),
+    (None, Some(text)) => format!("{author}: {text}"),
+    (None, None) => String::new(),
+  }
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+  timestamp: DateTime<Local>,
+  command: &'static str,
+  channel: &'a str,
+  /// The text version of the event, as in the text format.
+  text: String,
+  tags: BTreeMap<&'a str, String>,
+}
+
+fn json_line(
+  event: &ChatEvent,
+  raw: &str,
+  timestamp: DateTime<Local>,
+  text: String,
+) -> Result<String, serde_json::Error> {
+  let command = match event {
+    ChatEvent::PrivMsg(_) => "PRIVMSG",
+    ChatEvent::UserNotice(_) => "USERNOTICE",
+    ChatEvent::ClearChat(_) => "CLEARCHAT",
+    ChatEvent::ClearMsg(_) => "CLEARMSG",
+    _ => "NOTICE",
+  };
+  serde_json::to_string(&JsonLine {
+    timestamp,
+    command,
+    channel: event.channel().unwrap_or_default(),
+    text,
+    tags: Tags::parse(raw).iter().collect(),
+  })
+}
+
+/// Appends batches of records on the log thread.
+struct Writer {
+  dir: PathBuf,
+  retention_days: Option<u32>,
+  files: HashMap<PathBuf, File>,
+  /// The day old files were last deleted, the open files are closed when it changes.
+  today: Option<NaiveDate>,
+}
+
+impl Writer {
+  fn new(dir: PathBuf, retention_days: Option<u32>) -> Self {
+    Self {
+      dir,
+      retention_days,
+      files: HashMap::new(),
+      today: None,
+    }
+  }
+
+  fn run(&mut self, records: mpsc::Receiver<Record>) {
+    while let Ok(first) = records.recv() {
+      let mut batch = vec![first];
+      let deadline = Instant::now() + FLUSH_INTERVAL;
+      while batch.len() < MAX_BATCH {
+        match records.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+          Ok(record) => batch.push(record),
+          Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+        }
+      }
+      self.write(batch);
+    }
+  }
+
+  fn write(&mut self, batch: Vec<Record>) {
+    let today = Local::now().date_naive();
+    if self.today != Some(today) {
+      self.today = Some(today);
+      self.files.clear();
+      let cutoff = self
+        .retention_days
+        .and_then(|days| today.checked_sub_days(Days::new(days.into())));
+      if let Some(cutoff) = cutoff {
+        remove_old(&self.dir, cutoff);
+      }
+    }
+
+    let mut pending = HashMap::<PathBuf, (LogFormat, Vec<u8>)>::new();
+    for record in batch {
+      let (_, data) = pending
+        .entry(record.path)
+        .or_insert_with(|| (record.format, Vec::new()));
+      data.extend_from_slice(record.line.as_bytes());
+      data.push(b'\n');
+    }
+    for (path, (format, data)) in pending {
+      if let Err(e) = self.append(&path, format, &data) {
+        tracing::warn!(?path, "failed to write log: {e}");
+        // opened again for the next batch
+        self.files.remove(&path);
+      }
+    }
+  }
+
+  fn append(&mut self, path: &Path, format: LogFormat, data: &[u8]) -> std::io::Result<()> {
+    let file = match self.files.entry(path.into()) {
+      Entry::Occupied(entry) => entry.into_mut(),
+      Entry::Vacant(entry) => {
+        if let Some(dir) = path.parent() {
+          std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if format == LogFormat::Text {
+          let now = Local::now().format("%Y-%m-%d %H:%M:%S %:z");
+          writeln!(file, "# Start logging at {now}")?;
+        }
+        entry.insert(file)
+      }
+    };
+    file.write_all(data)?;
+    file.sync_data()
+  }
+}
+
+/// Deletes the log files of every channel which are from before `cutoff`.
+fn remove_old(dir: &Path, cutoff: NaiveDate) {
+  let Ok(channels) = std::fs::read_dir(dir.join("Twitch").join("Channels")) else {
+    return;
+  };
+  let files = channels
+    .flatten()
+    .filter_map(|channel| std::fs::read_dir(channel.path()).ok())
+    .flat_map(|files| files.flatten());
+  for file in files {
+    let path = file.path();
+    let date = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .and_then(file_date);
+    let is_log = path
+      .extension()
+      .is_some_and(|ext| ext == "log" || ext == "jsonl");
+    if is_log && date.is_some_and(|date| date < cutoff) {
+      if let Err(e) = std::fs::remove_file(&path) {
+        tracing::warn!(?path, "failed to delete old log: {e}");
+      }
+    }
+  }
+}
+
+/// The date at the end of `<channel>-YYYY-MM-DD`.
+fn file_date(stem: &str) -> Option<NaiveDate> {
+  let date = stem.get(stem.len().checked_sub(10)?..)?;
+  NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PRIVMSG: &str = "@badges=;color=;display-name=Ronni;emotes=;id=a;room-id=1;tmi-sent-ts=1689300000000;user-id=2 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #forsen :hi there";
+
+  fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("nanochat-logs-{:x}", rand::random::<u64>()))
+  }
+
+  fn event(raw: &str) -> ChatEvent {
+    ChatEvent::parse(&twitch::parse(raw.into()).unwrap()).unwrap()
+  }
+
+  fn lines(path: &Path) -> Vec<String> {
+    let data = std::fs::read_to_string(path).unwrap();
+    data.lines().map(String::from).collect()
+  }
+
+  fn date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+  }
+
+  /// Logs `raw` to a new directory, returns the directory and the file it is written to.
+  fn log(format: LogFormat, raw: &str) -> (PathBuf, PathBuf) {
+    let dir = temp_dir();
+    let config = LogConfig {
+      channels: vec!["forsen".into()],
+      format,
+      dir: Some(dir.clone()),
+      ..Default::default()
+    };
+    let event = event(raw);
+    let logs = Logs::start(&config);
+    logs.log(&event, raw);
+    // joins the writer
+    drop(logs);
+    let timestamp = match &event {
+      ChatEvent::PrivMsg(m) => m.timestamp.with_timezone(&Local),
+      _ => unreachable!(),
+    };
+    let path = path(&dir, "forsen", timestamp.date_naive(), format);
+    (dir, path)
+  }
+
+  #[test]
+  fn lays_out_paths() {
+    let dir = Path::new("logs");
+    assert_eq!(
+      path(dir, "forsen", date("2023-07-14"), LogFormat::Text),
+      Path::new("logs/Twitch/Channels/forsen/forsen-2023-07-14.log")
+    );
+    assert_eq!(
+      path(dir, "forsen", date("2023-07-14"), LogFormat::Jsonl),
+      Path::new("logs/Twitch/Channels/forsen/forsen-2023-07-14.jsonl")
+    );
+  }
+
+  #[test]
+  fn parses_file_dates() {
+    assert_eq!(file_date("forsen-2023-07-14"), Some(date("2023-07-14")));
+    assert_eq!(file_date("a_b-2023-01-02"), Some(date("2023-01-02")));
+    assert_eq!(file_date("forsen-2023-13-01"), None);
+    assert_eq!(file_date("forsen"), None);
+    assert_eq!(file_date(""), None);
+    // the last 10 bytes don't start at a char boundary
+    assert_eq!(file_date("xéééééé"), None);
+  }
+
+  #[test]
+  fn removes_old_logs() {
+    let dir = temp_dir();
+    let channel = dir.join("Twitch").join("Channels").join("forsen");
+    std::fs::create_dir_all(&channel).unwrap();
+    let files = [
+      "forsen-2023-07-01.log",
+      "forsen-2023-07-01.jsonl",
+      "forsen-2023-07-13.log",
+      "forsen-2023-07-14.log",
+      "forsen-2023-07-15.jsonl",
+      "notes-2023-07-01.txt",
+      "forsen.log",
+    ];
+    for file in files {
+      std::fs::write(channel.join(file), "").unwrap();
+    }
+
+    remove_old(&dir, date("2023-07-14"));
+    let mut left = std::fs::read_dir(&channel)
+      .unwrap()
+      .map(|file| file.unwrap().file_name().into_string().unwrap())
+      .collect::<Vec<_>>();
+    left.sort();
+    assert_eq!(
+      left,
+      [
+        "forsen-2023-07-14.log",
+        "forsen-2023-07-15.jsonl",
+        "forsen.log",
+        "notes-2023-07-01.txt",
+      ]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn logs_text() {
+    let (dir, path) = log(LogFormat::Text, PRIVMSG);
+    let lines = lines(&path);
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("# Start logging at "));
+    let time = DateTime::from_timestamp_millis(1689300000000)
+      .unwrap()
+      .with_timezone(&Local)
+      .format("%H:%M:%S");
+    assert_eq!(lines[1], format!("[{time}] Ronni: hi there"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn logs_jsonl() {
+    let (dir, path) = log(LogFormat::Jsonl, PRIVMSG);
+    let lines = lines(&path);
+    assert_eq!(lines.len(), 1);
+    let line = serde_json::from_str::<serde_json::Value>(&lines[0]).unwrap();
+    assert_eq!(line["command"], "PRIVMSG");
+    assert_eq!(line["channel"], "forsen");
+    assert_eq!(line["text"], "Ronni: hi there");
+    assert_eq!(line["tags"]["display-name"], "Ronni");
+    assert_eq!(line["tags"]["tmi-sent-ts"], "1689300000000");
+    let timestamp = line["timestamp"].as_str().unwrap();
+    let timestamp = DateTime::parse_from_rfc3339(timestamp).unwrap();
+    assert_eq!(timestamp.timestamp_millis(), 1689300000000);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn logs_enabled_channels() {
+    let dir = temp_dir();
+    let config = LogConfig {
+      channels: vec!["other".into()],
+      dir: Some(dir.clone()),
+      ..Default::default()
+    };
+    let mut logs = Logs::start(&config);
+    logs.log(&event(PRIVMSG), PRIVMSG);
+    assert!(!logs.is_enabled("forsen"));
+    logs.set_enabled("forsen", true);
+    logs.log(&event(PRIVMSG), PRIVMSG);
+    logs.set_enabled("forsen", false);
+    logs.log(&event(PRIVMSG), PRIVMSG);
+    drop(logs);
+
+    let channels = dir.join("Twitch").join("Channels");
+    let files = std::fs::read_dir(channels.join("forsen")).unwrap().count();
+    assert_eq!(files, 1);
+    assert!(!channels.join("other").exists());
+    let file = std::fs::read_dir(channels.join("forsen")).unwrap();
+    let path = file.map(|f| f.unwrap().path()).next().unwrap();
+    assert_eq!(lines(&path).len(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn flushes_batches() {
+    let dir = temp_dir();
+    let record = |channel: &str, line: &str| Record {
+      path: path(&dir, channel, date("2023-07-14"), LogFormat::Jsonl),
+      format: LogFormat::Jsonl,
+      line: line.into(),
+    };
+    let (tx, rx) = mpsc::channel();
+    let mut writer = Writer::new(dir.clone(), None);
+    let handle = thread::spawn(move || writer.run(rx));
+
+    // a small batch is written once the interval is over, while the channel is still open
+    tx.send(record("a", "1")).unwrap();
+    tx.send(record("b", "2")).unwrap();
+    tx.send(record("a", "3")).unwrap();
+    let a = path(&dir, "a", date("2023-07-14"), LogFormat::Jsonl);
+    let b = path(&dir, "b", date("2023-07-14"), LogFormat::Jsonl);
+    let written = || {
+      let read = |path: &Path| std::fs::read_to_string(path).unwrap_or_default();
+      (read(&a), read(&b))
+    };
+    let deadline = Instant::now() + 5 * FLUSH_INTERVAL;
+    while written() != ("1\n3\n".into(), "2\n".into()) && Instant::now() < deadline {
+      thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(written(), ("1\n3\n".into(), "2\n".into()));
+
+    // the rest is written when the channel is closed
+    for i in 0..MAX_BATCH + 10 {
+      tx.send(record("a", &i.to_string())).unwrap();
+    }
+    drop(tx);
+    handle.join().unwrap();
+    assert_eq!(lines(&a).len(), 2 + MAX_BATCH + 10);
+    assert_eq!(lines(&a).last().unwrap(), &(MAX_BATCH + 9).to_string());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,306 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::event::ClearChat;
+use crate::util::{data_dir, write_atomic};
+
+/// Entries kept in memory. The file is cut back to this many once it holds twice as many.
+pub const MAX_ENTRIES: usize = 10_000;
+
+/// A timeout or ban we saw in chat.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModLogEntry {
+  pub channel: String,
+  pub user_id: String,
+  pub login: String,
+  /// In seconds, `None` for a permanent ban.
+  pub duration: Option<u64>,
+  pub timestamp: DateTime<Utc>,
+}
+
+/// The most recent timeouts and bans seen in any channel, appended to a JSON lines file on a
+/// background thread as they happen.
+#[derive(Default)]
+pub struct ModLog {
+  entries: VecDeque<ModLogEntry>,
+  entries_tx: Option<mpsc::Sender<ModLogEntry>>,
+  writer: Option<JoinHandle<()>>,
+}
+
+impl ModLog {
+  /// Only kept in memory.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("moderation.jsonl"))
+  }
+
+  /// Returns an empty log if there is no log file yet.
+  pub fn load() -> Result<Self, Error> {
+    match Self::path() {
+      Some(path) => Self::load_from(&path),
+      None => Ok(Self::new()),
+    }
+  }
+
+  /// Lines which fail to parse are skipped. New entries are appended to `path`.
+  pub fn load_from(path: &Path) -> Result<Self, Error> {
+    let mut entries = VecDeque::new();
+    let mut lines = 0;
+    match File::open(path) {
+      Ok(file) => {
+        for line in BufReader::new(file).lines() {
+          lines += 1;
+          match serde_json::from_str(&line?) {
+            Ok(entry) => entries.push_back(entry),
+            Err(e) => tracing::warn!(?path, "invalid moderation log entry: {e}"),
+          }
+          if entries.len() > MAX_ENTRIES {
+            entries.pop_front();
+          }
+        }
+      }
+      Err(e) if e.kind() == ErrorKind::NotFound => {}
+      Err(e) => return Err(e.into()),
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut writer = Writer {
+      path: path.into(),
+      lines,
+    };
+    let writer = thread::Builder::new()
+      .name("mod-log".into())
+      .spawn(move || writer.run(rx))
+      .expect("failed to spawn moderation log thread");
+    Ok(Self {
+      entries,
+      entries_tx: Some(tx),
+      writer: Some(writer),
+    })
+  }
+
+  /// Records the timeout or ban in `event`, ignoring clears of the whole chat.
+  pub fn record(&mut self, event: &ClearChat) {
+    let Some(target) = &event.target else {
+      return;
+    };
+    let entry = ModLogEntry {
+      channel: event.channel.clone(),
+      user_id: target.user_id.clone(),
+      login: target.login.clone(),
+      duration: target.duration.map(|d| d.as_secs()),
+      timestamp: event.timestamp,
+    };
+    if let Some(entries_tx) = &self.entries_tx {
+      let _ = entries_tx.send(entry.clone());
+    }
+    if self.entries.len() == MAX_ENTRIES {
+      self.entries.pop_front();
+    }
+    self.entries.push_back(entry);
+  }
+
+  /// Timeouts and bans of `user_id` in `channel`, oldest first.
+  pub fn for_user<'a>(
+    &'a self,
+    channel: &'a str,
+    user_id: &'a str,
+  ) -> impl DoubleEndedIterator<Item = &'a ModLogEntry> + 'a {
+    self
+      .entries
+      .iter()
+      .filter(move |e| e.channel == channel && e.user_id == user_id)
+  }
+}
+
+impl Drop for ModLog {
+  fn drop(&mut self) {
+    // the writer finishes what is left once the channel is closed
+    self.entries_tx = None;
+    if let Some(writer) = self.writer.take() {
+      let _ = writer.join();
+    }
+  }
+}
+
+struct Writer {
+  path: PathBuf,
+  /// Lines in the file.
+  lines: usize,
+}
+
+impl Writer {
+  fn run(&mut self, entries: mpsc::Receiver<ModLogEntry>) {
+    self.compact();
+    while let Ok(first) = entries.recv() {
+      let batch = std::iter::once(first)
+        .chain(entries.try_iter())
+        .collect::<Vec<_>>();
+      if let Err(e) = self.append(&batch) {
+        tracing::warn!("{e}");
+      }
+      self.compact();
+    }
+  }
+
+  fn append(&mut self, batch: &[ModLogEntry]) -> Result<(), Error> {
+    let mut data = Vec::new();
+    for entry in batch {
+      serde_json::to_writer(&mut data, entry)?;
+      data.push(b'\n');
+    }
+    if let Some(dir) = self.path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&self.path)?
+      .write_all(&data)?;
+    self.lines += batch.len();
+    Ok(())
+  }
+
+  /// Keeps the last `MAX_ENTRIES` lines once the file has twice as many.
+  fn compact(&mut self) {
+    if self.lines < 2 * MAX_ENTRIES {
+      return;
+    }
+    let result = std::fs::read_to_string(&self.path).and_then(|data| {
+      let lines = data.lines().collect::<Vec<_>>();
+      let kept = &lines[lines.len().saturating_sub(MAX_ENTRIES)..];
+      let mut data = kept.join("\n");
+      data.push('\n');
+      write_atomic(&self.path, data.as_bytes())?;
+      Ok(kept.len())
+    });
+    match result {
+      Ok(lines) => self.lines = lines,
+      Err(e) => tracing::warn!("{}", Error::Io(e)),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  Json(serde_json::Error),
+}
+
+impl From<std::io::Error> for Error {
+  fn from(value: std::io::Error) -> Self {
+    Self::Io(value)
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(value: serde_json::Error) -> Self {
+    Self::Json(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Io(e) => write!(f, "failed to access moderation log: {e}"),
+      Error::Json(e) => write!(f, "failed to parse moderation log: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use super::super::event::ChatEvent;
+  use super::*;
+
+  fn clear_chat(line: &str) -> ClearChat {
+    match ChatEvent::parse(&twitch::parse(line.into()).unwrap()) {
+      Some(ChatEvent::ClearChat(event)) => event,
+      _ => panic!("expected a CLEARCHAT"),
+    }
+  }
+
+  fn lines(path: &Path) -> Vec<String> {
+    let data = std::fs::read_to_string(path).unwrap();
+    data.lines().map(String::from).collect()
+  }
+
+  #[test]
+  fn records_in_the_background() {
+    let dir = std::env::temp_dir().join(format!("nanochat-mod-log-{:x}", rand::random::<u64>()));
+    let path = dir.join("moderation.jsonl");
+
+    let mut log = ModLog::load_from(&path).unwrap();
+    log.record(&clear_chat("@ban-duration=350;room-id=1;target-user-id=2;tmi-sent-ts=1642719320727 :tmi.twitch.tv CLEARCHAT #dallas :ronni"));
+    log.record(&clear_chat(
+      "@room-id=1;tmi-sent-ts=1642715695392 :tmi.twitch.tv CLEARCHAT #dallas",
+    ));
+    log.record(&clear_chat("@room-id=1;target-user-id=2;tmi-sent-ts=1642715756806 :tmi.twitch.tv CLEARCHAT #dallas :ronni"));
+    let durations = log
+      .for_user("dallas", "2")
+      .map(|e| e.duration)
+      .collect::<Vec<_>>();
+    assert_eq!(durations, [Some(350), None]);
+    assert_eq!(log.for_user("other", "2").count(), 0);
+    drop(log);
+
+    assert_eq!(lines(&path).len(), 2);
+    std::fs::write(
+      &path,
+      format!("not json\n{}", std::fs::read_to_string(&path).unwrap()),
+    )
+    .unwrap();
+    let log = ModLog::load_from(&path).unwrap();
+    assert_eq!(log.for_user("dallas", "2").count(), 2);
+    drop(log);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn caps_the_file() {
+    let dir = std::env::temp_dir().join(format!("nanochat-mod-log-{:x}", rand::random::<u64>()));
+    let path = dir.join("moderation.jsonl");
+    std::fs::create_dir_all(&dir).unwrap();
+    let entry = |i: usize| ModLogEntry {
+      channel: "dallas".into(),
+      user_id: i.to_string(),
+      login: format!("user{i}"),
+      duration: Some(600),
+      timestamp: DateTime::from_timestamp(1642719320, 0).unwrap(),
+    };
+    let data = (0..2 * MAX_ENTRIES + 5)
+      .map(|i| serde_json::to_string(&entry(i)).unwrap() + "\n")
+      .collect::<String>();
+    std::fs::write(&path, data).unwrap();
+
+    let mut log = ModLog::load_from(&path).unwrap();
+    assert_eq!(log.entries.len(), MAX_ENTRIES);
+    assert_eq!(log.entries[0].user_id, (MAX_ENTRIES + 5).to_string());
+    log.record(&clear_chat("@ban-duration=1;room-id=1;target-user-id=new;tmi-sent-ts=1 :tmi.twitch.tv CLEARCHAT #dallas :new"));
+    assert_eq!(log.entries.len(), MAX_ENTRIES);
+    drop(log);
+
+    // cut back on startup, then appended to
+    let lines = lines(&path);
+    assert_eq!(lines.len(), MAX_ENTRIES + 1);
+    let first = serde_json::from_str::<ModLogEntry>(&lines[0]).unwrap();
+    assert_eq!(first, entry(MAX_ENTRIES + 5));
+    assert!(lines[MAX_ENTRIES].contains(r#""user_id":"new""#));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
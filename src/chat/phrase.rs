@@ -0,0 +1,128 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Text to look for in a message, used by highlight and ignore rules.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Phrase {
+  pub pattern: String,
+  /// Otherwise `pattern` is matched literally.
+  #[serde(default)]
+  pub regex: bool,
+  #[serde(default)]
+  pub case_sensitive: bool,
+  /// Only match if `pattern` isn't surrounded by other word characters.
+  #[serde(default)]
+  pub whole_word: bool,
+}
+
+impl Phrase {
+  pub fn plain(pattern: impl ToString) -> Self {
+    Self {
+      pattern: pattern.to_string(),
+      regex: false,
+      case_sensitive: false,
+      whole_word: true,
+    }
+  }
+
+  pub fn regex(pattern: impl ToString) -> Self {
+    Self {
+      pattern: pattern.to_string(),
+      regex: true,
+      case_sensitive: false,
+      whole_word: false,
+    }
+  }
+
+  pub fn compile(&self) -> Result<CompiledPhrase, regex::Error> {
+    let pattern = match self.regex {
+      true => self.pattern.clone(),
+      false => regex::escape(&self.pattern),
+    };
+    let regex = RegexBuilder::new(&pattern)
+      .case_insensitive(!self.case_sensitive)
+      .build()?;
+    Ok(CompiledPhrase {
+      regex,
+      whole_word: self.whole_word,
+    })
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct CompiledPhrase {
+  regex: Regex,
+  whole_word: bool,
+}
+
+impl CompiledPhrase {
+  pub fn is_match(&self, text: &str) -> bool {
+    self.find_iter(text).next().is_some()
+  }
+
+  /// Byte ranges of every non-overlapping match in `text`.
+  pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+    // `\b` doesn't work for patterns which start or end with a non-word character,
+    // so word boundaries are checked here instead. A rejected match is retried from its next
+    // character, so that it doesn't hide a valid match overlapping it.
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+      while pos <= text.len() {
+        let range = self.regex.find_at(text, pos)?.range();
+        let next_char = |i: usize| i + text[i..].chars().next().map_or(1, char::len_utf8);
+        let valid = !self.whole_word
+          || (!text[..range.start].chars().next_back().is_some_and(is_word)
+            && !text[range.end..].chars().next().is_some_and(is_word));
+        if valid {
+          pos = match range.start == range.end {
+            true => next_char(range.end),
+            false => range.end,
+          };
+          return Some(range);
+        }
+        pos = next_char(range.start);
+      }
+      None
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn find(phrase: Phrase, text: &str) -> Vec<&str> {
+    let phrase = phrase.compile().unwrap();
+    phrase.find_iter(text).map(|range| &text[range]).collect()
+  }
+
+  #[test]
+  fn whole_words() {
+    assert_eq!(find(Phrase::plain("lol"), "xlol lol lol"), ["lol", "lol"]);
+    assert_eq!(find(Phrase::plain("lol lol"), "xlol lol lol"), ["lol lol"]);
+    assert_eq!(
+      find(Phrase::plain("lol"), "LOL, lolx _lol lol"),
+      ["LOL", "lol"]
+    );
+    assert_eq!(find(Phrase::plain(":)"), "a:) :) :)"), [":)", ":)"]);
+    assert_eq!(find(Phrase::plain("é"), "éé é"), ["é"]);
+  }
+
+  #[test]
+  fn regexes() {
+    assert_eq!(find(Phrase::regex("a+"), "baaa aa"), ["aaa", "aa"]);
+    let mut phrase = Phrase::regex("a+");
+    phrase.whole_word = true;
+    assert_eq!(find(phrase, "baaa aa"), ["aa"]);
+    assert_eq!(find(Phrase::regex("^!cmd"), "!cmd !cmd"), ["!cmd"]);
+    // empty matches don't loop forever
+    assert_eq!(find(Phrase::regex("x*"), "ab").len(), 3);
+
+    let mut phrase = Phrase::plain("Kappa");
+    phrase.case_sensitive = true;
+    assert!(!phrase.compile().unwrap().is_match("kappa"));
+  }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use super::event::{RoomStateUpdate, UserState};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoomState {
+  pub channel: String,
+  pub room_id: String,
+  pub emote_only: bool,
+  /// Minimum follow age, `None` if followers-only mode is off.
+  pub followers_only: Option<Duration>,
+  pub r9k: bool,
+  /// Time between messages, `None` if slow mode is off.
+  pub slow: Option<Duration>,
+  pub subs_only: bool,
+}
+
+impl RoomState {
+  fn apply(&mut self, update: &RoomStateUpdate) {
+    if !update.room_id.is_empty() {
+      self.room_id = update.room_id.clone();
+    }
+    if let Some(emote_only) = update.emote_only {
+      self.emote_only = emote_only;
+    }
+    if let Some(minutes) = update.followers_only {
+      self.followers_only = (minutes >= 0).then(|| Duration::from_secs(minutes as u64 * 60));
+    }
+    if let Some(r9k) = update.r9k {
+      self.r9k = r9k;
+    }
+    if let Some(slow) = update.slow {
+      self.slow = (slow > 0).then(|| Duration::from_secs(slow));
+    }
+    if let Some(subs_only) = update.subs_only {
+      self.subs_only = subs_only;
+    }
+  }
+
+  /// Short labels for each active mode, e.g. `slow 30s`.
+  pub fn labels(&self) -> Vec<String> {
+    let mut labels = Vec::new();
+    if let Some(slow) = self.slow {
+      labels.push(format!("slow {}", format_duration(slow)));
+    }
+    if let Some(followers_only) = self.followers_only {
+      if followers_only.is_zero() {
+        labels.push("followers".into());
+      } else {
+        labels.push(format!("followers {}", format_duration(followers_only)));
+      }
+    }
+    if self.subs_only {
+      labels.push("subs".into());
+    }
+    if self.emote_only {
+      labels.push("emote-only".into());
+    }
+    if self.r9k {
+      labels.push("r9k".into());
+    }
+    labels
+  }
+
+  /// Checks the restrictions which can be verified locally before sending `text`.
+  ///
+  /// `user` is our `USERSTATE` in this channel, and `last_sent` is when we last sent a message.
+  /// `is_emote` tells whether a word is a Twitch emote we can use, other emotes are plain text to
+  /// Twitch.
+  pub fn check_send(
+    &self,
+    text: &str,
+    user: Option<&UserState>,
+    last_sent: Option<Instant>,
+    is_emote: impl Fn(&str) -> bool,
+  ) -> Result<(), SendBlocked> {
+    let has_badge = |names: &[&str]| {
+      user.is_some_and(|user| {
+        user
+          .badges
+          .iter()
+          .any(|badge| names.contains(&badge.name.as_str()))
+      })
+    };
+    if user.is_some_and(|user| user.is_mod) || has_badge(&["broadcaster"]) {
+      return Ok(());
+    }
+
+    if self.subs_only && !has_badge(&["subscriber", "founder", "vip"]) {
+      return Err(SendBlocked::SubsOnly);
+    }
+
+    let words = text.strip_prefix("/me ").unwrap_or(text).split_whitespace();
+    if self.emote_only && !words.into_iter().all(is_emote) {
+      return Err(SendBlocked::EmoteOnly);
+    }
+
+    if let (Some(slow), Some(last_sent)) = (self.slow, last_sent) {
+      let elapsed = last_sent.elapsed();
+      if elapsed < slow && !has_badge(&["vip"]) {
+        return Err(SendBlocked::SlowMode(slow - elapsed));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendBlocked {
+  SlowMode(Duration),
+  SubsOnly,
+  EmoteOnly,
+}
+
+impl Display for SendBlocked {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SendBlocked::SlowMode(remaining) => write!(
+        f,
+        "slow mode is on, wait {}s before sending another message",
+        remaining.as_secs() + 1
+      ),
+      SendBlocked::SubsOnly => write!(f, "this room is in subscribers-only mode"),
+      SendBlocked::EmoteOnly => write!(f, "this room is in emote-only mode"),
+    }
+  }
+}
+
+fn format_duration(duration: Duration) -> String {
+  let secs = duration.as_secs();
+  match secs {
+    s if s % 86400 == 0 => format!("{}d", s / 86400),
+    s if s % 3600 == 0 => format!("{}h", s / 3600),
+    s if s % 60 == 0 => format!("{}m", s / 60),
+    s => format!("{s}s"),
+  }
+}
+
+/// The current `ROOMSTATE` of every joined channel.
+#[derive(Default)]
+pub struct RoomStates {
+  rooms: HashMap<String, RoomState>,
+  room_ids: HashMap<String, String>,
+}
+
+impl RoomStates {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Merges a partial update into the channel's state.
+  pub fn update(&mut self, update: &RoomStateUpdate) {
+    let room = self
+      .rooms
+      .entry(update.channel.clone())
+      .or_insert_with(|| RoomState {
+        channel: update.channel.clone(),
+        ..Default::default()
+      });
+    room.apply(update);
+    if !room.room_id.is_empty() {
+      self
+        .room_ids
+        .insert(room.room_id.clone(), room.channel.clone());
+    }
+  }
+
+  pub fn get(&self, channel: &str) -> Option<&RoomState> {
+    self.rooms.get(channel)
+  }
+
+  pub fn by_room_id(&self, room_id: &str) -> Option<&RoomState> {
+    self.rooms.get(self.room_ids.get(room_id)?)
+  }
+
+  /// Forgets the channel, e.g. after leaving it.
+  pub fn remove(&mut self, channel: &str) {
+    if let Some(room) = self.rooms.remove(channel) {
+      self.room_ids.remove(&room.room_id);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn update(channel: &str) -> RoomStateUpdate {
+    RoomStateUpdate {
+      channel: channel.into(),
+      room_id: "1".into(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn merges_updates() {
+    let mut states = RoomStates::new();
+    let join = RoomStateUpdate {
+      emote_only: Some(false),
+      followers_only: Some(-1),
+      r9k: Some(false),
+      slow: Some(0),
+      subs_only: Some(false),
+      ..update("bar")
+    };
+    states.update(&join);
+    assert_eq!(states.by_room_id("1").unwrap().channel, "bar");
+
+    states.update(&RoomStateUpdate {
+      slow: Some(30),
+      ..update("bar")
+    });
+    states.update(&RoomStateUpdate {
+      followers_only: Some(10),
+      ..update("bar")
+    });
+    let room = states.get("bar").unwrap();
+    assert_eq!(room.slow, Some(Duration::from_secs(30)));
+    assert_eq!(room.followers_only, Some(Duration::from_secs(600)));
+    assert_eq!(room.labels(), ["slow 30s", "followers 10m"]);
+
+    states.remove("bar");
+    assert!(states.by_room_id("1").is_none());
+  }
+
+  #[test]
+  fn checks_send() {
+    let emote_only = RoomState {
+      emote_only: true,
+      ..Default::default()
+    };
+    let is_emote = |word: &str| word == "Kappa" || word == "PogChamp";
+    let check = |room: &RoomState, text: &str, user: Option<&UserState>| {
+      room.check_send(text, user, None, is_emote)
+    };
+    assert_eq!(check(&emote_only, "Kappa PogChamp", None), Ok(()));
+    assert_eq!(check(&emote_only, "/me Kappa", None), Ok(()));
+    assert_eq!(
+      check(&emote_only, "Kappa hi", None),
+      Err(SendBlocked::EmoteOnly)
+    );
+    let moderator = UserState {
+      channel: "bar".into(),
+      name: "ronni".into(),
+      color: None,
+      badges: Vec::new(),
+      badge_info: Vec::new(),
+      emote_sets: Vec::new(),
+      is_mod: true,
+      sent_msg_id: None,
+    };
+    assert_eq!(check(&emote_only, "hi", Some(&moderator)), Ok(()));
+
+    let slow = RoomState {
+      slow: Some(Duration::from_secs(30)),
+      ..Default::default()
+    };
+    assert_eq!(slow.check_send("hi", None, None, is_emote), Ok(()));
+    assert!(matches!(
+      slow.check_send("hi", None, Some(Instant::now()), is_emote),
+      Err(SendBlocked::SlowMode(_))
+    ));
+    let subs_only = RoomState {
+      subs_only: true,
+      ..Default::default()
+    };
+    assert_eq!(check(&subs_only, "hi", None), Err(SendBlocked::SubsOnly));
+  }
+}
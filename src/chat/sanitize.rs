@@ -0,0 +1,334 @@
+use std::borrow::Cow;
+
+use super::fragment::Offsets;
+use super::tags::Emote;
+
+/// Combining marks allowed on a single character, further ones are dropped. Enough for any real
+/// script, but not for "zalgo" text.
+pub const MAX_COMBINING_MARKS: usize = 4;
+
+/// Removes characters which are invisible or mess with the layout of surrounding text:
+/// - bidi overrides, embeddings and isolates, which could reverse the rest of the line
+/// - zero-width spaces and other invisible filler, which are used to dodge filters
+/// - tag characters (U+E0000 to U+E007F), which Twitch clients append to repeat a message
+/// - combining marks beyond `MAX_COMBINING_MARKS` on the same character
+///
+/// Zero-width joiners are only kept between pictographic characters, so that emoji sequences such
+/// as 👩‍💻 survive.
+pub fn sanitize(text: &str) -> Cow<'_, str> {
+  let removed = removed(text);
+  match removed.is_empty() {
+    true => Cow::Borrowed(text),
+    false => Cow::Owned(without(text, &removed)),
+  }
+}
+
+/// Like `sanitize`, but also moves the ranges of `emotes` to where they are in the sanitized
+/// text. Ranges are resolved like the tokenizer does, and counted in code points afterwards.
+/// Returns the sanitized text, and the characters which were removed from it.
+pub fn sanitize_with_emotes(text: &str, emotes: &mut Vec<Emote>) -> (String, Vec<char>) {
+  let removed = removed(text);
+  if removed.is_empty() {
+    return (text.into(), Vec::new());
+  }
+  let offsets = Offsets::new(text);
+  let shift = |i: usize| i - removed.partition_point(|r| *r < i);
+  for emote in emotes.iter_mut() {
+    emote.ranges.retain_mut(|range| {
+      // counted in UTF-16 code units, which `removed` isn't
+      if let Some(bytes) = offsets.resolve(range) {
+        *range = offsets.char_index(bytes.start)..offsets.char_index(bytes.end);
+      }
+      *range = shift(range.start)..shift(range.end);
+      range.start < range.end
+    });
+  }
+  emotes.retain(|emote| !emote.ranges.is_empty());
+  let mut chars = text.chars().enumerate();
+  let removed_chars = removed
+    .iter()
+    .filter_map(|i| chars.find(|(j, _)| j == i).map(|(_, c)| c))
+    .collect();
+  (without(text, &removed), removed_chars)
+}
+
+/// Code point indices of the characters `sanitize` removes, in order.
+fn removed(text: &str) -> Vec<usize> {
+  let mut removed = Vec::new();
+  // whether the last character which was kept, ignoring modifiers, is pictographic
+  let mut pictographic = false;
+  let mut marks = 0;
+  let mut chars = text.chars().enumerate().peekable();
+  while let Some((i, c)) = chars.next() {
+    let remove = if is_bidi_control(c) || is_invisible(c) {
+      true
+    } else if c == '\u{200D}' {
+      let next = chars.peek().map(|(_, c)| *c);
+      !(pictographic && next.is_some_and(is_extended_pictographic))
+    } else if is_combining_mark(c) {
+      marks += 1;
+      marks > MAX_COMBINING_MARKS
+    } else {
+      false
+    };
+    match remove {
+      true => removed.push(i),
+      false => {
+        if !is_combining_mark(c) {
+          marks = 0;
+        }
+        if !is_emoji_modifier(c) {
+          pictographic = is_extended_pictographic(c);
+        }
+      }
+    }
+  }
+  removed
+}
+
+/// `text` without the code points at the sorted indices in `removed`.
+fn without(text: &str, removed: &[usize]) -> String {
+  let mut removed = removed.iter().peekable();
+  text
+    .chars()
+    .enumerate()
+    .filter(|(i, _)| removed.next_if_eq(&i).is_none())
+    .map(|(_, c)| c)
+    .collect()
+}
+
+fn is_bidi_control(c: char) -> bool {
+  matches!(
+    c,
+    '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+  )
+}
+
+fn is_invisible(c: char) -> bool {
+  matches!(
+    c,
+    '\u{00AD}'
+      | '\u{034F}'
+      | '\u{115F}'
+      | '\u{1160}'
+      | '\u{180E}'
+      | '\u{200B}'
+      | '\u{200C}'
+      | '\u{2060}'..='\u{2064}'
+      | '\u{3164}'
+      | '\u{FEFF}'
+      | '\u{FFA0}'
+      | '\u{E0000}'..='\u{E007F}'
+  )
+}
+
+/// Variation selectors, skin tones and combining marks, which may come between an emoji and a
+/// zero-width joiner, as in 🏳️‍🌈.
+fn is_emoji_modifier(c: char) -> bool {
+  matches!(c, '\u{FE0E}' | '\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}') || is_combining_mark(c)
+}
+
+/// The `Extended_Pictographic` property from Unicode 15, which emoji sequences are made of.
+fn is_extended_pictographic(c: char) -> bool {
+  matches!(
+    c,
+    '\u{00A9}'
+      | '\u{00AE}'
+      | '\u{203C}'
+      | '\u{2049}'
+      | '\u{2122}'
+      | '\u{2139}'
+      | '\u{2194}'..='\u{2199}'
+      | '\u{21A9}'..='\u{21AA}'
+      | '\u{231A}'..='\u{231B}'
+      | '\u{2328}'
+      | '\u{2388}'
+      | '\u{23CF}'
+      | '\u{23E9}'..='\u{23F3}'
+      | '\u{23F8}'..='\u{23FA}'
+      | '\u{24C2}'
+      | '\u{25AA}'..='\u{25AB}'
+      | '\u{25B6}'
+      | '\u{25C0}'
+      | '\u{25FB}'..='\u{25FE}'
+      | '\u{2600}'..='\u{2605}'
+      | '\u{2607}'..='\u{2612}'
+      | '\u{2614}'..='\u{2685}'
+      | '\u{2690}'..='\u{2705}'
+      | '\u{2708}'..='\u{2712}'
+      | '\u{2714}'
+      | '\u{2716}'
+      | '\u{271D}'
+      | '\u{2721}'
+      | '\u{2728}'
+      | '\u{2733}'..='\u{2734}'
+      | '\u{2744}'
+      | '\u{2747}'
+      | '\u{274C}'
+      | '\u{274E}'
+      | '\u{2753}'..='\u{2755}'
+      | '\u{2757}'
+      | '\u{2763}'..='\u{2767}'
+      | '\u{2795}'..='\u{2797}'
+      | '\u{27A1}'
+      | '\u{27B0}'
+      | '\u{27BF}'
+      | '\u{2934}'..='\u{2935}'
+      | '\u{2B05}'..='\u{2B07}'
+      | '\u{2B1B}'..='\u{2B1C}'
+      | '\u{2B50}'
+      | '\u{2B55}'
+      | '\u{3030}'
+      | '\u{303D}'
+      | '\u{3297}'
+      | '\u{3299}'
+      | '\u{1F000}'..='\u{1F0FF}'
+      | '\u{1F10D}'..='\u{1F10F}'
+      | '\u{1F12F}'
+      | '\u{1F16C}'..='\u{1F171}'
+      | '\u{1F17E}'..='\u{1F17F}'
+      | '\u{1F18E}'
+      | '\u{1F191}'..='\u{1F19A}'
+      | '\u{1F1AD}'..='\u{1F1E5}'
+      | '\u{1F201}'..='\u{1F20F}'
+      | '\u{1F21A}'
+      | '\u{1F22F}'
+      | '\u{1F232}'..='\u{1F23A}'
+      | '\u{1F23C}'..='\u{1F23F}'
+      | '\u{1F249}'..='\u{1F3FA}'
+      | '\u{1F400}'..='\u{1F53D}'
+      | '\u{1F546}'..='\u{1F64F}'
+      | '\u{1F680}'..='\u{1F6FF}'
+      | '\u{1F774}'..='\u{1F77F}'
+      | '\u{1F7D5}'..='\u{1F7FF}'
+      | '\u{1F80C}'..='\u{1F80F}'
+      | '\u{1F848}'..='\u{1F84F}'
+      | '\u{1F85A}'..='\u{1F85F}'
+      | '\u{1F888}'..='\u{1F88F}'
+      | '\u{1F8AE}'..='\u{1F8FF}'
+      | '\u{1F90C}'..='\u{1F93A}'
+      | '\u{1F93C}'..='\u{1F945}'
+      | '\u{1F947}'..='\u{1FAFF}'
+      | '\u{1FC00}'..='\u{1FFFD}'
+  )
+}
+
+/// The blocks of general-purpose combining marks, which is where "zalgo" text comes from.
+fn is_combining_mark(c: char) -> bool {
+  matches!(
+    c,
+    '\u{0300}'..='\u{036F}'
+      | '\u{0483}'..='\u{0489}'
+      | '\u{1AB0}'..='\u{1AFF}'
+      | '\u{1DC0}'..='\u{1DFF}'
+      | '\u{20D0}'..='\u{20FF}'
+      | '\u{FE20}'..='\u{FE2F}'
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  #![allow(clippy::single_range_in_vec_init)]
+
+  use std::ops::Range;
+
+  use super::*;
+
+  #[test]
+  fn bidi() {
+    assert_eq!(sanitize("\u{202E}olleh\u{202C} world"), "olleh world");
+    assert_eq!(sanitize("a\u{2067}b\u{2069}\u{200F}c\u{061C}"), "abc");
+    // right-to-left scripts themselves are left alone
+    assert!(matches!(sanitize("שלום مرحبا"), Cow::Borrowed(_)));
+  }
+
+  #[test]
+  fn invisible() {
+    assert_eq!(sanitize("f\u{200B}o\u{00AD}o\u{FEFF}"), "foo");
+    // what Twitch clients append to send the same message twice
+    assert_eq!(sanitize("hello \u{E0000}"), "hello ");
+    assert_eq!(sanitize("🏴\u{E0067}\u{E0062}\u{E007F}"), "🏴");
+    assert_eq!(sanitize("\u{3164}\u{115F}"), "");
+  }
+
+  #[test]
+  fn zalgo() {
+    let marks = "\u{0301}\u{0302}\u{0303}\u{0304}\u{0305}\u{0306}";
+    let kept = &marks[..MAX_COMBINING_MARKS * 2];
+    assert_eq!(
+      sanitize(&format!("a{marks}b{marks}")),
+      format!("a{kept}b{kept}")
+    );
+    // the limit is per character
+    let text = format!("a{kept}b{kept}");
+    assert!(matches!(sanitize(&text), Cow::Borrowed(_)));
+    // invisible characters don't reset it
+    assert_eq!(
+      sanitize(&format!("a{kept}\u{200B}{kept}")),
+      format!("a{kept}")
+    );
+  }
+
+  #[test]
+  fn zero_width_joiners() {
+    for emoji in ["👩‍💻", "👨‍👩‍👧", "🏳️‍🌈", "👩🏽‍🚀", "❤️‍🔥", "👨‍⚕️"]
+    {
+      assert!(matches!(sanitize(emoji), Cow::Borrowed(_)), "{emoji}");
+    }
+    assert_eq!(sanitize("a\u{200D}b"), "ab");
+    assert_eq!(sanitize("日\u{200D}本"), "日本");
+    assert_eq!(sanitize("👩\u{200D}a"), "👩a");
+    assert_eq!(sanitize("a\u{200D}💻"), "a💻");
+    assert_eq!(sanitize("👩\u{200D}\u{200D}💻"), "👩\u{200D}💻");
+    assert_eq!(sanitize("\u{200D}👩\u{200D}"), "👩");
+  }
+
+  #[test]
+  fn shifts_emotes() {
+    let emote = |id: &str, ranges: Vec<Range<usize>>| Emote {
+      id: id.into(),
+      ranges,
+    };
+    let mut emotes = vec![
+      emote("25", vec![0..5, 14..19]),
+      emote("1902", vec![8..13]),
+      // covers only removed characters
+      emote("1", vec![5..7]),
+    ];
+    let text = "Kappa\u{200B}\u{202E} Keepo Kappa\u{E0000}";
+    let (sanitized, removed) = sanitize_with_emotes(text, &mut emotes);
+    assert_eq!(sanitized, "Kappa Keepo Kappa");
+    assert_eq!(removed, ['\u{200B}', '\u{202E}', '\u{E0000}']);
+    assert_eq!(
+      emotes,
+      [emote("25", vec![0..5, 12..17]), emote("1902", vec![6..11])]
+    );
+    for range in emotes.iter().flat_map(|e| &e.ranges) {
+      let name = sanitized
+        .chars()
+        .skip(range.start)
+        .take(range.len())
+        .collect::<String>();
+      assert!(name == "Kappa" || name == "Keepo", "{name}");
+    }
+
+    // an emoji before the removed character and the emote, with the emote counted in UTF-16 code
+    // units, where the emoji takes two
+    let text = "👋\u{200B} Kappa";
+    let mut emotes = vec![emote("25", vec![4..9])];
+    let (sanitized, _) = sanitize_with_emotes(text, &mut emotes);
+    assert_eq!(sanitized, "👋 Kappa");
+    assert_eq!(emotes, [emote("25", vec![2..7])]);
+    // and the same in code points
+    let mut emotes = vec![emote("25", vec![3..8])];
+    sanitize_with_emotes(text, &mut emotes);
+    assert_eq!(emotes, [emote("25", vec![2..7])]);
+
+    let mut unchanged = vec![emote("25", vec![0..5])];
+    assert_eq!(
+      sanitize_with_emotes("Kappa", &mut unchanged),
+      ("Kappa".into(), Vec::new())
+    );
+    assert_eq!(unchanged, [emote("25", vec![0..5])]);
+  }
+}
@@ -0,0 +1,497 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+
+use super::event::Author;
+use super::fragment;
+use super::history::{ChannelHistory, History, Message};
+use super::phrase::{CompiledPhrase, Phrase};
+
+/// What to look for. Empty fields match everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query {
+  pub text: String,
+  /// Otherwise `text` is matched literally.
+  pub regex: bool,
+  /// Login or display name.
+  pub author: String,
+  /// Badge name, e.g. `subscriber`.
+  pub badge: String,
+  /// Local dates, both inclusive.
+  pub from: Option<NaiveDate>,
+  pub to: Option<NaiveDate>,
+  pub has_link: bool,
+  /// Every channel if `None`.
+  pub channel: Option<String>,
+}
+
+impl Query {
+  pub fn is_empty(&self) -> bool {
+    *self
+      == Query {
+        channel: self.channel.clone(),
+        regex: self.regex,
+        ..Default::default()
+      }
+  }
+
+  pub fn compile(&self) -> Result<Matcher, regex::Error> {
+    let text = match self.text.is_empty() {
+      true => None,
+      false => {
+        let phrase = match self.regex {
+          true => Phrase::regex(&self.text),
+          false => Phrase {
+            whole_word: false,
+            ..Phrase::plain(&self.text)
+          },
+        };
+        Some(phrase.compile()?)
+      }
+    };
+    let non_empty = |s: &str| {
+      let s = s.trim().trim_start_matches('@');
+      (!s.is_empty()).then(|| s.to_lowercase())
+    };
+    Ok(Matcher {
+      text,
+      author: non_empty(&self.author),
+      badge: non_empty(&self.badge),
+      from: self.from.and_then(local_midnight),
+      until: self.to.and_then(|to| local_midnight(to.succ_opt()?)),
+      has_link: self.has_link,
+    })
+  }
+}
+
+fn local_midnight(date: NaiveDate) -> Option<DateTime<Utc>> {
+  let midnight = date.and_hms_opt(0, 0, 0)?;
+  Some(Local.from_local_datetime(&midnight).earliest()?.into())
+}
+
+/// A compiled `Query`.
+pub struct Matcher {
+  text: Option<CompiledPhrase>,
+  author: Option<String>,
+  badge: Option<String>,
+  from: Option<DateTime<Utc>>,
+  /// Exclusive.
+  until: Option<DateTime<Utc>>,
+  has_link: bool,
+}
+
+impl Matcher {
+  /// Only chat messages and user notices can match.
+  pub fn is_match(&self, message: &Message) -> bool {
+    let (author, text) = match message {
+      Message::Chat(m) => (&m.author, m.text.as_str()),
+      Message::UserNotice(m) => (&m.author, m.text.as_deref().unwrap_or_default()),
+      Message::System { .. } => return false,
+    };
+    let timestamp = message.timestamp();
+    self.from.iter().all(|from| timestamp >= *from)
+      && self.until.iter().all(|until| timestamp < *until)
+      && self.author.iter().all(|name| is_author(author, name))
+      && self
+        .badge
+        .iter()
+        .all(|badge| author.badges.iter().any(|b| b.name == *badge))
+      && (!self.has_link || fragment::has_url(text))
+      && self.text.iter().all(|phrase| phrase.is_match(text))
+  }
+}
+
+/// `name` is lowercase.
+fn is_author(author: &Author, name: &str) -> bool {
+  author.login == name || author.name.to_lowercase() == name
+}
+
+/// A matching message, which may have been evicted from the history since.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hit {
+  pub channel: String,
+  pub seq: u64,
+}
+
+/// Runs a query over the history a slice at a time, so that it never blocks a frame for long.
+/// Messages are searched newest first, across all channels.
+///
+/// Messages which arrive while the search runs are searched as well, and their hits come first.
+/// Historical messages which are added in front of a channel after the search passed its oldest
+/// message are not searched.
+pub struct Search {
+  matcher: Matcher,
+  /// Every channel if `None`.
+  channel: Option<String>,
+  /// The next sequence number to look at in each channel, until it runs out of messages.
+  cursors: Vec<(String, u64)>,
+  /// The first sequence number which arrived after the search started, in each channel.
+  arrivals: HashMap<String, u64>,
+  hits: VecDeque<Hit>,
+  searched: usize,
+}
+
+impl Search {
+  pub fn new(query: &Query, history: &History) -> Result<Self, regex::Error> {
+    let channels = history
+      .channels()
+      .filter(|(channel, _)| query.channel.iter().all(|c| c == channel))
+      .filter_map(|(channel, h)| Some((channel.to_string(), h.iter().next_back()?.seq)))
+      .collect::<Vec<_>>();
+    let arrivals = channels
+      .iter()
+      .map(|(channel, seq)| (channel.clone(), seq + 1))
+      .collect();
+    Ok(Self {
+      matcher: query.compile()?,
+      channel: query.channel.clone(),
+      cursors: channels,
+      arrivals,
+      hits: VecDeque::new(),
+      searched: 0,
+    })
+  }
+
+  /// Newest first.
+  pub fn hits(&self) -> &VecDeque<Hit> {
+    &self.hits
+  }
+
+  /// Number of messages looked at so far.
+  pub fn searched(&self) -> usize {
+    self.searched
+  }
+
+  /// Whether every message from before the search started was searched.
+  pub fn is_done(&self) -> bool {
+    self.cursors.is_empty()
+  }
+
+  /// Searches the messages which arrived since the last call, then older messages for about
+  /// `budget`, but at least a few hundred. Returns `true` once every older message was searched.
+  pub fn step(&mut self, history: &History, budget: Duration) -> bool {
+    self.search_arrivals(history);
+
+    let start = Instant::now();
+    for i in 0.. {
+      // checking the time for every message would slow the search down
+      if i % 256 == 0 && i > 0 && start.elapsed() >= budget {
+        break;
+      }
+      // the channel with the newest message which wasn't searched yet
+      let next = self
+        .cursors
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (channel, seq))| {
+          let entry = history.channel(channel)?.get(*seq)?;
+          Some((i, entry.message.timestamp()))
+        })
+        .max_by_key(|(_, timestamp)| *timestamp);
+      let Some((i, timestamp)) = next else {
+        self.cursors.clear();
+        break;
+      };
+
+      let (channel, seq) = &mut self.cursors[i];
+      let done = match history.channel(channel).and_then(|h| h.get(*seq)) {
+        Some(entry) => {
+          self.searched += 1;
+          if self.matcher.is_match(&entry.message) {
+            self.hits.push_back(Hit {
+              channel: channel.clone(),
+              seq: *seq,
+            });
+          }
+          // everything older is before the date range too
+          let before_range = self.matcher.from.is_some_and(|from| timestamp < from);
+          match seq.checked_sub(1) {
+            Some(prev) if !before_range => {
+              *seq = prev;
+              !has_entry(history.channel(channel), prev)
+            }
+            _ => true,
+          }
+        }
+        None => true,
+      };
+      if done {
+        self.cursors.swap_remove(i);
+      }
+    }
+    self.is_done()
+  }
+
+  fn search_arrivals(&mut self, history: &History) {
+    let mut hits = Vec::new();
+    let channels = history
+      .channels()
+      .filter(|(channel, _)| self.channel.as_deref().iter().all(|c| c == channel));
+    for (channel, h) in channels {
+      let Some(last) = h.iter().next_back().map(|entry| entry.seq) else {
+        continue;
+      };
+      let front = h.iter().next().map_or(last, |entry| entry.seq);
+      // channels joined since the search started are new in their entirety
+      let next = self.arrivals.entry(channel.into()).or_insert(front);
+      for seq in (*next).max(front)..=last {
+        let Some(entry) = h.get(seq) else {
+          continue;
+        };
+        self.searched += 1;
+        if self.matcher.is_match(&entry.message) {
+          let hit = Hit {
+            channel: channel.into(),
+            seq,
+          };
+          hits.push((entry.message.timestamp(), hit));
+        }
+      }
+      *next = (*next).max(last + 1);
+    }
+    // newest first, in front of the older hits
+    hits.sort_by_key(|(timestamp, _)| *timestamp);
+    for (_, hit) in hits {
+      self.hits.push_front(hit);
+    }
+  }
+}
+
+fn has_entry(history: Option<&ChannelHistory>, seq: u64) -> bool {
+  history.is_some_and(|h| h.get(seq).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::event::ChatEvent;
+  use super::*;
+
+  fn chat(channel: &str, name: &str, badges: &str, timestamp: i64, text: &str) -> Message {
+    let login = name.to_lowercase();
+    let line = format!(
+      "@badges={badges};color=;display-name={name};emotes=;id={timestamp};room-id=1;tmi-sent-ts={timestamp};user-id=1 :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #{channel} :{text}"
+    );
+    match ChatEvent::parse(&twitch::parse(line).unwrap()).unwrap() {
+      ChatEvent::PrivMsg(m) => Message::Chat(m),
+      _ => panic!("expected a PRIVMSG"),
+    }
+  }
+
+  fn matches(query: &Query, message: &Message) -> bool {
+    query.compile().unwrap().is_match(message)
+  }
+
+  fn text(text: &str) -> Query {
+    Query {
+      text: text.into(),
+      ..Default::default()
+    }
+  }
+
+  /// Local midnight of `date` in milliseconds, plus `hours`.
+  fn local_ms(date: NaiveDate, hours: i64) -> i64 {
+    local_midnight(date).unwrap().timestamp_millis() + hours * 3_600_000
+  }
+
+  /// Runs `search` to completion, one step of no budget at a time. Returns the number of steps.
+  fn run(search: &mut Search, history: &History) -> usize {
+    let mut steps = 1;
+    while !search.step(history, Duration::ZERO) {
+      steps += 1;
+    }
+    steps
+  }
+
+  fn seqs(search: &Search) -> Vec<(&str, u64)> {
+    search
+      .hits()
+      .iter()
+      .map(|hit| (hit.channel.as_str(), hit.seq))
+      .collect()
+  }
+
+  #[test]
+  fn empty_queries() {
+    assert!(Query::default().is_empty());
+    let query = Query {
+      channel: Some("forsen".into()),
+      regex: true,
+      ..Default::default()
+    };
+    assert!(query.is_empty());
+    assert!(!text("a").is_empty());
+    let query = Query {
+      has_link: true,
+      ..Default::default()
+    };
+    assert!(!query.is_empty());
+  }
+
+  #[test]
+  fn matches_text() {
+    let message = chat("forsen", "Ronni", "", 1, "Hello there, a.b");
+    assert!(matches(&text("hello"), &message));
+    assert!(matches(&text("HELLO THERE"), &message));
+    // not only whole words
+    assert!(matches(&text("ell"), &message));
+    // literal unless it's a regex
+    assert!(matches(&text("a.b"), &message));
+    assert!(!matches(&text("o.t"), &message));
+    let regex = Query {
+      regex: true,
+      ..text("o.t")
+    };
+    assert!(matches(&regex, &message));
+    assert!(!matches(&text("general"), &message));
+
+    let invalid = Query {
+      regex: true,
+      ..text("(")
+    };
+    assert!(invalid.compile().is_err());
+    assert!(text("(").compile().is_ok());
+  }
+
+  #[test]
+  fn matches_authors_and_badges() {
+    let message = chat("forsen", "Ronni", "subscriber/6,premium/1", 1, "hi");
+    for author in ["ronni", "Ronni", " @RONNI "] {
+      let query = Query {
+        author: author.into(),
+        ..Default::default()
+      };
+      assert!(matches(&query, &message), "{author}");
+    }
+    let query = Query {
+      author: "ron".into(),
+      ..Default::default()
+    };
+    assert!(!matches(&query, &message));
+
+    let badge = |badge: &str| Query {
+      badge: badge.into(),
+      ..Default::default()
+    };
+    assert!(matches(&badge("Subscriber"), &message));
+    assert!(matches(&badge("premium"), &message));
+    assert!(!matches(&badge("moderator"), &message));
+    assert!(!matches(&badge("sub"), &message));
+
+    assert!(!matches(&Query::default(), &Message::system("hi")));
+  }
+
+  #[test]
+  fn matches_links_and_dates() {
+    let links = Query {
+      has_link: true,
+      ..Default::default()
+    };
+    assert!(matches(
+      &links,
+      &chat("forsen", "a", "", 1, "see https://example.com.")
+    ));
+    assert!(!matches(
+      &links,
+      &chat("forsen", "a", "", 1, "see example dot com")
+    ));
+
+    let day = NaiveDate::from_ymd_opt(2023, 7, 14).unwrap();
+    let query = Query {
+      from: Some(day),
+      to: Some(day),
+      ..Default::default()
+    };
+    let at = |ms| chat("forsen", "a", "", ms, "hi");
+    assert!(matches(&query, &at(local_ms(day, 0))));
+    assert!(matches(&query, &at(local_ms(day, 24) - 1)));
+    assert!(!matches(&query, &at(local_ms(day, 0) - 1)));
+    assert!(!matches(&query, &at(local_ms(day, 24))));
+  }
+
+  #[test]
+  fn resumes_steps() {
+    let mut history = History::new(2000);
+    for i in 0..1000 {
+      let text = if i % 10 == 0 { "hit" } else { "miss" };
+      history.push("forsen", chat("forsen", "a", "", i, text));
+    }
+
+    let mut search = Search::new(&text("hit"), &history).unwrap();
+    // every step searches at least a batch, however small the budget
+    assert!(!search.step(&history, Duration::ZERO));
+    assert_eq!(search.searched(), 256);
+    assert_eq!(search.hits().len(), 25);
+    assert_eq!(run(&mut search, &history), 3);
+    assert!(search.is_done());
+    assert_eq!(search.searched(), 1000);
+    // every hit is kept, even beyond what the view shows
+    assert_eq!(search.hits().len(), 100);
+
+    let seqs = seqs(&search);
+    assert!(seqs.windows(2).all(|w| w[0].1 > w[1].1));
+    let first = history
+      .channel("forsen")
+      .unwrap()
+      .iter()
+      .next()
+      .unwrap()
+      .seq;
+    assert_eq!(seqs.last(), Some(&("forsen", first)));
+  }
+
+  #[test]
+  fn searches_channels_newest_first() {
+    let mut history = History::new(10);
+    let a = history.push("a", chat("a", "x", "", 1, "hit"));
+    let b = history.push("b", chat("b", "x", "", 2, "hit"));
+    let c = history.push("c", chat("c", "x", "", 3, "hit"));
+    let a2 = history.push("a", chat("a", "x", "", 4, "hit"));
+    history.push("b", chat("b", "x", "", 5, "miss"));
+
+    let mut search = Search::new(&text("hit"), &history).unwrap();
+    assert_eq!(run(&mut search, &history), 1);
+    assert_eq!(seqs(&search), [("a", a2), ("c", c), ("b", b), ("a", a)]);
+
+    let query = Query {
+      channel: Some("a".into()),
+      ..text("hit")
+    };
+    let mut search = Search::new(&query, &history).unwrap();
+    run(&mut search, &history);
+    assert_eq!(seqs(&search), [("a", a2), ("a", a)]);
+    assert_eq!(search.searched(), 2);
+  }
+
+  #[test]
+  fn searches_arrivals() {
+    let mut history = History::new(10);
+    let old = history.push("a", chat("a", "x", "", 1, "hit"));
+    let query = Query {
+      channel: Some("a".into()),
+      ..text("hit")
+    };
+    let mut search = Search::new(&query, &history).unwrap();
+    assert!(search.step(&history, Duration::ZERO));
+
+    let new = history.push("a", chat("a", "x", "", 2, "hit"));
+    history.push("a", chat("a", "x", "", 3, "miss"));
+    history.push("b", chat("b", "x", "", 4, "hit"));
+    assert!(search.step(&history, Duration::ZERO));
+    assert_eq!(seqs(&search), [("a", new), ("a", old)]);
+    assert_eq!(search.searched(), 3);
+
+    // only searched once
+    assert!(search.step(&history, Duration::ZERO));
+    assert_eq!(search.searched(), 3);
+
+    // channels joined after the search started too
+    let mut search = Search::new(&text("hit"), &history).unwrap();
+    run(&mut search, &history);
+    let joined = history.push("c", chat("c", "x", "", 5, "hit"));
+    let newer = history.push("a", chat("a", "x", "", 6, "hit"));
+    search.step(&history, Duration::ZERO);
+    assert_eq!(seqs(&search)[..2], [("a", newer), ("c", joined)]);
+    assert_eq!(search.hits().len(), 5);
+  }
+}
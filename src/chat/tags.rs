@@ -0,0 +1,304 @@
+use std::fmt::Display;
+use std::ops::Range;
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The `@key=value;...` section of a raw IRC line.
+pub struct Tags<'a> {
+  pairs: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Tags<'a> {
+  pub fn parse(raw: &'a str) -> Self {
+    let pairs = match raw.strip_prefix('@') {
+      Some(rest) => rest
+        .split_once(' ')
+        .map(|(tags, _)| tags)
+        .unwrap_or(rest)
+        .split(';')
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .collect(),
+      None => Vec::new(),
+    };
+    Self { pairs }
+  }
+
+  /// The value as it appears on the wire, still escaped.
+  pub fn raw(&self, key: &str) -> Option<&'a str> {
+    self.pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+  }
+
+  /// Every tag with its unescaped value, in the order they appear.
+  pub fn iter(&self) -> impl Iterator<Item = (&'a str, String)> + '_ {
+    self
+      .pairs
+      .iter()
+      .map(|(key, value)| (*key, unescape(value)))
+  }
+
+  /// The unescaped value, or `None` if the tag is missing or empty.
+  pub fn string(&self, key: &str) -> Option<String> {
+    self.raw(key).filter(|v| !v.is_empty()).map(unescape)
+  }
+
+  pub fn bool(&self, key: &str) -> bool {
+    self.raw(key).is_some_and(|v| v == "1")
+  }
+
+  pub fn number<T: FromStr>(&self, key: &str) -> Option<T> {
+    self.raw(key)?.parse().ok()
+  }
+
+  /// `tmi-sent-ts`, or the current time if it is missing.
+  pub fn timestamp(&self) -> DateTime<Utc> {
+    self
+      .number::<i64>("tmi-sent-ts")
+      .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+      .unwrap_or_else(Utc::now)
+  }
+
+  pub fn color(&self) -> Option<Color> {
+    self.raw("color")?.parse().ok()
+  }
+
+  /// `badges` or `badge-info`, which share the `name/version,...` format.
+  pub fn badges(&self, key: &str) -> Vec<Badge> {
+    let Some(value) = self.raw(key) else {
+      return Vec::new();
+    };
+    value
+      .split(',')
+      .filter_map(|badge| badge.split_once('/'))
+      .map(|(name, version)| Badge {
+        name: name.into(),
+        version: unescape(version),
+      })
+      .collect()
+  }
+
+  /// `emotes`, in the `id:start-end,start-end/id:start-end` format.
+  pub fn emotes(&self) -> Vec<Emote> {
+    let Some(value) = self.raw("emotes") else {
+      return Vec::new();
+    };
+    value
+      .split('/')
+      .filter_map(|emote| emote.split_once(':'))
+      .map(|(id, ranges)| Emote {
+        id: id.into(),
+        ranges: ranges
+          .split(',')
+          .filter_map(|range| range.split_once('-'))
+          .filter_map(|(start, end)| {
+            let start = start.parse::<usize>().ok()?;
+            // inclusive on the wire
+            let end = end.parse::<usize>().ok()?.checked_add(1)?;
+            (start < end).then_some(start..end)
+          })
+          .collect(),
+      })
+      .filter(|emote| !emote.ranges.is_empty())
+      .collect()
+  }
+
+  pub fn emote_sets(&self) -> Vec<String> {
+    self
+      .raw("emote-sets")
+      .map(|v| {
+        v.split(',')
+          .filter(|s| !s.is_empty())
+          .map(String::from)
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+}
+
+/// Reverses IRCv3 tag value escaping.
+pub fn unescape(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some(':') => out.push(';'),
+      Some('s') => out.push(' '),
+      Some('\\') => out.push('\\'),
+      Some('r') => out.push('\r'),
+      Some('n') => out.push('\n'),
+      Some(c) => out.push(c),
+      // a trailing lone backslash is dropped
+      None => {}
+    }
+  }
+  out
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Color {
+  pub r: u8,
+  pub g: u8,
+  pub b: u8,
+}
+
+impl FromStr for Color {
+  type Err = ();
+
+  /// Parses `#RRGGBB`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let hex = s.strip_prefix('#').ok_or(())?;
+    if hex.len() != 6 || !hex.is_ascii() {
+      return Err(());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| ());
+    Ok(Color {
+      r: channel(0)?,
+      g: channel(2)?,
+      b: channel(4)?,
+    })
+  }
+}
+
+impl Display for Color {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+  }
+}
+
+impl Serialize for Color {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for Color {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse()
+      .map_err(|_| serde::de::Error::custom(format!("invalid color `{s}`, expected #RRGGBB")))
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Badge {
+  pub name: String,
+  pub version: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Emote {
+  pub id: String,
+  /// Positions in the message text, counted in code points.
+  pub ranges: Vec<Range<usize>>,
+}
+
+#[cfg(test)]
+mod tests {
+  #![allow(clippy::single_range_in_vec_init)]
+
+  use super::*;
+
+  const PRIVMSG: &str = "@badge-info=subscriber/8;badges=subscriber/6,premium/1;color=#FF4500;display-name=Ronni;emotes=25:0-4,12-16/1902:6-10;first-msg=0;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;mod=0;room-id=1337;subscriber=1;tmi-sent-ts=1507246572675;turbo=1;user-id=1337;user-type= :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni :Kappa Keepo Kappa";
+
+  #[test]
+  fn decodes_tags() {
+    let tags = Tags::parse(PRIVMSG);
+    assert_eq!(tags.raw("user-type"), Some(""));
+    assert_eq!(tags.string("user-type"), None);
+    assert_eq!(tags.string("display-name").as_deref(), Some("Ronni"));
+    assert!(tags.bool("subscriber"));
+    assert!(!tags.bool("mod"));
+    assert!(!tags.bool("missing"));
+    assert_eq!(tags.number::<u64>("room-id"), Some(1337));
+    assert_eq!(tags.timestamp().timestamp_millis(), 1507246572675);
+    assert_eq!(
+      tags.color(),
+      Some(Color {
+        r: 0xFF,
+        g: 0x45,
+        b: 0
+      })
+    );
+    assert_eq!(
+      tags.badges("badges"),
+      [
+        Badge {
+          name: "subscriber".into(),
+          version: "6".into()
+        },
+        Badge {
+          name: "premium".into(),
+          version: "1".into()
+        }
+      ]
+    );
+    assert_eq!(
+      tags.emotes(),
+      [
+        Emote {
+          id: "25".into(),
+          ranges: vec![0..5, 12..17]
+        },
+        Emote {
+          id: "1902".into(),
+          ranges: vec![6..11]
+        }
+      ]
+    );
+  }
+
+  #[test]
+  fn unescapes_values() {
+    let raw = r"@system-msg=ronni\shas\ssubscribed\sfor\s6\smonths!;msg=a\:b\\c\rd\ne\x\ :tmi.twitch.tv USERNOTICE #dallas";
+    let tags = Tags::parse(raw);
+    assert_eq!(
+      tags.string("system-msg").as_deref(),
+      Some("ronni has subscribed for 6 months!")
+    );
+    assert_eq!(tags.string("msg").as_deref(), Some("a;b\\c\rd\nex"));
+  }
+
+  #[test]
+  fn without_tags() {
+    let tags = Tags::parse(":ronni!ronni@ronni.tmi.twitch.tv JOIN #dallas");
+    assert_eq!(tags.iter().count(), 0);
+    assert!(tags.emotes().is_empty());
+    assert!(tags.badges("badges").is_empty());
+  }
+
+  #[test]
+  fn drops_invalid_emote_ranges() {
+    let raw = format!(
+      "@emotes=25:0-{},5-3,x-2,7-7/1902:0-18446744073709551615/33:;id=1 :a!a@a PRIVMSG #a :hi",
+      usize::MAX - 1
+    );
+    assert_eq!(
+      Tags::parse(&raw).emotes(),
+      [Emote {
+        id: "25".into(),
+        ranges: vec![0..usize::MAX, 7..8]
+      }]
+    );
+  }
+
+  #[test]
+  fn parses_colors() {
+    assert_eq!(
+      "#1E90FF".parse(),
+      Ok(Color {
+        r: 0x1E,
+        g: 0x90,
+        b: 0xFF
+      })
+    );
+    assert_eq!("1E90FF".parse::<Color>(), Err(()));
+    assert_eq!("#1E90F".parse::<Color>(), Err(()));
+    assert_eq!("#ÄÄÄ".parse::<Color>(), Err(()));
+    assert_eq!(Color { r: 1, g: 2, b: 255 }.to_string(), "#0102FF");
+  }
+}
@@ -0,0 +1,394 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::event::Whisper;
+use crate::util::{data_dir, write_atomic};
+
+/// Messages kept per conversation, older ones are dropped.
+pub const CONVERSATION_CAP: usize = 500;
+/// Changes are written at most this often.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WhisperMessage {
+  pub id: String,
+  /// Display name of the sender.
+  pub from: String,
+  pub text: String,
+  pub outgoing: bool,
+  /// Set if sending an outgoing whisper failed.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+  pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Conversation {
+  /// The other user.
+  pub login: String,
+  pub name: String,
+  /// Known once the user whispered us, so that replying doesn't need to look it up.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user_id: Option<String>,
+  pub messages: VecDeque<WhisperMessage>,
+  #[serde(default)]
+  pub unread: usize,
+}
+
+impl Conversation {
+  fn new(login: String, name: String) -> Self {
+    Self {
+      login,
+      name,
+      user_id: None,
+      messages: VecDeque::new(),
+      unread: 0,
+    }
+  }
+
+  fn push(&mut self, message: WhisperMessage) {
+    if self.messages.len() == CONVERSATION_CAP {
+      self.messages.pop_front();
+    }
+    self.messages.push_back(message);
+  }
+
+  pub fn last_activity(&self) -> Option<DateTime<Utc>> {
+    self.messages.back().map(|m| m.timestamp)
+  }
+}
+
+/// Whisper conversations by the login of the other user, persisted between sessions.
+#[derive(Default)]
+pub struct Whispers {
+  conversations: HashMap<String, Conversation>,
+  next_local_id: u64,
+  saver: Option<Saver>,
+  /// Changed since the last save.
+  dirty: bool,
+}
+
+impl Whispers {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("whispers.json"))
+  }
+
+  /// Returns no conversations if there is no whisper file yet.
+  pub fn load() -> Result<Self, Error> {
+    match Self::path() {
+      Some(path) => Self::load_from(&path),
+      None => Ok(Self::new()),
+    }
+  }
+
+  /// Changes are saved back to `path` by `save`.
+  pub fn load_from(path: &Path) -> Result<Self, Error> {
+    let conversations: Vec<Conversation> = match std::fs::read(path) {
+      Ok(data) => serde_json::from_slice(&data)?,
+      Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+      Err(e) => return Err(e.into()),
+    };
+    Ok(Self {
+      conversations: conversations
+        .into_iter()
+        .map(|c| (c.login.clone(), c))
+        .collect(),
+      next_local_id: 0,
+      saver: Some(Saver::start(path.into())),
+      dirty: false,
+    })
+  }
+
+  /// Writes the conversations on a background thread if they changed, at most once per
+  /// `SAVE_INTERVAL`. Returns how long to wait before calling this again if there are changes
+  /// left to save.
+  pub fn save(&mut self) -> Option<Duration> {
+    let saver = self.saver.as_mut().filter(|_| self.dirty)?;
+    let wait = saver
+      .saved_at
+      .map(|at| SAVE_INTERVAL.saturating_sub(at.elapsed()))
+      .unwrap_or_default();
+    if !wait.is_zero() {
+      return Some(wait);
+    }
+    let conversations = self.conversations.values().collect::<Vec<_>>();
+    match serde_json::to_vec(&conversations) {
+      Ok(data) => saver.send(data),
+      Err(e) => tracing::warn!("failed to save whisper history: {e}"),
+    }
+    self.dirty = false;
+    None
+  }
+
+  pub fn get(&self, login: &str) -> Option<&Conversation> {
+    self.conversations.get(&normalize(login))
+  }
+
+  /// Most recently active first.
+  pub fn conversations(&self) -> Vec<&Conversation> {
+    let mut conversations = self.conversations.values().collect::<Vec<_>>();
+    conversations.sort_by_key(|c| std::cmp::Reverse(c.last_activity()));
+    conversations
+  }
+
+  pub fn unread(&self) -> usize {
+    self.conversations.values().map(|c| c.unread).sum()
+  }
+
+  pub fn mark_read(&mut self, login: &str) {
+    if let Some(conversation) = self.conversations.get_mut(&normalize(login)) {
+      self.dirty |= conversation.unread > 0;
+      conversation.unread = 0;
+    }
+  }
+
+  /// Opens a conversation without any messages yet.
+  pub fn open(&mut self, login: &str) {
+    let login = normalize(login);
+    if !login.is_empty() && !self.conversations.contains_key(&login) {
+      self
+        .conversations
+        .insert(login.clone(), Conversation::new(login.clone(), login));
+      self.dirty = true;
+    }
+  }
+
+  /// Stores an incoming whisper. Whispers we sent ourselves are stored under the recipient.
+  pub fn handle(&mut self, whisper: &Whisper, own_login: Option<&str>) {
+    let outgoing = own_login.is_some_and(|own| own.eq_ignore_ascii_case(&whisper.author.login));
+    let (login, name) = match outgoing {
+      true => (
+        whisper.recipient_login.clone(),
+        whisper.recipient_login.clone(),
+      ),
+      false => (whisper.author.login.clone(), whisper.author.name.clone()),
+    };
+    let conversation = self
+      .conversations
+      .entry(login.clone())
+      .or_insert_with(|| Conversation::new(login, name.clone()));
+    if !outgoing {
+      conversation.name = name;
+      conversation.user_id = Some(whisper.author.id.clone());
+      conversation.unread += 1;
+    }
+    conversation.push(WhisperMessage {
+      id: whisper.id.clone(),
+      from: whisper.author.name.clone(),
+      text: whisper.text.clone(),
+      outgoing,
+      error: None,
+      timestamp: whisper.timestamp,
+    });
+    self.dirty = true;
+  }
+
+  /// Stores a whisper we are about to send, returning its local id for `set_error`.
+  pub fn push_outgoing(&mut self, to: &str, from: &str, text: &str) -> String {
+    self.open(to);
+    let id = format!(
+      "local-{}-{}",
+      Utc::now().timestamp_millis(),
+      self.next_local_id
+    );
+    self.next_local_id += 1;
+    if let Some(conversation) = self.conversations.get_mut(&normalize(to)) {
+      conversation.push(WhisperMessage {
+        id: id.clone(),
+        from: from.into(),
+        text: text.into(),
+        outgoing: true,
+        error: None,
+        timestamp: Utc::now(),
+      });
+    }
+    self.dirty = true;
+    id
+  }
+
+  pub fn set_error(&mut self, login: &str, id: &str, error: String) {
+    let message = self
+      .conversations
+      .get_mut(&normalize(login))
+      .and_then(|c| c.messages.iter_mut().rev().find(|m| m.id == id));
+    if let Some(message) = message {
+      message.error = Some(error);
+      self.dirty = true;
+    }
+  }
+}
+
+/// Conversations are keyed by lowercase login, without an `@`.
+fn normalize(login: &str) -> String {
+  login.trim().trim_start_matches('@').to_lowercase()
+}
+
+impl Drop for Whispers {
+  fn drop(&mut self) {
+    if let Some(saver) = &mut self.saver {
+      // skip the interval, this is the last chance
+      saver.saved_at = None;
+    }
+    self.save();
+  }
+}
+
+/// Writes snapshots of the whisper history on a background thread.
+struct Saver {
+  snapshots: Option<mpsc::Sender<Vec<u8>>>,
+  writer: Option<JoinHandle<()>>,
+  saved_at: Option<Instant>,
+}
+
+impl Saver {
+  fn start(path: PathBuf) -> Self {
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+    let writer = thread::Builder::new()
+      .name("whispers".into())
+      .spawn(move || {
+        while let Ok(data) = rx.recv() {
+          // only the latest snapshot matters
+          let data = rx.try_iter().last().unwrap_or(data);
+          if let Err(e) = write_atomic(&path, &data) {
+            tracing::warn!("{}", Error::Io(e));
+          }
+        }
+      })
+      .expect("failed to spawn whisper thread");
+    Self {
+      snapshots: Some(tx),
+      writer: Some(writer),
+      saved_at: None,
+    }
+  }
+
+  fn send(&mut self, data: Vec<u8>) {
+    if let Some(snapshots) = &self.snapshots {
+      let _ = snapshots.send(data);
+    }
+    self.saved_at = Some(Instant::now());
+  }
+}
+
+impl Drop for Saver {
+  fn drop(&mut self) {
+    // the writer finishes what is left once the channel is closed
+    self.snapshots = None;
+    if let Some(writer) = self.writer.take() {
+      let _ = writer.join();
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  Json(serde_json::Error),
+}
+
+impl From<std::io::Error> for Error {
+  fn from(value: std::io::Error) -> Self {
+    Self::Io(value)
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(value: serde_json::Error) -> Self {
+    Self::Json(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Io(e) => write!(f, "failed to access whisper history: {e}"),
+      Error::Json(e) => write!(f, "failed to parse whisper history: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use super::super::event::ChatEvent;
+  use super::*;
+
+  fn whisper(from: &str, to: &str, text: &str) -> Whisper {
+    let login = from.to_lowercase();
+    let line = format!(
+      "@badges=;color=;display-name={from};emotes=;message-id=1;thread-id=1_2;user-id=87654321 :{login}!{login}@{login}.tmi.twitch.tv WHISPER {to} :{text}"
+    );
+    match ChatEvent::parse(&twitch::parse(line).unwrap()) {
+      Some(ChatEvent::Whisper(whisper)) => whisper,
+      _ => panic!("expected a WHISPER"),
+    }
+  }
+
+  #[test]
+  fn conversations() {
+    let mut whispers = Whispers::new();
+    whispers.handle(&whisper("PetsgomOO", "foo", "hello"), Some("foo"));
+    whispers.handle(&whisper("foo", "petsgomoo", "hi"), Some("foo"));
+    whispers.push_outgoing("@PetsgomOO", "foo", "how are you");
+
+    let conversation = whispers.get("petsgomoo").unwrap();
+    assert_eq!(conversation.name, "PetsgomOO");
+    assert_eq!(conversation.user_id.as_deref(), Some("87654321"));
+    let outgoing = conversation
+      .messages
+      .iter()
+      .map(|m| m.outgoing)
+      .collect::<Vec<_>>();
+    assert_eq!(outgoing, [false, true, true]);
+    assert_eq!(whispers.unread(), 1);
+    whispers.mark_read("petsgomoo");
+    assert_eq!(whispers.unread(), 0);
+    // nothing to save to
+    assert_eq!(whispers.save(), None);
+  }
+
+  #[test]
+  fn saves_in_the_background() {
+    let dir = std::env::temp_dir().join(format!("nanochat-whispers-{:x}", rand::random::<u64>()));
+    let path = dir.join("whispers.json");
+
+    let mut whispers = Whispers::load_from(&path).unwrap();
+    assert_eq!(whispers.save(), None);
+    whispers.handle(&whisper("a", "foo", "one"), Some("foo"));
+    assert_eq!(whispers.save(), None);
+    // written at most once per interval
+    let id = whispers.push_outgoing("a", "foo", "two");
+    whispers.set_error("a", &id, "failed".into());
+    assert!(whispers.save().is_some_and(|wait| wait <= SAVE_INTERVAL));
+    // and once more when closing
+    drop(whispers);
+
+    let whispers = Whispers::load_from(&path).unwrap();
+    let conversation = whispers.get("a").unwrap();
+    let texts = conversation
+      .messages
+      .iter()
+      .map(|m| m.text.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(texts, ["one", "two"]);
+    assert_eq!(conversation.messages[1].error.as_deref(), Some("failed"));
+    assert_eq!(conversation.unread, 1);
+    assert!(!path.with_extension("tmp").exists());
+    drop(whispers);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat::alias::Alias;
+use crate::chat::collapse::CollapseConfig;
+use crate::chat::command::QuickAction;
+use crate::chat::filter::IgnoreRules;
+use crate::chat::highlight;
+use crate::chat::logs::LogConfig;
+use crate::emotes::Source;
+use crate::recent_messages;
+use crate::util::data_dir;
+
+/// User configuration, stored as JSON in the data directory.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+  pub highlights: Vec<highlight::Rule>,
+  pub ignores: IgnoreRules,
+  /// Expanded before slash commands.
+  pub aliases: Vec<Alias>,
+  /// Buttons next to each message in channels we moderate.
+  pub quick_actions: Vec<QuickAction>,
+  /// Which channels are logged to disk, and how.
+  pub logs: LogConfig,
+  /// Where the messages from before joining a channel are loaded from.
+  pub recent_messages: recent_messages::Config,
+  /// Merging of repeated messages from different users.
+  pub collapse: CollapseConfig,
+  /// Which third-party emote wins when several share a name, highest first. Sources which
+  /// aren't listed are not shown.
+  pub emote_priority: Vec<Source>,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      highlights: Vec::new(),
+      ignores: IgnoreRules::default(),
+      aliases: Vec::new(),
+      quick_actions: QuickAction::defaults(),
+      logs: LogConfig::default(),
+      recent_messages: recent_messages::Config::default(),
+      collapse: CollapseConfig::default(),
+      emote_priority: Source::DEFAULT_PRIORITY.to_vec(),
+    }
+  }
+}
+
+impl Config {
+  pub fn path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("config.json"))
+  }
+
+  /// Returns the default config if there is no config file yet.
+  pub fn load() -> Result<Self, Error> {
+    match Self::path() {
+      Some(path) => Self::load_from(&path),
+      None => Ok(Self::default()),
+    }
+  }
+
+  pub fn load_from(path: &Path) -> Result<Self, Error> {
+    match std::fs::read(path) {
+      Ok(data) => Ok(serde_json::from_slice(&data)?),
+      Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  pub fn save(&self) -> Result<(), Error> {
+    match Self::path() {
+      Some(path) => self.save_to(&path),
+      None => Ok(()),
+    }
+  }
+
+  pub fn save_to(&self, path: &Path) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  Json(serde_json::Error),
+}
+
+impl From<std::io::Error> for Error {
+  fn from(value: std::io::Error) -> Self {
+    Self::Io(value)
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(value: serde_json::Error) -> Self {
+    Self::Json(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Io(e) => write!(f, "failed to read config: {e}"),
+      Error::Json(e) => write!(f, "failed to parse config: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,272 @@
+pub mod bttv;
+pub mod ffz;
+pub mod seventv;
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use futures_util::future::BoxFuture;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+pub use self::bttv::Bttv;
+pub use self::ffz::Ffz;
+pub use self::seventv::SevenTv;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+  Twitch,
+  Bttv,
+  Ffz,
+  SevenTv,
+}
+
+impl Source {
+  /// The order in which third-party emotes with the same name win, unless configured otherwise.
+  pub const DEFAULT_PRIORITY: &[Source] = &[Source::SevenTv, Source::Bttv, Source::Ffz];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Source::Twitch => "Twitch",
+      Source::Bttv => "BetterTTV",
+      Source::Ffz => "FrankerFaceZ",
+      Source::SevenTv => "7TV",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Emote {
+  pub id: String,
+  pub name: String,
+  pub source: Source,
+  /// The smallest size, meant for display at regular text size.
+  pub url: String,
+  /// The largest size available, e.g. for tooltips.
+  pub url_hd: Option<String>,
+  pub animated: bool,
+  /// Drawn on top of the previous emote instead of next to it.
+  pub zero_width: bool,
+}
+
+impl Emote {
+  /// A native Twitch emote, as referenced by the `emotes` tag.
+  pub fn twitch(id: impl ToString, name: impl ToString) -> Self {
+    let id = id.to_string();
+    let url =
+      |scale: &str| format!("https://static-cdn.jtvnw.net/emoticons/v2/{id}/default/dark/{scale}");
+    Self {
+      url: url("1.0"),
+      url_hd: Some(url("3.0")),
+      name: name.to_string(),
+      source: Source::Twitch,
+      animated: false,
+      zero_width: false,
+      id,
+    }
+  }
+}
+
+/// A source of emotes which are matched by name, rather than through the `emotes` tag.
+pub trait Provider: Send + Sync {
+  fn source(&self) -> Source;
+  fn global(&self) -> BoxFuture<'_, Result<Vec<Emote>, Error>>;
+  fn channel<'a>(&'a self, room_id: &'a str) -> BoxFuture<'a, Result<Vec<Emote>, Error>>;
+}
+
+pub fn default_providers(http: reqwest::Client) -> Vec<Box<dyn Provider>> {
+  vec![
+    Box::new(SevenTv::new(http.clone())),
+    Box::new(Bttv::new(http.clone())),
+    Box::new(Ffz::new(http)),
+  ]
+}
+
+/// Third-party emotes by name, for the global set and each channel's set.
+pub struct EmoteStore {
+  /// Earlier sources win when two emotes share a name.
+  priority: Vec<Source>,
+  global: HashMap<Source, Vec<Emote>>,
+  channels: HashMap<String, HashMap<Source, Vec<Emote>>>,
+
+  global_index: HashMap<String, Emote>,
+  channel_index: HashMap<String, HashMap<String, Emote>>,
+}
+
+impl EmoteStore {
+  pub fn new() -> Self {
+    Self::with_priority(Source::DEFAULT_PRIORITY.to_vec())
+  }
+
+  /// Sources which aren't in `priority` are not matched by name.
+  pub fn with_priority(priority: Vec<Source>) -> Self {
+    Self {
+      priority,
+      global: HashMap::new(),
+      channels: HashMap::new(),
+      global_index: HashMap::new(),
+      channel_index: HashMap::new(),
+    }
+  }
+
+  pub fn set_global(&mut self, source: Source, emotes: Vec<Emote>) {
+    self.global.insert(source, emotes);
+    self.global_index = self.index(&self.global);
+  }
+
+  pub fn set_channel(&mut self, room_id: &str, source: Source, emotes: Vec<Emote>) {
+    let sets = self.channels.entry(room_id.into()).or_default();
+    sets.insert(source, emotes);
+    let index = self.index(&self.channels[room_id]);
+    self.channel_index.insert(room_id.into(), index);
+  }
+
+  pub fn remove_channel(&mut self, room_id: &str) {
+    self.channels.remove(room_id);
+    self.channel_index.remove(room_id);
+  }
+
+  /// Channel emotes take precedence over global ones.
+  pub fn lookup(&self, room_id: &str, name: &str) -> Option<&Emote> {
+    self
+      .channel_index
+      .get(room_id)
+      .and_then(|index| index.get(name))
+      .or_else(|| self.global_index.get(name))
+  }
+
+  /// The global emotes of `source`, including ones which aren't matched by name, such as the
+  /// user's Twitch emote sets.
+  pub fn global(&self, source: Source) -> &[Emote] {
+    self
+      .global
+      .get(&source)
+      .map(Vec::as_slice)
+      .unwrap_or_default()
+  }
+
+  /// Every emote usable in `room_id`, without duplicate names.
+  pub fn all<'a>(&'a self, room_id: &str) -> impl Iterator<Item = &'a Emote> + 'a {
+    let channel = self.channel_index.get(room_id);
+    channel.into_iter().flat_map(|index| index.values()).chain(
+      self
+        .global_index
+        .values()
+        .filter(move |e| !channel.is_some_and(|index| index.contains_key(&e.name))),
+    )
+  }
+
+  fn index(&self, sets: &HashMap<Source, Vec<Emote>>) -> HashMap<String, Emote> {
+    let mut index = HashMap::new();
+    // insert in reverse priority order so that higher priority sources overwrite lower ones
+    for source in self.priority.iter().rev() {
+      for emote in sets.get(source).into_iter().flatten() {
+        index.insert(emote.name.clone(), emote.clone());
+      }
+    }
+    index
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Http(reqwest::Error),
+  Status(StatusCode),
+}
+
+impl From<reqwest::Error> for Error {
+  fn from(value: reqwest::Error) -> Self {
+    Self::Http(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Http(e) => write!(f, "failed to load emotes: {e}"),
+      Error::Status(status) => write!(f, "failed to load emotes: {status}"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+/// GETs `url`, treating `404 Not Found` as `None`, which providers use for channels without emotes.
+async fn get_json<T: serde::de::DeserializeOwned>(
+  http: &reqwest::Client,
+  url: &str,
+) -> Result<Option<T>, Error> {
+  tracing::debug!(url, "fetching emotes");
+  let response = http.get(url).send().await?;
+  match response.status() {
+    StatusCode::NOT_FOUND => Ok(None),
+    status if status.is_success() => Ok(Some(response.json().await?)),
+    status => Err(Error::Status(status)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn emote(source: Source, name: &str) -> Emote {
+    Emote {
+      id: format!("{}-{name}", source.name()),
+      name: name.into(),
+      source,
+      url: String::new(),
+      url_hd: None,
+      animated: false,
+      zero_width: false,
+    }
+  }
+
+  fn lookup(store: &EmoteStore, room_id: &str, name: &str) -> Option<Source> {
+    store.lookup(room_id, name).map(|emote| emote.source)
+  }
+
+  #[test]
+  fn priority() {
+    let fill = |store: &mut EmoteStore| {
+      for source in [Source::Ffz, Source::Bttv, Source::SevenTv] {
+        store.set_global(source, vec![emote(source, "Same")]);
+        store.set_channel("1", source, vec![emote(source, "Channel")]);
+      }
+      store.set_global(
+        Source::Ffz,
+        vec![emote(Source::Ffz, "Same"), emote(Source::Ffz, "Channel")],
+      );
+    };
+
+    let mut store = EmoteStore::new();
+    fill(&mut store);
+    assert_eq!(lookup(&store, "1", "Same"), Some(Source::SevenTv));
+    // channel emotes win over global ones
+    assert_eq!(lookup(&store, "1", "Channel"), Some(Source::SevenTv));
+    assert_eq!(lookup(&store, "2", "Channel"), Some(Source::Ffz));
+
+    let mut store = EmoteStore::with_priority(vec![Source::Ffz, Source::Bttv]);
+    fill(&mut store);
+    assert_eq!(lookup(&store, "1", "Same"), Some(Source::Ffz));
+    assert_eq!(lookup(&store, "1", "Channel"), Some(Source::Ffz));
+    assert!(store.all("1").all(|e| e.source != Source::SevenTv));
+
+    store.set_channel("1", Source::Ffz, Vec::new());
+    assert_eq!(lookup(&store, "1", "Channel"), Some(Source::Bttv));
+    store.remove_channel("1");
+    assert_eq!(lookup(&store, "1", "Channel"), Some(Source::Ffz));
+  }
+
+  #[test]
+  fn priority_from_config() {
+    let config =
+      serde_json::from_str::<crate::config::Config>(r#"{ "emote_priority": ["ffz", "seven_tv"] }"#)
+        .unwrap();
+    assert_eq!(config.emote_priority, [Source::Ffz, Source::SevenTv]);
+    assert_eq!(
+      crate::config::Config::default().emote_priority,
+      Source::DEFAULT_PRIORITY
+    );
+  }
+}
@@ -0,0 +1,181 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::Deserialize;
+
+use super::{get_json, Emote, Error, Provider, Source};
+
+pub const BASE_URL: &str = "https://api.betterttv.net/3";
+pub const CDN_URL: &str = "https://cdn.betterttv.net";
+
+pub struct Bttv {
+  http: reqwest::Client,
+  base_url: String,
+  cdn_url: String,
+}
+
+impl Bttv {
+  pub fn new(http: reqwest::Client) -> Self {
+    Self {
+      http,
+      base_url: BASE_URL.into(),
+      cdn_url: CDN_URL.into(),
+    }
+  }
+
+  pub fn with_base_url(mut self, base_url: impl ToString, cdn_url: impl ToString) -> Self {
+    self.base_url = base_url.to_string();
+    self.cdn_url = cdn_url.to_string();
+    self
+  }
+
+  fn convert(&self, emote: BttvEmote) -> Emote {
+    let url = |scale: &str| format!("{}/emote/{}/{scale}", self.cdn_url, emote.id);
+    Emote {
+      url: url("1x"),
+      url_hd: Some(url("3x")),
+      zero_width: ZERO_WIDTH.contains(&emote.code.as_str()),
+      animated: emote.animated || emote.image_type == "gif",
+      id: emote.id,
+      name: emote.code,
+      source: Source::Bttv,
+    }
+  }
+}
+
+/// BTTV has no flag for these, they are hardcoded in its extension as well.
+const ZERO_WIDTH: &[&str] = &[
+  "SoSnowy",
+  "IceCold",
+  "SantaHat",
+  "TopHat",
+  "ReinDeer",
+  "CandyCane",
+  "cvMask",
+  "cvHazmat",
+];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BttvEmote {
+  id: String,
+  code: String,
+  #[serde(default)]
+  image_type: String,
+  #[serde(default)]
+  animated: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BttvUser {
+  #[serde(default)]
+  channel_emotes: Vec<BttvEmote>,
+  #[serde(default)]
+  shared_emotes: Vec<BttvEmote>,
+}
+
+impl Provider for Bttv {
+  fn source(&self) -> Source {
+    Source::Bttv
+  }
+
+  fn global(&self) -> BoxFuture<'_, Result<Vec<Emote>, Error>> {
+    async move {
+      let url = format!("{}/cached/emotes/global", self.base_url);
+      let emotes = get_json::<Vec<BttvEmote>>(&self.http, &url).await?;
+      Ok(
+        emotes
+          .into_iter()
+          .flatten()
+          .map(|e| self.convert(e))
+          .collect(),
+      )
+    }
+    .boxed()
+  }
+
+  fn channel<'a>(&'a self, room_id: &'a str) -> BoxFuture<'a, Result<Vec<Emote>, Error>> {
+    async move {
+      let url = format!("{}/cached/users/twitch/{room_id}", self.base_url);
+      let Some(user) = get_json::<BttvUser>(&self.http, &url).await? else {
+        return Ok(Vec::new());
+      };
+      Ok(
+        user
+          .channel_emotes
+          .into_iter()
+          .chain(user.shared_emotes)
+          .map(|e| self.convert(e))
+          .collect(),
+      )
+    }
+    .boxed()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::testing::{Response, Server};
+
+  fn emote(id: &str, code: &str, image_type: &str, animated: bool) -> serde_json::Value {
+    json!({
+      "id": id,
+      "code": code,
+      "imageType": image_type,
+      "animated": animated,
+      "userId": "5561169bd6b9d206222a8c19",
+    })
+  }
+
+  #[tokio::test]
+  async fn parses_emotes() {
+    let server = Server::start(|request| match request.path.as_str() {
+      "/3/cached/emotes/global" => Response::json(json!([
+        emote("54fa8f1401e468494b85b537", ":tf:", "png", false),
+        emote("5e76d338d6581c3724c0f0b2", "cvHazmat", "png", false),
+      ])),
+      "/3/cached/users/twitch/22484632" => Response::json(json!({
+        "id": "5575c4d9e3b7ed5e2ea71ec5",
+        "bots": [],
+        "avatar": "https://static-cdn.jtvnw.net/jtv_user_pictures/avatar.png",
+        "channelEmotes": [emote("5f1b0186cf6d2144653d2970", "catJAM", "gif", true)],
+        "sharedEmotes": [{
+          "id": "566ca38765dbbdab32ec0560",
+          "code": "SourPls",
+          "imageType": "gif",
+          "user": { "id": "5561169bd6b9d206222a8c19", "name": "sirsquare" },
+        }],
+      })),
+      _ => Response::new(404, r#"{"message":"user not found"}"#),
+    })
+    .await;
+    let bttv = Bttv::new(reqwest::Client::new())
+      .with_base_url(format!("{}/3", server.url()), "https://cdn.test");
+
+    let global = bttv.global().await.unwrap();
+    assert_eq!(
+      global[0],
+      Emote {
+        id: "54fa8f1401e468494b85b537".into(),
+        name: ":tf:".into(),
+        source: Source::Bttv,
+        url: "https://cdn.test/emote/54fa8f1401e468494b85b537/1x".into(),
+        url_hd: Some("https://cdn.test/emote/54fa8f1401e468494b85b537/3x".into()),
+        animated: false,
+        zero_width: false,
+      }
+    );
+    assert!(global[1].zero_width);
+
+    let channel = bttv.channel("22484632").await.unwrap();
+    let names = channel.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["catJAM", "SourPls"]);
+    // `animated` is missing from older shared emotes
+    assert!(channel.iter().all(|e| e.animated));
+
+    assert_eq!(bttv.channel("1").await.unwrap(), []);
+  }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::Deserialize;
+
+use super::{get_json, Emote, Error, Provider, Source};
+
+pub const BASE_URL: &str = "https://api.frankerfacez.com/v1";
+
+pub struct Ffz {
+  http: reqwest::Client,
+  base_url: String,
+}
+
+impl Ffz {
+  pub fn new(http: reqwest::Client) -> Self {
+    Self {
+      http,
+      base_url: BASE_URL.into(),
+    }
+  }
+
+  pub fn with_base_url(mut self, base_url: impl ToString) -> Self {
+    self.base_url = base_url.to_string();
+    self
+  }
+}
+
+#[derive(Deserialize)]
+struct FfzSet {
+  #[serde(default)]
+  emoticons: Vec<FfzEmote>,
+}
+
+#[derive(Deserialize)]
+struct FfzEmote {
+  id: u64,
+  name: String,
+  urls: HashMap<String, String>,
+  #[serde(default)]
+  animated: Option<HashMap<String, String>>,
+  #[serde(default)]
+  modifier: bool,
+}
+
+impl FfzEmote {
+  fn convert(self) -> Option<Emote> {
+    let animated = self.animated.is_some();
+    let urls = self.animated.unwrap_or(self.urls);
+    let url = |scale: &str| urls.get(scale).map(|url| absolute(url));
+    Some(Emote {
+      url: url("1")?,
+      url_hd: url("4").or_else(|| url("2")),
+      id: self.id.to_string(),
+      name: self.name,
+      source: Source::Ffz,
+      animated,
+      zero_width: self.modifier,
+    })
+  }
+}
+
+/// FFZ sometimes returns protocol-relative URLs.
+fn absolute(url: &str) -> String {
+  match url.strip_prefix("//") {
+    Some(rest) => format!("https://{rest}"),
+    None => url.into(),
+  }
+}
+
+#[derive(Deserialize)]
+struct FfzGlobal {
+  default_sets: Vec<u64>,
+  sets: HashMap<String, FfzSet>,
+}
+
+#[derive(Deserialize)]
+struct FfzRoom {
+  sets: HashMap<String, FfzSet>,
+}
+
+impl Provider for Ffz {
+  fn source(&self) -> Source {
+    Source::Ffz
+  }
+
+  fn global(&self) -> BoxFuture<'_, Result<Vec<Emote>, Error>> {
+    async move {
+      let url = format!("{}/set/global", self.base_url);
+      let Some(mut global) = get_json::<FfzGlobal>(&self.http, &url).await? else {
+        return Ok(Vec::new());
+      };
+      Ok(
+        global
+          .default_sets
+          .iter()
+          .filter_map(|id| global.sets.remove(&id.to_string()))
+          .flat_map(|set| set.emoticons)
+          .filter_map(FfzEmote::convert)
+          .collect(),
+      )
+    }
+    .boxed()
+  }
+
+  fn channel<'a>(&'a self, room_id: &'a str) -> BoxFuture<'a, Result<Vec<Emote>, Error>> {
+    async move {
+      let url = format!("{}/room/id/{room_id}", self.base_url);
+      let Some(room) = get_json::<FfzRoom>(&self.http, &url).await? else {
+        return Ok(Vec::new());
+      };
+      Ok(
+        room
+          .sets
+          .into_values()
+          .flat_map(|set| set.emoticons)
+          .filter_map(FfzEmote::convert)
+          .collect(),
+      )
+    }
+    .boxed()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::testing::{Response, Server};
+
+  #[tokio::test]
+  async fn parses_emotes() {
+    let server = Server::start(|request| match request.path.as_str() {
+      "/v1/set/global" => Response::json(json!({
+        "default_sets": [3],
+        "sets": {
+          "3": {
+            "id": 3,
+            "_type": 1,
+            "title": "Global Emotes",
+            "emoticons": [{
+              "id": 9,
+              "name": "ZrehplaR",
+              "height": 30,
+              "width": 32,
+              "public": false,
+              "hidden": false,
+              "modifier": false,
+              "owner": { "_id": 1, "name": "sirstendec", "display_name": "SirStendec" },
+              "urls": {
+                "1": "https://cdn.frankerfacez.com/emote/9/1",
+                "2": "https://cdn.frankerfacez.com/emote/9/2",
+                "4": "https://cdn.frankerfacez.com/emote/9/4",
+              },
+            }],
+          },
+          "4330": {
+            "id": 4330,
+            "emoticons": [{ "id": 1, "name": "NotDefault", "urls": { "1": "//cdn/1" } }],
+          },
+        },
+        "users": { "4330": ["ffz_user"] },
+      })),
+      "/v1/room/id/22484632" => Response::json(json!({
+        "room": { "_id": 1, "twitch_id": 22484632, "id": "forsen", "set": 1 },
+        "sets": {
+          "1": {
+            "id": 1,
+            "emoticons": [
+              {
+                "id": 720507,
+                "name": "ffzW",
+                "modifier": true,
+                "urls": { "1": "//cdn.frankerfacez.com/emote/720507/1" },
+                "animated": { "1": "//cdn.frankerfacez.com/emote/720507/animated/1" },
+              },
+              { "id": 2, "name": "NoUrls", "urls": {} },
+            ],
+          },
+        },
+      })),
+      _ => Response::new(404, r#"{"error":"Not Found","status":404}"#),
+    })
+    .await;
+    let ffz = Ffz::new(reqwest::Client::new()).with_base_url(format!("{}/v1", server.url()));
+
+    assert_eq!(
+      ffz.global().await.unwrap(),
+      [Emote {
+        id: "9".into(),
+        name: "ZrehplaR".into(),
+        source: Source::Ffz,
+        url: "https://cdn.frankerfacez.com/emote/9/1".into(),
+        url_hd: Some("https://cdn.frankerfacez.com/emote/9/4".into()),
+        animated: false,
+        zero_width: false,
+      }]
+    );
+
+    assert_eq!(
+      ffz.channel("22484632").await.unwrap(),
+      [Emote {
+        id: "720507".into(),
+        name: "ffzW".into(),
+        source: Source::Ffz,
+        url: "https://cdn.frankerfacez.com/emote/720507/animated/1".into(),
+        url_hd: None,
+        animated: true,
+        zero_width: true,
+      }]
+    );
+
+    assert_eq!(ffz.channel("1").await.unwrap(), []);
+  }
+}
@@ -0,0 +1,219 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::Deserialize;
+
+use super::{get_json, Emote, Error, Provider, Source};
+
+pub const BASE_URL: &str = "https://7tv.io/v3";
+
+/// Set on an active emote which should be drawn over the previous one.
+const FLAG_ZERO_WIDTH: u32 = 1;
+
+pub struct SevenTv {
+  http: reqwest::Client,
+  base_url: String,
+}
+
+impl SevenTv {
+  pub fn new(http: reqwest::Client) -> Self {
+    Self {
+      http,
+      base_url: BASE_URL.into(),
+    }
+  }
+
+  pub fn with_base_url(mut self, base_url: impl ToString) -> Self {
+    self.base_url = base_url.to_string();
+    self
+  }
+}
+
+#[derive(Deserialize)]
+struct EmoteSet {
+  #[serde(default)]
+  emotes: Vec<ActiveEmote>,
+}
+
+#[derive(Deserialize)]
+struct ActiveEmote {
+  id: String,
+  name: String,
+  #[serde(default)]
+  flags: u32,
+  data: EmoteData,
+}
+
+#[derive(Deserialize)]
+struct EmoteData {
+  #[serde(default)]
+  animated: bool,
+  host: Host,
+}
+
+#[derive(Deserialize)]
+struct Host {
+  url: String,
+  files: Vec<File>,
+}
+
+#[derive(Deserialize)]
+struct File {
+  name: String,
+}
+
+#[derive(Deserialize)]
+struct User {
+  emote_set: Option<EmoteSet>,
+}
+
+impl ActiveEmote {
+  fn convert(self) -> Option<Emote> {
+    let base = match self.data.host.url.strip_prefix("//") {
+      Some(rest) => format!("https://{rest}"),
+      None => self.data.host.url.clone(),
+    };
+    let webp = self
+      .data
+      .host
+      .files
+      .iter()
+      .filter(|f| f.name.ends_with(".webp"))
+      .map(|f| format!("{base}/{}", f.name))
+      .collect::<Vec<_>>();
+    Some(Emote {
+      url: webp.first()?.clone(),
+      url_hd: webp.last().cloned(),
+      id: self.id,
+      name: self.name,
+      source: Source::SevenTv,
+      animated: self.data.animated,
+      zero_width: self.flags & FLAG_ZERO_WIDTH != 0,
+    })
+  }
+}
+
+impl Provider for SevenTv {
+  fn source(&self) -> Source {
+    Source::SevenTv
+  }
+
+  fn global(&self) -> BoxFuture<'_, Result<Vec<Emote>, Error>> {
+    async move {
+      let url = format!("{}/emote-sets/global", self.base_url);
+      let set = get_json::<EmoteSet>(&self.http, &url).await?;
+      Ok(
+        set
+          .into_iter()
+          .flat_map(|set| set.emotes)
+          .filter_map(ActiveEmote::convert)
+          .collect(),
+      )
+    }
+    .boxed()
+  }
+
+  fn channel<'a>(&'a self, room_id: &'a str) -> BoxFuture<'a, Result<Vec<Emote>, Error>> {
+    async move {
+      let url = format!("{}/users/twitch/{room_id}", self.base_url);
+      let user = get_json::<User>(&self.http, &url).await?;
+      Ok(
+        user
+          .and_then(|user| user.emote_set)
+          .into_iter()
+          .flat_map(|set| set.emotes)
+          .filter_map(ActiveEmote::convert)
+          .collect(),
+      )
+    }
+    .boxed()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::testing::{Response, Server};
+
+  fn emote(id: &str, name: &str, flags: u32, files: &[&str]) -> serde_json::Value {
+    let files = files
+      .iter()
+      .map(|name| json!({ "name": name, "static_name": name, "width": 32, "height": 32 }))
+      .collect::<Vec<_>>();
+    json!({
+      "id": id,
+      "name": name,
+      "flags": flags,
+      "timestamp": 1621994942000u64,
+      "data": {
+        "id": id,
+        "name": name,
+        "animated": name == "RainTime",
+        "owner": null,
+        "host": { "url": format!("//cdn.7tv.app/emote/{id}"), "files": files },
+      },
+    })
+  }
+
+  #[tokio::test]
+  async fn parses_emotes() {
+    let server = Server::start(|request| match request.path.as_str() {
+      "/v3/emote-sets/global" => Response::json(json!({
+        "id": "62cdd34e72a832540de95857",
+        "name": "Global Emotes",
+        "emotes": [
+          emote("60ae958e229664e8667aea38", "peepoSad", 0, &["1x.avif", "1x.webp", "4x.webp"]),
+          emote("6129ca6f4f1e9a2ea6ae5e95", "RainTime", 257, &["1x.webp"]),
+          emote("6042089e77137b000de9e669", "AvifOnly", 0, &["1x.avif"]),
+        ],
+      })),
+      "/v3/users/twitch/22484632" => Response::json(json!({
+        "id": "01F6NPG9D00003SSBJ0X7J0Y7E",
+        "platform": "TWITCH",
+        "username": "forsen",
+        "emote_set": {
+          "id": "61d5b9c5f6d7e2d1b5a7b3c2",
+          "emotes": [emote("60aeab8df6a2c3b332d21139", "forsenPls", 0, &["1x.webp"])],
+        },
+      })),
+      "/v3/users/twitch/2" => Response::json(json!({ "id": "2", "emote_set": null })),
+      _ => Response::new(404, r#"{"status_code":404,"error":"Unknown User"}"#),
+    })
+    .await;
+    let seventv =
+      SevenTv::new(reqwest::Client::new()).with_base_url(format!("{}/v3", server.url()));
+
+    let global = seventv.global().await.unwrap();
+    assert_eq!(
+      global,
+      [
+        Emote {
+          id: "60ae958e229664e8667aea38".into(),
+          name: "peepoSad".into(),
+          source: Source::SevenTv,
+          url: "https://cdn.7tv.app/emote/60ae958e229664e8667aea38/1x.webp".into(),
+          url_hd: Some("https://cdn.7tv.app/emote/60ae958e229664e8667aea38/4x.webp".into()),
+          animated: false,
+          zero_width: false,
+        },
+        Emote {
+          id: "6129ca6f4f1e9a2ea6ae5e95".into(),
+          name: "RainTime".into(),
+          source: Source::SevenTv,
+          url: "https://cdn.7tv.app/emote/6129ca6f4f1e9a2ea6ae5e95/1x.webp".into(),
+          url_hd: Some("https://cdn.7tv.app/emote/6129ca6f4f1e9a2ea6ae5e95/1x.webp".into()),
+          animated: true,
+          zero_width: true,
+        },
+      ]
+    );
+
+    let channel = seventv.channel("22484632").await.unwrap();
+    assert_eq!(channel.len(), 1);
+    assert_eq!(channel[0].name, "forsenPls");
+
+    assert_eq!(seventv.channel("2").await.unwrap(), []);
+    assert_eq!(seventv.channel("1").await.unwrap(), []);
+  }
+}
@@ -0,0 +1,62 @@
+use tokio::sync::mpsc;
+
+use crate::emotes::{Emote, Source};
+use crate::eventsub;
+use crate::helix::chat::BadgeSet;
+use crate::helix::users::User;
+
+/// Everything received from Twitch and third-party services, regardless of which
+/// connection it arrived on.
+#[derive(Debug)]
+pub enum Event {
+  Chat(twitch::Message),
+  EventSub(Box<eventsub::Event>),
+  /// The global emote set of `source` if `room_id` is `None`, otherwise the channel's set.
+  Emotes {
+    room_id: Option<String>,
+    source: Source,
+    emotes: Vec<Emote>,
+  },
+  /// The global badge sets if `room_id` is `None`, otherwise the channel's sets.
+  Badges {
+    room_id: Option<String>,
+    sets: Vec<BadgeSet>,
+  },
+  /// Messages `channel` received before we joined it or reconnected, oldest first.
+  RecentMessages {
+    channel: String,
+    messages: Vec<twitch::Message>,
+  },
+  /// A user loaded through Helix, e.g. for a user card.
+  User(Box<User>),
+  /// Shown inline in `channel`, e.g. the result of a slash command.
+  System {
+    channel: String,
+    text: String,
+  },
+  /// Sending the whisper with the local `id` to `login` failed.
+  WhisperFailed {
+    login: String,
+    id: String,
+    error: String,
+  },
+}
+
+impl From<twitch::Message> for Event {
+  fn from(value: twitch::Message) -> Self {
+    Self::Chat(value)
+  }
+}
+
+impl From<eventsub::Event> for Event {
+  fn from(value: eventsub::Event) -> Self {
+    Self::EventSub(Box::new(value))
+  }
+}
+
+pub type Sender = mpsc::UnboundedSender<Event>;
+pub type Receiver = mpsc::UnboundedReceiver<Event>;
+
+pub fn channel() -> (Sender, Receiver) {
+  mpsc::unbounded_channel()
+}
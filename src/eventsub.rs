@@ -0,0 +1,702 @@
+pub mod event;
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+use crate::event::Sender;
+use crate::helix;
+use crate::util::Timeout;
+
+pub use self::event::{Event, Topic};
+
+pub const URL: &str = "wss://eventsub.wss.twitch.tv/ws";
+
+/// Extra time on top of the session's keepalive timeout before the connection is considered dead.
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(5);
+const MAX_SEEN_MESSAGES: usize = 64;
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub struct Config {
+  pub url: String,
+  /// The id of the logged in user.
+  pub user_id: String,
+}
+
+impl Config {
+  pub fn new(user_id: impl ToString) -> Self {
+    Self {
+      url: URL.into(),
+      user_id: user_id.to_string(),
+    }
+  }
+
+  pub fn with_url(mut self, url: impl ToString) -> Self {
+    self.url = url.to_string();
+    self
+  }
+
+  pub async fn connect(self, helix: helix::Client, timeout: Duration) -> Result<Client, Error> {
+    Client::connect(self, helix, timeout).await
+  }
+}
+
+pub struct Client {
+  ws: WebSocket,
+  session: Session,
+  seen: VecDeque<String>,
+  /// Every subscription, which are created again after a reconnect.
+  subscriptions: Vec<Subscription>,
+  /// Set while the connection is lost, so that a cancelled `next` reconnects when it is called
+  /// again.
+  reconnecting: bool,
+
+  helix: helix::Client,
+  config: Config,
+  timeout: Duration,
+}
+
+impl Client {
+  pub async fn connect(
+    config: Config,
+    helix: helix::Client,
+    timeout: Duration,
+  ) -> Result<Client, Error> {
+    tracing::debug!(url = config.url, "connecting to eventsub");
+    let (ws, session) = open(&config.url).timeout(timeout).await??;
+    Ok(Client {
+      ws,
+      session,
+      seen: VecDeque::with_capacity(MAX_SEEN_MESSAGES),
+      subscriptions: Vec::new(),
+      reconnecting: false,
+      helix,
+      config,
+      timeout,
+    })
+  }
+
+  pub fn session_id(&self) -> &str {
+    &self.session.id
+  }
+
+  pub async fn subscribe(&mut self, topic: Topic, broadcaster_id: &str) -> Result<(), Error> {
+    let id = self.create(topic, broadcaster_id).await?;
+    self.subscriptions.push(Subscription {
+      topic,
+      broadcaster_id: broadcaster_id.into(),
+      id: Some(id),
+    });
+    Ok(())
+  }
+
+  /// Deletes every subscription to `broadcaster_id`'s topics, e.g. after leaving their channel.
+  /// Failures are only logged, the subscriptions end with the session anyway.
+  pub async fn unsubscribe(&mut self, broadcaster_id: &str) {
+    let (removed, kept) = std::mem::take(&mut self.subscriptions)
+      .into_iter()
+      .partition::<Vec<_>, _>(|s| s.broadcaster_id == broadcaster_id);
+    self.subscriptions = kept;
+    for subscription in removed {
+      let Some(id) = subscription.id else {
+        continue;
+      };
+      if let Err(e) = self.helix.delete_eventsub_subscription(&id).await {
+        tracing::warn!(
+          topic = subscription.topic.name(),
+          broadcaster_id,
+          "failed to unsubscribe: {e}"
+        );
+      }
+    }
+  }
+
+  /// Returns the id of the new subscription.
+  async fn create(&self, topic: Topic, broadcaster_id: &str) -> Result<String, Error> {
+    tracing::debug!(
+      topic = topic.name(),
+      broadcaster_id,
+      "creating subscription"
+    );
+    let condition = topic.condition(broadcaster_id, &self.config.user_id);
+    let subscription = self
+      .helix
+      .create_eventsub_subscription(topic.name(), topic.version(), &condition, &self.session.id)
+      .await?;
+    Ok(subscription.id)
+  }
+
+  /// Waits for the next event. Messages which can't be parsed are logged and skipped.
+  ///
+  /// Cancel safe, so it can be used in `tokio::select!`.
+  pub async fn next(&mut self) -> Result<Event, Error> {
+    if self.reconnecting {
+      self.reconnect().await?;
+    }
+    loop {
+      let keepalive = self.session.keepalive + KEEPALIVE_GRACE;
+      let frame = match self.ws.next().timeout(keepalive).await {
+        Ok(Some(Ok(frame))) => frame,
+        // e.g. a connection reset without a close handshake
+        Ok(Some(Err(e))) => {
+          tracing::debug!("eventsub connection failed: {e}");
+          self.reconnect().await?;
+          continue;
+        }
+        Ok(None) => {
+          tracing::debug!("eventsub stream closed");
+          self.reconnect().await?;
+          continue;
+        }
+        Err(_) => {
+          tracing::debug!("no message within keepalive timeout");
+          self.reconnect().await?;
+          continue;
+        }
+      };
+
+      let text = match frame {
+        WsMessage::Text(text) => text,
+        WsMessage::Close(frame) => {
+          tracing::debug!(?frame, "eventsub connection closed by server");
+          self.reconnect().await?;
+          continue;
+        }
+        _ => continue,
+      };
+
+      let message = match serde_json::from_str::<Message>(&text) {
+        Ok(message) => message,
+        Err(e) => {
+          tracing::warn!("skipping invalid eventsub message: {e}");
+          continue;
+        }
+      };
+      if self.is_duplicate(&message.metadata.message_id) {
+        tracing::debug!(
+          id = message.metadata.message_id,
+          "skipping duplicate message"
+        );
+        continue;
+      }
+
+      match message.metadata.message_type.as_str() {
+        "session_keepalive" => {}
+        "session_welcome" => {
+          if let Some(session) = message.payload.session {
+            self.session = session.into();
+          }
+        }
+        "session_reconnect" => {
+          let url = message
+            .payload
+            .session
+            .and_then(|session| session.reconnect_url);
+          let migrated = match url {
+            Some(url) => self.migrate(&url).await,
+            None => Err(Error::UnexpectedMessage(text)),
+          };
+          // start over in a new session if the old one can't be carried over
+          if let Err(e) = migrated {
+            tracing::debug!("failed to migrate eventsub session: {e}");
+            self.reconnect().await?;
+          }
+        }
+        "notification" => {
+          let (Some(subscription), Some(event)) =
+            (message.payload.subscription, message.payload.event)
+          else {
+            tracing::warn!("skipping notification without an event: {text}");
+            continue;
+          };
+          let Some(topic) = Topic::from_name(&subscription.kind) else {
+            tracing::debug!(kind = subscription.kind, "unknown notification type");
+            continue;
+          };
+          match Event::parse(topic, event) {
+            Ok(event) => return Ok(event),
+            Err(e) => tracing::warn!(
+              kind = subscription.kind,
+              "skipping invalid notification: {e}"
+            ),
+          }
+        }
+        "revocation" => {
+          let Some(subscription) = message.payload.subscription else {
+            tracing::warn!("skipping revocation without a subscription: {text}");
+            continue;
+          };
+          if let Some(topic) = Topic::from_name(&subscription.kind) {
+            let user_id = &self.config.user_id;
+            self.subscriptions.retain(|s| {
+              !(s.topic == topic
+                && topic.condition(&s.broadcaster_id, user_id) == subscription.condition)
+            });
+          }
+          return Ok(Event::Revoked {
+            topic: subscription.kind,
+            status: subscription.status,
+          });
+        }
+        other => tracing::debug!(message_type = other, "unknown message type"),
+      }
+    }
+  }
+
+  /// Forwards events into `events` until the receiver is dropped or the connection fails.
+  pub async fn run(mut self, events: Sender) -> Result<(), Error> {
+    loop {
+      let event = self.next().await?;
+      if events.send(event.into()).is_err() {
+        return Ok(());
+      }
+    }
+  }
+
+  /// Moves to a new connection at `url`, as requested by a `session_reconnect` message.
+  ///
+  /// Subscriptions are carried over to the new session by Twitch.
+  async fn migrate(&mut self, url: &str) -> Result<(), Error> {
+    tracing::debug!(url, "migrating eventsub session");
+    let (ws, session) = open(url).timeout(self.timeout).await??;
+    let mut old = std::mem::replace(&mut self.ws, ws);
+    self.session = session;
+    let _ = old.close(None).await;
+    Ok(())
+  }
+
+  /// Opens a fresh session and re-creates all subscriptions, which do not survive a lost connection.
+  ///
+  /// Subscriptions which fail to be created are kept, and tried again on the next reconnect.
+  async fn reconnect(&mut self) -> Result<(), Error> {
+    tracing::debug!("reconnecting to eventsub");
+    self.reconnecting = true;
+
+    let mut tries = 10;
+    let mut delay = Duration::from_secs(1);
+
+    while tries != 0 {
+      tries -= 1;
+
+      match open(&self.config.url).timeout(self.timeout).await {
+        Ok(Ok((ws, session))) => {
+          self.ws = ws;
+          self.session = session;
+          let mut subscriptions = std::mem::take(&mut self.subscriptions);
+          for subscription in &mut subscriptions {
+            let (topic, broadcaster_id) = (subscription.topic, &subscription.broadcaster_id);
+            subscription.id = match self.create(topic, broadcaster_id).await {
+              Ok(id) => Some(id),
+              Err(e) => {
+                tracing::warn!(
+                  topic = topic.name(),
+                  broadcaster_id,
+                  "failed to subscribe again: {e}"
+                );
+                None
+              }
+            };
+          }
+          self.subscriptions = subscriptions;
+          self.reconnecting = false;
+          return Ok(());
+        }
+        Ok(Err(e)) => tracing::debug!("failed to reconnect: {e}"),
+        Err(_) => tracing::debug!("failed to reconnect: timed out"),
+      }
+
+      tokio::time::sleep(delay).await;
+      delay *= 2;
+    }
+
+    Err(Error::Reconnect)
+  }
+
+  fn is_duplicate(&mut self, id: &str) -> bool {
+    if self.seen.iter().any(|seen| seen == id) {
+      return true;
+    }
+    if self.seen.len() == MAX_SEEN_MESSAGES {
+      self.seen.pop_front();
+    }
+    self.seen.push_back(id.into());
+    false
+  }
+}
+
+/// Connects to `url` and waits for the `session_welcome` message.
+async fn open(url: &str) -> Result<(WebSocket, Session), Error> {
+  let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+  while let Some(frame) = ws.next().await {
+    let WsMessage::Text(text) = frame? else {
+      continue;
+    };
+    let message = serde_json::from_str::<Message>(&text)?;
+    return match (
+      message.metadata.message_type.as_str(),
+      message.payload.session,
+    ) {
+      ("session_welcome", Some(session)) => {
+        tracing::debug!(id = session.id, "eventsub session started");
+        Ok((ws, session.into()))
+      }
+      _ => Err(Error::UnexpectedMessage(text)),
+    };
+  }
+  Err(Error::StreamClosed)
+}
+
+struct Subscription {
+  topic: Topic,
+  broadcaster_id: String,
+  /// `None` while it failed to be created in the current session.
+  id: Option<String>,
+}
+
+struct Session {
+  id: String,
+  keepalive: Duration,
+}
+
+impl From<SessionPayload> for Session {
+  fn from(value: SessionPayload) -> Self {
+    Self {
+      id: value.id,
+      keepalive: Duration::from_secs(value.keepalive_timeout_seconds.unwrap_or(10)),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct Message {
+  metadata: Metadata,
+  #[serde(default)]
+  payload: Payload,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+  message_id: String,
+  message_type: String,
+}
+
+#[derive(Default, Deserialize)]
+struct Payload {
+  session: Option<SessionPayload>,
+  subscription: Option<SubscriptionPayload>,
+  event: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct SessionPayload {
+  id: String,
+  keepalive_timeout_seconds: Option<u64>,
+  reconnect_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionPayload {
+  #[serde(rename = "type")]
+  kind: String,
+  status: String,
+  condition: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub enum Error {
+  WebSocket(tungstenite::Error),
+  Json(serde_json::Error),
+  Helix(helix::Error),
+  Timeout(tokio::time::error::Elapsed),
+  UnexpectedMessage(String),
+  StreamClosed,
+  Reconnect,
+}
+
+impl From<tungstenite::Error> for Error {
+  fn from(value: tungstenite::Error) -> Self {
+    Self::WebSocket(value)
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(value: serde_json::Error) -> Self {
+    Self::Json(value)
+  }
+}
+
+impl From<helix::Error> for Error {
+  fn from(value: helix::Error) -> Self {
+    Self::Helix(value)
+  }
+}
+
+impl From<tokio::time::error::Elapsed> for Error {
+  fn from(value: tokio::time::error::Elapsed) -> Self {
+    Self::Timeout(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::WebSocket(e) => write!(f, "eventsub error: {e}"),
+      Error::Json(e) => write!(f, "eventsub error: invalid message, {e}"),
+      Error::Helix(e) => write!(f, "eventsub error: {e}"),
+      Error::Timeout(e) => write!(f, "eventsub error: connection timed out, {e}"),
+      Error::UnexpectedMessage(msg) => write!(f, "eventsub error: unexpected message `{msg}`"),
+      Error::StreamClosed => write!(f, "eventsub error: stream closed"),
+      Error::Reconnect => write!(f, "eventsub error: reconnect attempt failed"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use futures_util::SinkExt;
+  use serde_json::{json, Value};
+  use tokio::net::TcpListener;
+  use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+  use super::*;
+  use crate::testing::{Response, Server};
+  use crate::twitch::ChatConfig;
+
+  type ServerSocket = WebSocketStream<TcpStream>;
+
+  /// Accepts WebSocket connections and hands them to the test, which plays the part of Twitch.
+  async fn fake_eventsub() -> (String, UnboundedReceiver<ServerSocket>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (tx, rx) = unbounded_channel();
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        if let Ok(ws) = tokio_tungstenite::accept_async(stream).await {
+          let _ = tx.send(ws);
+        }
+      }
+    });
+    (url, rx)
+  }
+
+  async fn send(ws: &mut ServerSocket, message: Value) {
+    ws.send(WsMessage::Text(message.to_string())).await.unwrap();
+  }
+
+  fn welcome(session_id: &str) -> Value {
+    json!({
+      "metadata": { "message_id": format!("welcome-{session_id}"), "message_type": "session_welcome" },
+      "payload": { "session": { "id": session_id, "status": "connected", "keepalive_timeout_seconds": 10 } }
+    })
+  }
+
+  fn follow(message_id: &str, user_name: &str) -> Value {
+    json!({
+      "metadata": { "message_id": message_id, "message_type": "notification" },
+      "payload": {
+        "subscription": { "type": "channel.follow", "status": "enabled", "condition": {} },
+        "event": {
+          "user_id": "3",
+          "user_login": user_name.to_lowercase(),
+          "user_name": user_name,
+          "broadcaster_user_id": "2",
+          "broadcaster_user_login": "streamer",
+          "broadcaster_user_name": "Streamer",
+          "followed_at": "2023-06-01T00:00:00Z"
+        }
+      }
+    })
+  }
+
+  /// Creating a `channel.raid` subscription fails in the session `failing`. Subscription ids are
+  /// their type and session.
+  async fn fake_helix(failing: &'static str) -> Server {
+    Server::start(move |request| {
+      if request.method == "DELETE" {
+        return Response::new(204, "");
+      }
+      let body = serde_json::from_str::<Value>(&request.body).unwrap();
+      if body["transport"]["session_id"] == failing && body["type"] == "channel.raid" {
+        return Response::new(500, "");
+      }
+      Response::json(json!({
+        "data": [{
+          "id": format!("{}-{}", body["type"].as_str().unwrap(), body["transport"]["session_id"].as_str().unwrap()),
+          "status": "enabled",
+          "type": body["type"],
+          "version": body["version"],
+          "condition": body["condition"]
+        }]
+      }))
+    })
+    .await
+  }
+
+  fn session_ids(helix: &Server) -> Vec<(String, String)> {
+    helix
+      .requests()
+      .iter()
+      .filter(|request| request.method == "POST")
+      .map(|request| {
+        let body = serde_json::from_str::<Value>(&request.body).unwrap();
+        let kind = body["type"].as_str().unwrap().to_string();
+        let session = body["transport"]["session_id"]
+          .as_str()
+          .unwrap()
+          .to_string();
+        (kind, session)
+      })
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn handles_session_messages() {
+    let (url, mut connections) = fake_eventsub().await;
+    let helix_server = fake_helix("s3").await;
+    let helix = helix::Config::new("id", &ChatConfig::new("nick", "oauth:token"))
+      .with_base_url(helix_server.url())
+      .build();
+
+    let (client, mut ws) = tokio::join!(
+      Config::new("1")
+        .with_url(&url)
+        .connect(helix, Duration::from_secs(5)),
+      async {
+        let mut ws = connections.recv().await.unwrap();
+        send(&mut ws, welcome("s1")).await;
+        ws
+      }
+    );
+    let mut client = client.unwrap();
+    assert_eq!(client.session_id(), "s1");
+
+    client.subscribe(Topic::Follow, "2").await.unwrap();
+    client.subscribe(Topic::Raid, "2").await.unwrap();
+    assert_eq!(
+      session_ids(&helix_server),
+      [
+        ("channel.follow".into(), "s1".into()),
+        ("channel.raid".into(), "s1".into())
+      ]
+    );
+
+    // bad payloads are skipped, and duplicates are only delivered once
+    ws.send(WsMessage::Text("not json".into())).await.unwrap();
+    let mut invalid = follow("bad", "Invalid");
+    invalid["payload"]["event"]["followed_at"] = json!("yesterday");
+    send(&mut ws, invalid).await;
+    let mut unknown = follow("unknown", "Unknown");
+    unknown["payload"]["subscription"]["type"] = json!("channel.unknown");
+    send(&mut ws, unknown).await;
+    send(&mut ws, follow("n1", "First")).await;
+    send(&mut ws, follow("n1", "First")).await;
+    send(&mut ws, follow("n2", "Second")).await;
+    let Event::Follow(first) = client.next().await.unwrap() else {
+      panic!("expected a follow");
+    };
+    assert_eq!(first.user_name, "First");
+    let Event::Follow(second) = client.next().await.unwrap() else {
+      panic!("expected a follow");
+    };
+    assert_eq!(second.user_name, "Second");
+
+    // `session_reconnect` moves to the new URL, and Twitch keeps the subscriptions
+    let reconnect = json!({
+      "metadata": { "message_id": "r1", "message_type": "session_reconnect" },
+      "payload": { "session": { "id": "s1", "status": "reconnecting", "reconnect_url": url } }
+    });
+    let (event, mut ws) = tokio::join!(client.next(), async {
+      send(&mut ws, reconnect).await;
+      let mut new = connections.recv().await.unwrap();
+      send(&mut new, welcome("s2")).await;
+      send(&mut new, follow("n3", "Migrated")).await;
+      new
+    });
+    assert!(matches!(event.unwrap(), Event::Follow(e) if e.user_name == "Migrated"));
+    assert_eq!(client.session_id(), "s2");
+    assert_eq!(helix_server.requests().len(), 2);
+
+    // a lost connection starts a new session, in which the subscriptions are created again
+    let (event, _ws) = tokio::join!(client.next(), async {
+      ws.close(None).await.unwrap();
+      let mut new = connections.recv().await.unwrap();
+      send(&mut new, welcome("s3")).await;
+      send(&mut new, follow("n4", "Reconnected")).await;
+      new
+    });
+    assert!(matches!(event.unwrap(), Event::Follow(e) if e.user_name == "Reconnected"));
+    assert_eq!(client.session_id(), "s3");
+    assert_eq!(
+      session_ids(&helix_server)[2..],
+      [
+        ("channel.follow".into(), "s3".into()),
+        ("channel.raid".into(), "s3".into())
+      ]
+    );
+    // the subscription which failed is kept for the next reconnect
+    assert_eq!(client.subscriptions.len(), 2);
+  }
+
+  #[tokio::test]
+  async fn reconnects_after_reset() {
+    let (url, mut connections) = fake_eventsub().await;
+    let helix_server = fake_helix("none").await;
+    let helix = helix::Config::new("id", &ChatConfig::new("nick", "oauth:token"))
+      .with_base_url(helix_server.url())
+      .build();
+    let (client, ws) = tokio::join!(
+      Config::new("1")
+        .with_url(&url)
+        .connect(helix, Duration::from_secs(5)),
+      async {
+        let mut ws = connections.recv().await.unwrap();
+        send(&mut ws, welcome("s1")).await;
+        ws
+      }
+    );
+    let mut client = client.unwrap();
+    client.subscribe(Topic::Follow, "2").await.unwrap();
+    client.subscribe(Topic::Raid, "2").await.unwrap();
+    client.subscribe(Topic::Follow, "5").await.unwrap();
+
+    // the connection drops without a close handshake
+    let (event, _ws) = tokio::join!(client.next(), async {
+      drop(ws);
+      let new = connections.recv().timeout(Duration::from_secs(5)).await;
+      let mut new = new.expect("no reconnect").unwrap();
+      send(&mut new, welcome("s2")).await;
+      send(&mut new, follow("n1", "Reconnected")).await;
+      new
+    });
+    assert!(matches!(event.unwrap(), Event::Follow(e) if e.user_name == "Reconnected"));
+    assert_eq!(client.session_id(), "s2");
+    assert_eq!(session_ids(&helix_server).len(), 6);
+
+    // only the subscriptions of the new session are deleted
+    client.unsubscribe("2").await;
+    let deleted = helix_server
+      .requests()
+      .into_iter()
+      .filter(|request| request.method == "DELETE")
+      .flat_map(|request| {
+        request
+          .query("id")
+          .into_iter()
+          .map(String::from)
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(deleted, ["channel.follow-s2", "channel.raid-s2"]);
+    assert_eq!(client.subscriptions.len(), 1);
+    assert_eq!(client.subscriptions[0].broadcaster_id, "5");
+  }
+}
@@ -0,0 +1,297 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Topic {
+  Follow,
+  Raid,
+  Redemption,
+  PollBegin,
+  PollProgress,
+  PollEnd,
+  PredictionBegin,
+  PredictionProgress,
+  PredictionLock,
+  PredictionEnd,
+  StreamOnline,
+  StreamOffline,
+}
+
+impl Topic {
+  pub const ALL: &[Topic] = &[
+    Topic::Follow,
+    Topic::Raid,
+    Topic::Redemption,
+    Topic::PollBegin,
+    Topic::PollProgress,
+    Topic::PollEnd,
+    Topic::PredictionBegin,
+    Topic::PredictionProgress,
+    Topic::PredictionLock,
+    Topic::PredictionEnd,
+    Topic::StreamOnline,
+    Topic::StreamOffline,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Topic::Follow => "channel.follow",
+      Topic::Raid => "channel.raid",
+      Topic::Redemption => "channel.channel_points_custom_reward_redemption.add",
+      Topic::PollBegin => "channel.poll.begin",
+      Topic::PollProgress => "channel.poll.progress",
+      Topic::PollEnd => "channel.poll.end",
+      Topic::PredictionBegin => "channel.prediction.begin",
+      Topic::PredictionProgress => "channel.prediction.progress",
+      Topic::PredictionLock => "channel.prediction.lock",
+      Topic::PredictionEnd => "channel.prediction.end",
+      Topic::StreamOnline => "stream.online",
+      Topic::StreamOffline => "stream.offline",
+    }
+  }
+
+  pub fn version(&self) -> &'static str {
+    match self {
+      Topic::Follow => "2",
+      _ => "1",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    Self::ALL.iter().copied().find(|topic| topic.name() == name)
+  }
+
+  /// `user_id` is the logged in user, which must be a moderator for `channel.follow`.
+  pub fn condition(&self, broadcaster_id: &str, user_id: &str) -> serde_json::Value {
+    match self {
+      Topic::Follow => json!({
+        "broadcaster_user_id": broadcaster_id,
+        "moderator_user_id": user_id,
+      }),
+      Topic::Raid => json!({ "to_broadcaster_user_id": broadcaster_id }),
+      _ => json!({ "broadcaster_user_id": broadcaster_id }),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub enum Event {
+  Follow(Follow),
+  Raid(Raid),
+  Redemption(Redemption),
+  PollBegin(Poll),
+  PollProgress(Poll),
+  PollEnd(Poll),
+  PredictionBegin(Prediction),
+  PredictionProgress(Prediction),
+  PredictionLock(Prediction),
+  PredictionEnd(Prediction),
+  StreamOnline(StreamOnline),
+  StreamOffline(StreamOffline),
+  /// The subscription was revoked by Twitch, e.g. because the user's token expired.
+  Revoked {
+    topic: String,
+    status: String,
+  },
+}
+
+impl Event {
+  pub(super) fn parse(topic: Topic, event: serde_json::Value) -> serde_json::Result<Self> {
+    use serde_json::from_value;
+
+    Ok(match topic {
+      Topic::Follow => Event::Follow(from_value(event)?),
+      Topic::Raid => Event::Raid(from_value(event)?),
+      Topic::Redemption => Event::Redemption(from_value(event)?),
+      Topic::PollBegin => Event::PollBegin(from_value(event)?),
+      Topic::PollProgress => Event::PollProgress(from_value(event)?),
+      Topic::PollEnd => Event::PollEnd(from_value(event)?),
+      Topic::PredictionBegin => Event::PredictionBegin(from_value(event)?),
+      Topic::PredictionProgress => Event::PredictionProgress(from_value(event)?),
+      Topic::PredictionLock => Event::PredictionLock(from_value(event)?),
+      Topic::PredictionEnd => Event::PredictionEnd(from_value(event)?),
+      Topic::StreamOnline => Event::StreamOnline(from_value(event)?),
+      Topic::StreamOffline => Event::StreamOffline(from_value(event)?),
+    })
+  }
+
+  /// A line to show in the broadcaster's chat, or `None` for updates which would only be noise.
+  pub fn text(&self) -> Option<String> {
+    Some(match self {
+      Event::Follow(e) => format!("{} followed", e.user_name),
+      Event::Raid(e) => format!(
+        "{} is raiding with {} viewers",
+        e.from_broadcaster_user_name, e.viewers
+      ),
+      Event::Redemption(e) => match e.user_input.is_empty() {
+        true => format!("{} redeemed {}", e.user_name, e.reward.title),
+        false => format!(
+          "{} redeemed {}: {}",
+          e.user_name, e.reward.title, e.user_input
+        ),
+      },
+      Event::PollBegin(e) => format!("Poll started: {}", e.title),
+      Event::PollEnd(e) => {
+        let votes = e
+          .choices
+          .iter()
+          .map(|choice| format!("{} ({})", choice.title, choice.votes))
+          .collect::<Vec<_>>();
+        format!("Poll ended: {}, {}", e.title, votes.join(", "))
+      }
+      Event::PredictionBegin(e) => format!("Prediction started: {}", e.title),
+      Event::PredictionLock(e) => format!("Prediction locked: {}", e.title),
+      Event::PredictionEnd(e) => {
+        let winner = e
+          .winning_outcome_id
+          .as_ref()
+          .and_then(|id| e.outcomes.iter().find(|outcome| outcome.id == *id));
+        match winner {
+          Some(outcome) => format!("Prediction ended: {}, {} won", e.title, outcome.title),
+          None => format!("Prediction ended: {}", e.title),
+        }
+      }
+      Event::StreamOnline(e) => format!("{} is live", e.broadcaster_user_name),
+      Event::StreamOffline(e) => format!("{} went offline", e.broadcaster_user_name),
+      Event::PollProgress(_) | Event::PredictionProgress(_) | Event::Revoked { .. } => return None,
+    })
+  }
+
+  pub fn broadcaster_id(&self) -> Option<&str> {
+    match self {
+      Event::Follow(e) => Some(&e.broadcaster_user_id),
+      Event::Raid(e) => Some(&e.to_broadcaster_user_id),
+      Event::Redemption(e) => Some(&e.broadcaster_user_id),
+      Event::PollBegin(e) | Event::PollProgress(e) | Event::PollEnd(e) => {
+        Some(&e.broadcaster_user_id)
+      }
+      Event::PredictionBegin(e)
+      | Event::PredictionProgress(e)
+      | Event::PredictionLock(e)
+      | Event::PredictionEnd(e) => Some(&e.broadcaster_user_id),
+      Event::StreamOnline(e) => Some(&e.broadcaster_user_id),
+      Event::StreamOffline(e) => Some(&e.broadcaster_user_id),
+      Event::Revoked { .. } => None,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Follow {
+  pub user_id: String,
+  pub user_login: String,
+  pub user_name: String,
+  pub broadcaster_user_id: String,
+  pub broadcaster_user_login: String,
+  pub broadcaster_user_name: String,
+  pub followed_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Raid {
+  pub from_broadcaster_user_id: String,
+  pub from_broadcaster_user_login: String,
+  pub from_broadcaster_user_name: String,
+  pub to_broadcaster_user_id: String,
+  pub to_broadcaster_user_login: String,
+  pub to_broadcaster_user_name: String,
+  pub viewers: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Redemption {
+  pub id: String,
+  pub broadcaster_user_id: String,
+  pub broadcaster_user_login: String,
+  pub broadcaster_user_name: String,
+  pub user_id: String,
+  pub user_login: String,
+  pub user_name: String,
+  #[serde(default)]
+  pub user_input: String,
+  pub status: String,
+  pub reward: Reward,
+  pub redeemed_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Reward {
+  pub id: String,
+  pub title: String,
+  pub cost: u64,
+  #[serde(default)]
+  pub prompt: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Poll {
+  pub id: String,
+  pub broadcaster_user_id: String,
+  pub broadcaster_user_login: String,
+  pub broadcaster_user_name: String,
+  pub title: String,
+  pub choices: Vec<PollChoice>,
+  /// Only present in `channel.poll.end`.
+  pub status: Option<String>,
+  pub started_at: DateTime<Utc>,
+  pub ends_at: Option<DateTime<Utc>>,
+  pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PollChoice {
+  pub id: String,
+  pub title: String,
+  #[serde(default)]
+  pub votes: u64,
+  #[serde(default)]
+  pub channel_points_votes: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Prediction {
+  pub id: String,
+  pub broadcaster_user_id: String,
+  pub broadcaster_user_login: String,
+  pub broadcaster_user_name: String,
+  pub title: String,
+  pub outcomes: Vec<PredictionOutcome>,
+  /// Only present in `channel.prediction.end`.
+  pub winning_outcome_id: Option<String>,
+  /// Only present in `channel.prediction.end`.
+  pub status: Option<String>,
+  pub started_at: DateTime<Utc>,
+  pub locks_at: Option<DateTime<Utc>>,
+  pub locked_at: Option<DateTime<Utc>>,
+  pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PredictionOutcome {
+  pub id: String,
+  pub title: String,
+  pub color: String,
+  #[serde(default)]
+  pub users: u64,
+  #[serde(default)]
+  pub channel_points: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StreamOnline {
+  pub id: String,
+  pub broadcaster_user_id: String,
+  pub broadcaster_user_login: String,
+  pub broadcaster_user_name: String,
+  #[serde(rename = "type")]
+  pub kind: String,
+  pub started_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct StreamOffline {
+  pub broadcaster_user_id: String,
+  pub broadcaster_user_login: String,
+  pub broadcaster_user_name: String,
+}
@@ -0,0 +1,466 @@
+pub mod channels;
+pub mod chat;
+pub mod eventsub;
+pub mod moderation;
+pub mod raids;
+pub mod users;
+pub mod whispers;
+
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::twitch::ChatConfig;
+
+pub const BASE_URL: &str = "https://api.twitch.tv/helix";
+
+const MAX_RETRIES: usize = 3;
+
+pub struct Config {
+  pub base_url: String,
+  pub client_id: String,
+  pub token: Option<String>,
+}
+
+impl Config {
+  pub fn new(client_id: impl ToString, chat: &ChatConfig) -> Self {
+    Self {
+      base_url: BASE_URL.into(),
+      client_id: client_id.to_string(),
+      token: chat.token().map(String::from),
+    }
+  }
+
+  pub fn with_base_url(mut self, base_url: impl ToString) -> Self {
+    self.base_url = base_url.to_string();
+    self
+  }
+
+  pub fn build(self) -> Client {
+    Client::new(self)
+  }
+}
+
+#[derive(Clone)]
+pub struct Client {
+  inner: Arc<Inner>,
+}
+
+struct Inner {
+  http: reqwest::Client,
+  base_url: String,
+  client_id: String,
+  token: RwLock<Option<String>>,
+  rate_limit: Mutex<RateLimit>,
+}
+
+impl Client {
+  pub fn new(config: Config) -> Self {
+    Self {
+      inner: Arc::new(Inner {
+        http: reqwest::Client::new(),
+        base_url: config.base_url.trim_end_matches('/').into(),
+        client_id: config.client_id,
+        token: RwLock::new(config.token),
+        rate_limit: Mutex::new(RateLimit::default()),
+      }),
+    }
+  }
+
+  pub fn set_token(&self, token: Option<String>) {
+    *self.inner.token.write().unwrap() = token;
+  }
+
+  pub fn rate_limit(&self) -> RateLimit {
+    *self.inner.rate_limit.lock().unwrap()
+  }
+
+  pub(crate) async fn get<T: DeserializeOwned>(
+    &self,
+    path: &str,
+    query: &[(&str, String)],
+  ) -> Result<Page<T>, Error> {
+    let response = self.send(Method::GET, path, query, None::<&()>).await?;
+    Ok(response.json().await?)
+  }
+
+  /// Follows the `pagination.cursor` of each response until all pages were read.
+  pub(crate) async fn get_all<T: DeserializeOwned>(
+    &self,
+    path: &str,
+    query: &[(&str, String)],
+  ) -> Result<Vec<T>, Error> {
+    let mut query = query.to_vec();
+    let mut data = Vec::new();
+    loop {
+      let page = self.get::<T>(path, &query).await?;
+      data.extend(page.data);
+      match page.pagination.and_then(|p| p.cursor) {
+        Some(cursor) if !cursor.is_empty() => {
+          query.retain(|(k, _)| *k != "after");
+          query.push(("after", cursor));
+        }
+        _ => break,
+      }
+    }
+    Ok(data)
+  }
+
+  pub(crate) async fn post<B: Serialize, T: DeserializeOwned>(
+    &self,
+    path: &str,
+    query: &[(&str, String)],
+    body: &B,
+  ) -> Result<Page<T>, Error> {
+    let response = self.send(Method::POST, path, query, Some(body)).await?;
+    Ok(response.json().await?)
+  }
+
+  pub(crate) async fn patch<B: Serialize, T: DeserializeOwned>(
+    &self,
+    path: &str,
+    query: &[(&str, String)],
+    body: &B,
+  ) -> Result<Page<T>, Error> {
+    let response = self.send(Method::PATCH, path, query, Some(body)).await?;
+    Ok(response.json().await?)
+  }
+
+  /// For endpoints which respond with `204 No Content`.
+  pub(crate) async fn execute<B: Serialize>(
+    &self,
+    method: Method,
+    path: &str,
+    query: &[(&str, String)],
+    body: Option<&B>,
+  ) -> Result<(), Error> {
+    self.send(method, path, query, body).await?;
+    Ok(())
+  }
+
+  async fn send<B: Serialize>(
+    &self,
+    method: Method,
+    path: &str,
+    query: &[(&str, String)],
+    body: Option<&B>,
+  ) -> Result<reqwest::Response, Error> {
+    let url = format!("{}/{}", self.inner.base_url, path.trim_start_matches('/'));
+
+    for _ in 0..MAX_RETRIES {
+      let wait = self.inner.rate_limit.lock().unwrap().wait_time();
+      if let Some(wait) = wait {
+        tracing::debug!(?wait, "helix rate limit exhausted, waiting for reset");
+        tokio::time::sleep(wait).await;
+      }
+
+      let token = self.inner.token.read().unwrap().clone();
+      let Some(token) = token else {
+        return Err(Error::MissingToken);
+      };
+
+      tracing::debug!(%method, %url, "helix request");
+      let mut request = self
+        .inner
+        .http
+        .request(method.clone(), &url)
+        .header("Client-Id", &self.inner.client_id)
+        .bearer_auth(token)
+        .query(query);
+      if let Some(body) = body {
+        request = request.json(body);
+      }
+      let response = request.send().await?;
+
+      self
+        .inner
+        .rate_limit
+        .lock()
+        .unwrap()
+        .update(response.headers());
+
+      match response.status() {
+        StatusCode::TOO_MANY_REQUESTS => {
+          tracing::debug!("helix request was rate limited");
+          continue;
+        }
+        status if status.is_success() => return Ok(response),
+        status => {
+          let message = match response.json::<ErrorBody>().await {
+            Ok(body) => body.message,
+            Err(_) => String::new(),
+          };
+          return Err(Error::Status(status, message));
+        }
+      }
+    }
+
+    Err(Error::RateLimited)
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Page<T> {
+  pub data: Vec<T>,
+  #[serde(default)]
+  pub pagination: Option<Pagination>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Pagination {
+  pub cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+  #[serde(default)]
+  message: String,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimit {
+  pub limit: Option<u32>,
+  pub remaining: Option<u32>,
+  /// Unix timestamp (in seconds) at which the bucket is refilled.
+  pub reset: Option<u64>,
+}
+
+impl RateLimit {
+  fn update(&mut self, headers: &HeaderMap) {
+    fn header<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+      headers.get(name)?.to_str().ok()?.parse().ok()
+    }
+
+    if let Some(limit) = header(headers, "ratelimit-limit") {
+      self.limit = Some(limit);
+    }
+    if let Some(remaining) = header(headers, "ratelimit-remaining") {
+      self.remaining = Some(remaining);
+    }
+    if let Some(reset) = header(headers, "ratelimit-reset") {
+      self.reset = Some(reset);
+    }
+  }
+
+  fn wait_time(&self) -> Option<Duration> {
+    if self.remaining != Some(0) {
+      return None;
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let reset = self.reset?;
+    (reset > now).then(|| Duration::from_secs(reset - now))
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Http(reqwest::Error),
+  Status(StatusCode, String),
+  MissingToken,
+  RateLimited,
+  NotFound,
+}
+
+impl From<reqwest::Error> for Error {
+  fn from(value: reqwest::Error) -> Self {
+    Self::Http(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Http(e) => write!(f, "helix request failed: {e}"),
+      Error::Status(status, message) if message.is_empty() => {
+        write!(f, "helix request failed: {status}")
+      }
+      Error::Status(status, message) => write!(f, "helix request failed: {status}, {message}"),
+      Error::MissingToken => write!(f, "helix request failed: not logged in"),
+      Error::RateLimited => write!(f, "helix request failed: rate limited"),
+      Error::NotFound => write!(f, "helix request failed: no data in response"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::testing::{Response, Server};
+
+  fn client(server: &Server) -> Client {
+    Config::new("client-id", &ChatConfig::new("nick", "oauth:token"))
+      .with_base_url(format!("{}/helix/", server.url()))
+      .build()
+  }
+
+  #[tokio::test]
+  async fn sends_credentials_and_query() {
+    let server = Server::start(|_| {
+      Response::json(json!({
+        "data": [{
+          "id": "141981764",
+          "login": "twitchdev",
+          "display_name": "TwitchDev",
+          "type": "",
+          "broadcaster_type": "partner",
+          "description": "",
+          "profile_image_url": "",
+          "offline_image_url": "",
+          "created_at": "2016-12-14T20:32:28Z"
+        }]
+      }))
+    })
+    .await;
+
+    let user = client(&server)
+      .get_user_by_login("twitchdev")
+      .await
+      .unwrap();
+    assert_eq!(user.id, "141981764");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "GET");
+    assert_eq!(requests[0].path, "/helix/users");
+    assert_eq!(requests[0].query("login"), ["twitchdev"]);
+    assert_eq!(requests[0].header("client-id"), Some("client-id"));
+    assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+  }
+
+  #[tokio::test]
+  async fn follows_pagination() {
+    let server = Server::start(|request| {
+      let (data, cursor) = match request.query("after").as_slice() {
+        [] => (json!([{ "cursor": 1 }]), json!({ "cursor": "a" })),
+        ["a"] => (json!([{ "cursor": 2 }]), json!({ "cursor": "b" })),
+        _ => (json!([{ "cursor": 3 }]), json!({})),
+      };
+      Response::json(json!({ "data": data, "pagination": cursor }))
+    })
+    .await;
+
+    let data = client(&server)
+      .get_all::<serde_json::Value>("things", &[("first", "1".into())])
+      .await
+      .unwrap();
+    assert_eq!(
+      data,
+      [
+        json!({"cursor": 1}),
+        json!({"cursor": 2}),
+        json!({"cursor": 3})
+      ]
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|r| r.query("first") == ["1"]));
+    assert_eq!(requests[2].query("after"), ["b"]);
+  }
+
+  #[tokio::test]
+  async fn sends_json_body() {
+    let server = Server::start(|_| {
+      Response::json(json!({
+        "data": [{
+          "broadcaster_id": "1",
+          "moderator_id": "2",
+          "user_id": "3",
+          "created_at": "2023-06-01T00:00:00Z",
+          "end_time": "2023-06-01T00:01:00Z"
+        }]
+      }))
+    })
+    .await;
+
+    let ban = client(&server)
+      .ban_user("1", "2", "3", Some(60), "")
+      .await
+      .unwrap();
+    assert!(ban.end_time.is_some());
+
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/helix/moderation/bans");
+    assert_eq!(request.query("broadcaster_id"), ["1"]);
+    assert_eq!(request.query("moderator_id"), ["2"]);
+    let body = serde_json::from_str::<serde_json::Value>(&request.body).unwrap();
+    assert_eq!(body, json!({ "data": { "user_id": "3", "duration": 60 } }));
+  }
+
+  #[tokio::test]
+  async fn reports_error_message() {
+    let server = Server::start(|_| {
+      Response::new(
+        400,
+        r#"{"error":"Bad Request","status":400,"message":"Missing scope"}"#,
+      )
+    })
+    .await;
+
+    let error = client(&server).get_current_user().await.unwrap_err();
+    assert!(matches!(&error, Error::Status(StatusCode::BAD_REQUEST, m) if m == "Missing scope"));
+    assert_eq!(
+      error.to_string(),
+      "helix request failed: 400 Bad Request, Missing scope"
+    );
+  }
+
+  #[tokio::test]
+  async fn retries_when_rate_limited() {
+    let server = Server::start(|_| {
+      Response::new(429, "")
+        .with_header("ratelimit-limit", 800)
+        .with_header("ratelimit-remaining", 5)
+    })
+    .await;
+
+    let client = client(&server);
+    let error = client.get_current_user().await.unwrap_err();
+    assert!(matches!(error, Error::RateLimited));
+    assert_eq!(server.requests().len(), MAX_RETRIES);
+    assert_eq!(client.rate_limit().limit, Some(800));
+    assert_eq!(client.rate_limit().remaining, Some(5));
+  }
+
+  #[tokio::test]
+  async fn requires_token() {
+    let server = Server::start(|_| Response::json(json!({ "data": [] }))).await;
+    let client = client(&server);
+    client.set_token(None);
+
+    assert!(matches!(
+      client.get_current_user().await,
+      Err(Error::MissingToken)
+    ));
+    assert!(server.requests().is_empty());
+  }
+
+  #[test]
+  fn waits_for_reset_when_exhausted() {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    let mut rate_limit = RateLimit {
+      limit: Some(800),
+      remaining: Some(0),
+      reset: Some(now + 10),
+    };
+    assert!(rate_limit
+      .wait_time()
+      .is_some_and(|wait| wait.as_secs() <= 10));
+
+    rate_limit.remaining = Some(1);
+    assert_eq!(rate_limit.wait_time(), None);
+  }
+}
@@ -0,0 +1,52 @@
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::{Client, Error};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChannelInformation {
+  pub broadcaster_id: String,
+  pub broadcaster_login: String,
+  pub broadcaster_name: String,
+  pub broadcaster_language: String,
+  pub game_id: String,
+  pub game_name: String,
+  pub title: String,
+  #[serde(default)]
+  pub delay: u32,
+  #[serde(default)]
+  pub tags: Vec<String>,
+}
+
+impl Client {
+  pub async fn get_channel_information(
+    &self,
+    broadcaster_ids: &[&str],
+  ) -> Result<Vec<ChannelInformation>, Error> {
+    let query = broadcaster_ids
+      .iter()
+      .map(|id| ("broadcaster_id", id.to_string()))
+      .collect::<Vec<_>>();
+    Ok(self.get("channels", &query).await?.data)
+  }
+
+  pub async fn add_channel_vip(&self, broadcaster_id: &str, user_id: &str) -> Result<(), Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("user_id", user_id.to_string()),
+    ];
+    self
+      .execute(Method::POST, "channels/vips", &query, None::<&()>)
+      .await
+  }
+
+  pub async fn remove_channel_vip(&self, broadcaster_id: &str, user_id: &str) -> Result<(), Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("user_id", user_id.to_string()),
+    ];
+    self
+      .execute(Method::DELETE, "channels/vips", &query, None::<&()>)
+      .await
+  }
+}
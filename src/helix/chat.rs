@@ -0,0 +1,223 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::{Client, Error};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BadgeSet {
+  pub set_id: String,
+  pub versions: Vec<BadgeVersion>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BadgeVersion {
+  pub id: String,
+  pub image_url_1x: String,
+  pub image_url_2x: String,
+  pub image_url_4x: String,
+  #[serde(default)]
+  pub title: String,
+  #[serde(default)]
+  pub description: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Emote {
+  pub id: String,
+  pub name: String,
+  #[serde(default)]
+  pub emote_type: String,
+  #[serde(default)]
+  pub emote_set_id: String,
+  #[serde(default)]
+  pub format: Vec<String>,
+  #[serde(default)]
+  pub scale: Vec<String>,
+  #[serde(default)]
+  pub theme_mode: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct EmoteResponse {
+  data: Vec<Emote>,
+  template: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct EmoteSet {
+  pub emotes: Vec<Emote>,
+  /// URL template with `{{id}}`, `{{format}}`, `{{theme_mode}}` and `{{scale}}` placeholders.
+  pub template: String,
+}
+
+impl EmoteSet {
+  pub fn url(&self, emote: &Emote, scale: &str) -> String {
+    let format = if emote.format.iter().any(|f| f == "animated") {
+      "animated"
+    } else {
+      "static"
+    };
+    self
+      .template
+      .replace("{{id}}", &emote.id)
+      .replace("{{format}}", format)
+      .replace("{{theme_mode}}", "dark")
+      .replace("{{scale}}", scale)
+  }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChatSettings {
+  #[serde(skip_serializing)]
+  pub broadcaster_id: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub emote_mode: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub follower_mode: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub follower_mode_duration: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub slow_mode: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub slow_mode_wait_time: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub subscriber_mode: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub unique_chat_mode: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub non_moderator_chat_delay: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub non_moderator_chat_delay_duration: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct Announcement<'a> {
+  message: &'a str,
+  color: &'a str,
+}
+
+impl Client {
+  pub async fn get_global_chat_badges(&self) -> Result<Vec<BadgeSet>, Error> {
+    Ok(self.get("chat/badges/global", &[]).await?.data)
+  }
+
+  pub async fn get_channel_chat_badges(
+    &self,
+    broadcaster_id: &str,
+  ) -> Result<Vec<BadgeSet>, Error> {
+    let query = [("broadcaster_id", broadcaster_id.to_string())];
+    Ok(self.get("chat/badges", &query).await?.data)
+  }
+
+  pub async fn get_global_emotes(&self) -> Result<EmoteSet, Error> {
+    self.get_emotes("chat/emotes/global", &[]).await
+  }
+
+  pub async fn get_channel_emotes(&self, broadcaster_id: &str) -> Result<EmoteSet, Error> {
+    let query = [("broadcaster_id", broadcaster_id.to_string())];
+    self.get_emotes("chat/emotes", &query).await
+  }
+
+  /// Emotes in the sets with `ids`, e.g. from the `emote-sets` tag of `GLOBALUSERSTATE`.
+  pub async fn get_emote_sets(&self, ids: &[String]) -> Result<EmoteSet, Error> {
+    let mut set = EmoteSet {
+      emotes: Vec::new(),
+      template: String::new(),
+    };
+    // at most 25 sets per request
+    for chunk in ids.chunks(25) {
+      let query = chunk
+        .iter()
+        .map(|id| ("emote_set_id", id.clone()))
+        .collect::<Vec<_>>();
+      let response = self.get_emotes("chat/emotes/set", &query).await?;
+      set.emotes.extend(response.emotes);
+      set.template = response.template;
+    }
+    Ok(set)
+  }
+
+  async fn get_emotes(&self, path: &str, query: &[(&str, String)]) -> Result<EmoteSet, Error> {
+    let response = self
+      .send(Method::GET, path, query, None::<&()>)
+      .await?
+      .json::<EmoteResponse>()
+      .await?;
+    Ok(EmoteSet {
+      emotes: response.data,
+      template: response.template,
+    })
+  }
+
+  /// `moderator_id` is required to see the `non_moderator_chat_delay` fields.
+  pub async fn get_chat_settings(
+    &self,
+    broadcaster_id: &str,
+    moderator_id: Option<&str>,
+  ) -> Result<ChatSettings, Error> {
+    let mut query = vec![("broadcaster_id", broadcaster_id.to_string())];
+    if let Some(moderator_id) = moderator_id {
+      query.push(("moderator_id", moderator_id.to_string()));
+    }
+    self
+      .get("chat/settings", &query)
+      .await?
+      .data
+      .into_iter()
+      .next()
+      .ok_or(Error::NotFound)
+  }
+
+  /// Only the fields which are `Some` are changed.
+  pub async fn update_chat_settings(
+    &self,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    settings: &ChatSettings,
+  ) -> Result<ChatSettings, Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("moderator_id", moderator_id.to_string()),
+    ];
+    self
+      .patch("chat/settings", &query, settings)
+      .await?
+      .data
+      .into_iter()
+      .next()
+      .ok_or(Error::NotFound)
+  }
+
+  /// `color` is one of `blue`, `green`, `orange`, `purple` or `primary`.
+  pub async fn send_chat_announcement(
+    &self,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    message: &str,
+    color: &str,
+  ) -> Result<(), Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("moderator_id", moderator_id.to_string()),
+    ];
+    self
+      .execute(
+        Method::POST,
+        "chat/announcements",
+        &query,
+        Some(&Announcement { message, color }),
+      )
+      .await
+  }
+
+  /// `color` is a named color like `blue_violet`, or `#RRGGBB` for Turbo and Prime users.
+  pub async fn update_user_chat_color(&self, user_id: &str, color: &str) -> Result<(), Error> {
+    let query = [
+      ("user_id", user_id.to_string()),
+      ("color", color.to_string()),
+    ];
+    self
+      .execute(Method::PUT, "chat/color", &query, None::<&()>)
+      .await
+  }
+}
@@ -0,0 +1,69 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::{Client, Error};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Subscription {
+  pub id: String,
+  pub status: String,
+  #[serde(rename = "type")]
+  pub kind: String,
+  pub version: String,
+  pub condition: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct CreateSubscription<'a> {
+  #[serde(rename = "type")]
+  kind: &'a str,
+  version: &'a str,
+  condition: &'a serde_json::Value,
+  transport: Transport<'a>,
+}
+
+#[derive(Serialize)]
+struct Transport<'a> {
+  method: &'static str,
+  session_id: &'a str,
+}
+
+impl Client {
+  /// Subscribes the EventSub WebSocket session `session_id` to `kind`.
+  pub async fn create_eventsub_subscription(
+    &self,
+    kind: &str,
+    version: &str,
+    condition: &serde_json::Value,
+    session_id: &str,
+  ) -> Result<Subscription, Error> {
+    let body = CreateSubscription {
+      kind,
+      version,
+      condition,
+      transport: Transport {
+        method: "websocket",
+        session_id,
+      },
+    };
+    self
+      .post("eventsub/subscriptions", &[], &body)
+      .await?
+      .data
+      .into_iter()
+      .next()
+      .ok_or(Error::NotFound)
+  }
+
+  pub async fn delete_eventsub_subscription(&self, id: &str) -> Result<(), Error> {
+    let query = [("id", id.to_string())];
+    self
+      .execute(
+        Method::DELETE,
+        "eventsub/subscriptions",
+        &query,
+        None::<&()>,
+      )
+      .await
+  }
+}
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::{Client, Error};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Ban {
+  pub broadcaster_id: String,
+  pub moderator_id: String,
+  pub user_id: String,
+  pub created_at: DateTime<Utc>,
+  pub end_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BannedUser {
+  pub user_id: String,
+  pub user_login: String,
+  pub user_name: String,
+  pub expires_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>,
+  #[serde(default)]
+  pub reason: String,
+  pub moderator_id: String,
+  pub moderator_login: String,
+  pub moderator_name: String,
+}
+
+#[derive(Serialize)]
+struct BanRequest<'a> {
+  data: BanRequestData<'a>,
+}
+
+#[derive(Serialize)]
+struct BanRequestData<'a> {
+  user_id: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  duration: Option<u32>,
+  #[serde(skip_serializing_if = "str::is_empty")]
+  reason: &'a str,
+}
+
+impl Client {
+  /// Bans `user_id`, or times them out if `duration` (in seconds) is set.
+  pub async fn ban_user(
+    &self,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    user_id: &str,
+    duration: Option<u32>,
+    reason: &str,
+  ) -> Result<Ban, Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("moderator_id", moderator_id.to_string()),
+    ];
+    let body = BanRequest {
+      data: BanRequestData {
+        user_id,
+        duration,
+        reason,
+      },
+    };
+    self
+      .post("moderation/bans", &query, &body)
+      .await?
+      .data
+      .into_iter()
+      .next()
+      .ok_or(Error::NotFound)
+  }
+
+  pub async fn unban_user(
+    &self,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    user_id: &str,
+  ) -> Result<(), Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("moderator_id", moderator_id.to_string()),
+      ("user_id", user_id.to_string()),
+    ];
+    self
+      .execute(Method::DELETE, "moderation/bans", &query, None::<&()>)
+      .await
+  }
+
+  pub async fn get_banned_users(&self, broadcaster_id: &str) -> Result<Vec<BannedUser>, Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("first", "100".to_string()),
+    ];
+    self.get_all("moderation/banned", &query).await
+  }
+
+  /// Deletes a single message, or every message in the chat if `message_id` is `None`.
+  pub async fn delete_chat_messages(
+    &self,
+    broadcaster_id: &str,
+    moderator_id: &str,
+    message_id: Option<&str>,
+  ) -> Result<(), Error> {
+    let mut query = vec![
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("moderator_id", moderator_id.to_string()),
+    ];
+    if let Some(message_id) = message_id {
+      query.push(("message_id", message_id.to_string()));
+    }
+    self
+      .execute(Method::DELETE, "moderation/chat", &query, None::<&()>)
+      .await
+  }
+
+  pub async fn add_channel_moderator(
+    &self,
+    broadcaster_id: &str,
+    user_id: &str,
+  ) -> Result<(), Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("user_id", user_id.to_string()),
+    ];
+    self
+      .execute(Method::POST, "moderation/moderators", &query, None::<&()>)
+      .await
+  }
+
+  pub async fn remove_channel_moderator(
+    &self,
+    broadcaster_id: &str,
+    user_id: &str,
+  ) -> Result<(), Error> {
+    let query = [
+      ("broadcaster_id", broadcaster_id.to_string()),
+      ("user_id", user_id.to_string()),
+    ];
+    self
+      .execute(Method::DELETE, "moderation/moderators", &query, None::<&()>)
+      .await
+  }
+}
@@ -0,0 +1,26 @@
+use reqwest::Method;
+
+use super::{Client, Error};
+
+impl Client {
+  pub async fn start_raid(
+    &self,
+    from_broadcaster_id: &str,
+    to_broadcaster_id: &str,
+  ) -> Result<(), Error> {
+    let query = [
+      ("from_broadcaster_id", from_broadcaster_id.to_string()),
+      ("to_broadcaster_id", to_broadcaster_id.to_string()),
+    ];
+    self
+      .execute(Method::POST, "raids", &query, None::<&()>)
+      .await
+  }
+
+  pub async fn cancel_raid(&self, broadcaster_id: &str) -> Result<(), Error> {
+    let query = [("broadcaster_id", broadcaster_id.to_string())];
+    self
+      .execute(Method::DELETE, "raids", &query, None::<&()>)
+      .await
+  }
+}
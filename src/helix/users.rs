@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::{Client, Error};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct User {
+  pub id: String,
+  pub login: String,
+  pub display_name: String,
+  #[serde(rename = "type")]
+  pub kind: String,
+  pub broadcaster_type: String,
+  pub description: String,
+  pub profile_image_url: String,
+  pub offline_image_url: String,
+  pub created_at: DateTime<Utc>,
+}
+
+impl Client {
+  /// Fetches up to 100 users by id and/or login.
+  ///
+  /// With no ids or logins, returns the user who owns the token.
+  pub async fn get_users(&self, ids: &[&str], logins: &[&str]) -> Result<Vec<User>, Error> {
+    let query = ids
+      .iter()
+      .map(|id| ("id", id.to_string()))
+      .chain(logins.iter().map(|login| ("login", login.to_string())))
+      .collect::<Vec<_>>();
+    Ok(self.get("users", &query).await?.data)
+  }
+
+  pub async fn get_user_by_login(&self, login: &str) -> Result<User, Error> {
+    self
+      .get_users(&[], &[login])
+      .await?
+      .into_iter()
+      .next()
+      .ok_or(Error::NotFound)
+  }
+
+  pub async fn get_current_user(&self) -> Result<User, Error> {
+    self
+      .get_users(&[], &[])
+      .await?
+      .into_iter()
+      .next()
+      .ok_or(Error::NotFound)
+  }
+
+  /// Blocks `target_user_id` for the user who owns the token.
+  pub async fn block_user(&self, target_user_id: &str) -> Result<(), Error> {
+    let query = [("target_user_id", target_user_id.to_string())];
+    self
+      .execute(Method::PUT, "users/blocks", &query, None::<&()>)
+      .await
+  }
+
+  pub async fn unblock_user(&self, target_user_id: &str) -> Result<(), Error> {
+    let query = [("target_user_id", target_user_id.to_string())];
+    self
+      .execute(Method::DELETE, "users/blocks", &query, None::<&()>)
+      .await
+  }
+}
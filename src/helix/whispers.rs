@@ -0,0 +1,32 @@
+use reqwest::Method;
+use serde::Serialize;
+
+use super::{Client, Error};
+
+#[derive(Serialize)]
+struct SendWhisper<'a> {
+  message: &'a str,
+}
+
+impl Client {
+  /// Requires a verified phone number on the sending account.
+  pub async fn send_whisper(
+    &self,
+    from_user_id: &str,
+    to_user_id: &str,
+    message: &str,
+  ) -> Result<(), Error> {
+    let query = [
+      ("from_user_id", from_user_id.to_string()),
+      ("to_user_id", to_user_id.to_string()),
+    ];
+    self
+      .execute(
+        Method::POST,
+        "whispers",
+        &query,
+        Some(&SendWhisper { message }),
+      )
+      .await
+  }
+}
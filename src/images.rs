@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use egui::{ColorImage, TextureHandle, TextureOptions, Vec2};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, ImageFormat, RgbaImage};
+use reqwest::StatusCode;
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::util::data_dir;
+use crate::window::Waker;
+
+/// Browsers treat shorter frame delays as unset, and so do we.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+/// How long to wait before loading an image which failed again.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+pub struct Config {
+  /// Images are only cached in memory if this is `None`.
+  pub cache_dir: Option<PathBuf>,
+  /// Cached files older than this are downloaded again.
+  pub max_age: Duration,
+  /// Maximum number of images being downloaded or decoded at the same time.
+  pub concurrency: usize,
+}
+
+impl Config {
+  pub fn new() -> Self {
+    Self {
+      cache_dir: data_dir().map(|dir| dir.join("cache").join("images")),
+      max_age: Duration::from_secs(7 * 24 * 60 * 60),
+      concurrency: 8,
+    }
+  }
+
+  pub fn with_cache_dir(mut self, cache_dir: Option<PathBuf>) -> Self {
+    self.cache_dir = cache_dir;
+    self
+  }
+
+  pub fn with_max_age(mut self, max_age: Duration) -> Self {
+    self.max_age = max_age;
+    self
+  }
+
+  pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+    self.concurrency = concurrency.max(1);
+    self
+  }
+
+  pub fn start(self, waker: Waker) -> Images {
+    Images::start(self, waker)
+  }
+}
+
+pub struct Image {
+  size: Vec2,
+  frames: Vec<(TextureHandle, Duration)>,
+  duration: Duration,
+}
+
+impl Image {
+  /// In pixels.
+  pub fn size(&self) -> Vec2 {
+    self.size
+  }
+
+  pub fn is_animated(&self) -> bool {
+    self.frames.len() > 1
+  }
+
+  /// The frame to show at `time` seconds, and how long it stays visible if the image is animated.
+  pub fn frame(&self, time: f64) -> (&TextureHandle, Option<Duration>) {
+    if !self.is_animated() {
+      return (&self.frames[0].0, None);
+    }
+
+    let total = self.duration.as_millis() as u64;
+    let mut t = (time * 1000.0) as u64 % total;
+    for (texture, delay) in &self.frames {
+      let delay = delay.as_millis() as u64;
+      if t < delay {
+        return (texture, Some(Duration::from_millis(delay - t)));
+      }
+      t -= delay;
+    }
+    let (texture, delay) = &self.frames[self.frames.len() - 1];
+    (texture, Some(*delay))
+  }
+}
+
+enum State {
+  Loading,
+  Loaded(Image),
+  /// Loaded again after `RETRY_DELAY`.
+  Failed(Instant),
+}
+
+struct Decoded {
+  size: [usize; 2],
+  frames: Vec<(ColorImage, Duration)>,
+}
+
+/// Loads images by URL on a background thread. The owner of the `Waker` is woken whenever
+/// an image finishes loading, and should then call `poll` to upload it.
+pub struct Images {
+  images: HashMap<String, State>,
+  requests: mpsc::UnboundedSender<String>,
+  loaded: mpsc::UnboundedReceiver<(String, Result<Decoded, Error>)>,
+}
+
+impl Images {
+  pub fn start(config: Config, waker: Waker) -> Self {
+    let (requests, request_rx) = mpsc::unbounded_channel();
+    let (loaded_tx, loaded) = mpsc::unbounded_channel();
+
+    thread::Builder::new()
+      .name("images".into())
+      .spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+          .enable_all()
+          .build()
+          .expect("failed to build image runtime");
+        runtime.block_on(run(config, request_rx, loaded_tx, waker));
+      })
+      .expect("failed to spawn image thread");
+
+    Self {
+      images: HashMap::new(),
+      requests,
+      loaded,
+    }
+  }
+
+  /// Returns the image if it has been loaded, otherwise starts loading it.
+  pub fn get(&mut self, url: &str) -> Option<&Image> {
+    let retry = match self.images.get(url) {
+      None => true,
+      Some(State::Failed(at)) => at.elapsed() >= RETRY_DELAY,
+      Some(State::Loading | State::Loaded(_)) => false,
+    };
+    if retry {
+      self.images.insert(url.into(), State::Loading);
+      let _ = self.requests.send(url.into());
+      return None;
+    }
+    match &self.images[url] {
+      State::Loaded(image) => Some(image),
+      State::Loading | State::Failed(_) => None,
+    }
+  }
+
+  /// Uploads the frames of every image which finished loading since the last call.
+  pub fn poll(&mut self, ui: &egui::Context) {
+    while let Ok((url, result)) = self.loaded.try_recv() {
+      let state = match result {
+        Ok(decoded) => State::Loaded(upload(ui, &url, decoded)),
+        Err(_) => State::Failed(Instant::now()),
+      };
+      self.images.insert(url, state);
+    }
+  }
+}
+
+fn upload(ui: &egui::Context, url: &str, decoded: Decoded) -> Image {
+  let frames = decoded
+    .frames
+    .into_iter()
+    .enumerate()
+    .map(|(i, (image, delay))| {
+      let texture = ui.load_texture(format!("{url}#{i}"), image, TextureOptions::LINEAR);
+      (texture, delay)
+    })
+    .collect::<Vec<_>>();
+  Image {
+    size: Vec2::new(decoded.size[0] as f32, decoded.size[1] as f32),
+    duration: frames.iter().map(|(_, delay)| *delay).sum(),
+    frames,
+  }
+}
+
+async fn run(
+  config: Config,
+  mut requests: mpsc::UnboundedReceiver<String>,
+  loaded: mpsc::UnboundedSender<(String, Result<Decoded, Error>)>,
+  waker: Waker,
+) {
+  let config = Arc::new(config);
+  let http = reqwest::Client::new();
+  let limit = Arc::new(Semaphore::new(config.concurrency));
+
+  if let Some(dir) = config.cache_dir.clone() {
+    let max_age = config.max_age;
+    tokio::task::spawn_blocking(move || prune(&dir, max_age));
+  }
+
+  while let Some(url) = requests.recv().await {
+    let (config, http, limit) = (config.clone(), http.clone(), limit.clone());
+    let (loaded, waker) = (loaded.clone(), waker.clone());
+    tokio::spawn(async move {
+      let Ok(_permit) = limit.acquire().await else {
+        return;
+      };
+      let result = load(&config, &http, &url).await;
+      if let Err(e) = &result {
+        tracing::warn!(url, "{e}");
+      }
+      if loaded.send((url, result)).is_ok() {
+        waker.wake();
+      }
+    });
+  }
+}
+
+/// Loads the image from the cache, falling back to `url`. Only images which could be decoded
+/// are cached, and cached files which can't be decoded are removed.
+async fn load(config: &Config, http: &reqwest::Client, url: &str) -> Result<Decoded, Error> {
+  let path = config
+    .cache_dir
+    .as_ref()
+    .map(|dir| dir.join(cache_key(url)));
+  if let Some(path) = path.as_deref() {
+    if is_fresh(path, config.max_age).await {
+      let cached = match tokio::fs::read(path).await {
+        Ok(bytes) => decode_blocking(bytes).await.1,
+        Err(e) => Err(e.into()),
+      };
+      match cached {
+        Ok(decoded) => return Ok(decoded),
+        Err(e) => {
+          tracing::debug!(?path, "removing cached image: {e}");
+          let _ = tokio::fs::remove_file(path).await;
+        }
+      }
+    }
+  }
+
+  tracing::debug!(url, "fetching image");
+  let response = http.get(url).send().await?;
+  if !response.status().is_success() {
+    return Err(Error::Status(response.status()));
+  }
+  let bytes = response.bytes().await?.to_vec();
+  let (bytes, decoded) = decode_blocking(bytes).await;
+  let decoded = decoded?;
+  if let Some(path) = path.as_deref() {
+    if let Err(e) = store(path, &bytes).await {
+      tracing::debug!(?path, "failed to cache image: {e}");
+    }
+  }
+  Ok(decoded)
+}
+
+/// Decodes on the blocking pool, and hands the bytes back.
+async fn decode_blocking(bytes: Vec<u8>) -> (Vec<u8>, Result<Decoded, Error>) {
+  let task = tokio::task::spawn_blocking(move || {
+    let decoded = decode(&bytes);
+    (bytes, decoded)
+  });
+  match task.await {
+    Ok(result) => result,
+    Err(e) => (Vec::new(), Err(e.into())),
+  }
+}
+
+/// FNV-1a of the URL, which unlike `DefaultHasher` is stable across builds.
+fn cache_key(url: &str) -> String {
+  let mut hash = 0xcbf29ce484222325u64;
+  for b in url.bytes() {
+    hash ^= b as u64;
+    hash = hash.wrapping_mul(0x100000001b3);
+  }
+  format!("{hash:016x}")
+}
+
+fn is_expired(modified: SystemTime, max_age: Duration) -> bool {
+  modified.elapsed().map_or(true, |age| age > max_age)
+}
+
+async fn is_fresh(path: &Path, max_age: Duration) -> bool {
+  match tokio::fs::metadata(path).await.and_then(|m| m.modified()) {
+    Ok(modified) => !is_expired(modified, max_age),
+    Err(_) => false,
+  }
+}
+
+/// Writes to a temporary file first, so that a crash can't leave a truncated image behind.
+async fn store(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+  if let Some(dir) = path.parent() {
+    tokio::fs::create_dir_all(dir).await?;
+  }
+  let tmp = path.with_extension("tmp");
+  let result = match tokio::fs::write(&tmp, bytes).await {
+    Ok(()) => tokio::fs::rename(&tmp, path).await,
+    Err(e) => Err(e),
+  };
+  if result.is_err() {
+    let _ = tokio::fs::remove_file(&tmp).await;
+  }
+  result
+}
+
+/// Removes expired files from the cache.
+fn prune(dir: &Path, max_age: Duration) {
+  let Ok(entries) = std::fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let expired = entry
+      .metadata()
+      .and_then(|m| m.modified())
+      .is_ok_and(|modified| is_expired(modified, max_age));
+    if expired {
+      let _ = std::fs::remove_file(entry.path());
+    }
+  }
+}
+
+fn decode(bytes: &[u8]) -> Result<Decoded, Error> {
+  let frames = match image::guess_format(bytes)? {
+    ImageFormat::Gif => GifDecoder::new(Cursor::new(bytes))?
+      .into_frames()
+      .collect_frames()?,
+    ImageFormat::WebP => {
+      let decoder = WebPDecoder::new(Cursor::new(bytes))?;
+      if !decoder.has_animation() {
+        return still(bytes);
+      }
+      decoder.into_frames().collect_frames()?
+    }
+    ImageFormat::Png => {
+      let decoder = PngDecoder::new(Cursor::new(bytes))?;
+      if !decoder.is_apng() {
+        return still(bytes);
+      }
+      decoder.apng().into_frames().collect_frames()?
+    }
+    _ => return still(bytes),
+  };
+
+  let frames = frames
+    .into_iter()
+    .map(|frame| {
+      let (numer, denom) = frame.delay().numer_denom_ms();
+      let delay = Duration::from_millis((numer / denom.max(1)) as u64);
+      let delay = if delay < MIN_FRAME_DELAY {
+        DEFAULT_FRAME_DELAY
+      } else {
+        delay
+      };
+      (frame.into_buffer(), delay)
+    })
+    .collect::<Vec<_>>();
+  if frames.is_empty() {
+    return Err(Error::Empty);
+  }
+  Ok(Decoded {
+    size: size(&frames[0].0),
+    frames: frames
+      .into_iter()
+      .map(|(buffer, delay)| (color_image(&buffer), delay))
+      .collect(),
+  })
+}
+
+fn still(bytes: &[u8]) -> Result<Decoded, Error> {
+  let buffer = image::load_from_memory(bytes)?.to_rgba8();
+  Ok(Decoded {
+    size: size(&buffer),
+    frames: vec![(color_image(&buffer), Duration::ZERO)],
+  })
+}
+
+fn size(buffer: &RgbaImage) -> [usize; 2] {
+  [buffer.width() as usize, buffer.height() as usize]
+}
+
+fn color_image(buffer: &RgbaImage) -> ColorImage {
+  ColorImage::from_rgba_unmultiplied(size(buffer), buffer.as_raw())
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Http(reqwest::Error),
+  Status(StatusCode),
+  Io(std::io::Error),
+  Decode(image::ImageError),
+  Task(tokio::task::JoinError),
+  /// An animation without any frames.
+  Empty,
+}
+
+impl From<reqwest::Error> for Error {
+  fn from(value: reqwest::Error) -> Self {
+    Self::Http(value)
+  }
+}
+
+impl From<std::io::Error> for Error {
+  fn from(value: std::io::Error) -> Self {
+    Self::Io(value)
+  }
+}
+
+impl From<image::ImageError> for Error {
+  fn from(value: image::ImageError) -> Self {
+    Self::Decode(value)
+  }
+}
+
+impl From<tokio::task::JoinError> for Error {
+  fn from(value: tokio::task::JoinError) -> Self {
+    Self::Task(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Http(e) => write!(f, "failed to fetch image: {e}"),
+      Error::Status(status) => write!(f, "failed to fetch image: {status}"),
+      Error::Io(e) => write!(f, "failed to read cached image: {e}"),
+      Error::Decode(e) => write!(f, "failed to decode image: {e}"),
+      Error::Task(e) => write!(f, "failed to decode image: {e}"),
+      Error::Empty => write!(f, "failed to decode image: no frames"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use image::{DynamicImage, ImageOutputFormat};
+
+  use super::*;
+  use crate::testing::{Response, Server};
+
+  fn png() -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(RgbaImage::new(3, 2))
+      .write_to(&mut bytes, ImageOutputFormat::Png)
+      .unwrap();
+    bytes.into_inner()
+  }
+
+  #[tokio::test]
+  async fn caches_only_decodable_images() {
+    let server = Server::start(|request| match request.path.as_str() {
+      "/ok.png" => Response::new(200, png()),
+      _ => Response::new(200, "<html>not an image</html>"),
+    })
+    .await;
+    let dir = std::env::temp_dir().join(format!("nanochat-images-{:x}", rand::random::<u64>()));
+    let config = Config::new()
+      .with_cache_dir(Some(dir.clone()))
+      .with_max_age(Duration::from_secs(60));
+    let http = reqwest::Client::new();
+    let ok = format!("{}/ok.png", server.url());
+    let bad = format!("{}/bad.png", server.url());
+    let cached = dir.join(cache_key(&ok));
+
+    let image = load(&config, &http, &ok).await.unwrap();
+    assert_eq!(image.size, [3, 2]);
+    assert_eq!(std::fs::read(&cached).unwrap(), png());
+    assert!(!cached.with_extension("tmp").exists());
+
+    // served from the cache
+    load(&config, &http, &ok).await.unwrap();
+    assert_eq!(server.requests().len(), 1);
+
+    // a broken cache entry is removed and fetched again
+    std::fs::write(&cached, &png()[..20]).unwrap();
+    load(&config, &http, &ok).await.unwrap();
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(std::fs::read(&cached).unwrap(), png());
+
+    assert!(matches!(
+      load(&config, &http, &bad).await,
+      Err(Error::Decode(_))
+    ));
+    assert!(!dir.join(cache_key(&bad)).exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
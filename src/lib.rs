@@ -3,6 +3,17 @@
 pub mod util;
 
 pub mod app;
+pub mod badges;
+pub mod chat;
+pub mod config;
+pub mod emotes;
 pub mod error;
+pub mod event;
+pub mod eventsub;
+pub mod helix;
+pub mod images;
+pub mod recent_messages;
+#[cfg(test)]
+mod testing;
 pub mod twitch;
 pub mod window;
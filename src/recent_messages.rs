@@ -0,0 +1,216 @@
+use std::fmt::Display;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+pub const BASE_URL: &str = "https://recent-messages.robotty.de/api/v2";
+/// The most messages the service keeps per channel.
+pub const DEFAULT_LIMIT: u32 = 800;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+  /// Channels start out empty if this is disabled.
+  pub enabled: bool,
+  /// Any service with the same API as `recent-messages.robotty.de`.
+  pub base_url: String,
+  pub limit: u32,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      base_url: BASE_URL.into(),
+      limit: DEFAULT_LIMIT,
+    }
+  }
+}
+
+/// Loads the messages a channel received before we joined, so that it doesn't start out empty.
+#[derive(Clone)]
+pub struct RecentMessages {
+  http: reqwest::Client,
+  base_url: String,
+  limit: u32,
+}
+
+#[derive(Deserialize)]
+struct Response {
+  #[serde(default)]
+  messages: Vec<String>,
+  error: Option<String>,
+}
+
+impl RecentMessages {
+  pub fn new(http: reqwest::Client) -> Self {
+    Self {
+      http,
+      base_url: BASE_URL.into(),
+      limit: DEFAULT_LIMIT,
+    }
+  }
+
+  pub fn with_base_url(mut self, base_url: impl ToString) -> Self {
+    self.base_url = base_url.to_string();
+    self
+  }
+
+  pub fn with_limit(mut self, limit: u32) -> Self {
+    self.limit = limit;
+    self
+  }
+
+  /// `None` if `config` disables recent messages.
+  pub fn from_config(http: reqwest::Client, config: &Config) -> Option<Self> {
+    config.enabled.then(|| {
+      Self::new(http)
+        .with_base_url(config.base_url.trim_end_matches('/'))
+        .with_limit(config.limit)
+    })
+  }
+
+  /// The raw IRC messages, oldest first. Lines which fail to parse are skipped.
+  pub async fn get(&self, channel: &str) -> Result<Vec<twitch::Message>, Error> {
+    let url = format!("{}/recent-messages/{channel}", self.base_url);
+    tracing::debug!(url, "fetching recent messages");
+    let response = self
+      .http
+      .get(&url)
+      .query(&[("limit", self.limit)])
+      .send()
+      .await?;
+    let status = response.status();
+    // errors such as a channel being excluded still come with a JSON body
+    let body = match response.json::<Response>().await {
+      Ok(body) => body,
+      Err(_) if !status.is_success() => return Err(Error::Status(status)),
+      Err(e) => return Err(e.into()),
+    };
+    if let Some(error) = body.error {
+      return Err(Error::Service(error));
+    }
+    if !status.is_success() {
+      return Err(Error::Status(status));
+    }
+
+    let messages = body
+      .messages
+      .into_iter()
+      .filter_map(|line| match twitch::parse(line) {
+        Ok(message) => Some(message),
+        Err(line) => {
+          tracing::warn!(channel, "invalid recent message `{line}`");
+          None
+        }
+      })
+      .collect();
+    Ok(messages)
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Http(reqwest::Error),
+  Status(StatusCode),
+  /// The service responded with an error message.
+  Service(String),
+}
+
+impl From<reqwest::Error> for Error {
+  fn from(value: reqwest::Error) -> Self {
+    Self::Http(value)
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Http(e) => write!(f, "failed to load recent messages: {e}"),
+      Error::Status(status) => write!(f, "failed to load recent messages: {status}"),
+      Error::Service(e) => write!(f, "failed to load recent messages: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::chat::event::ChatEvent;
+  use crate::chat::history::{History, Moderation};
+  use crate::testing::{Response, Server};
+
+  fn privmsg(id: &str, login: &str, user_id: &str, timestamp: u64, text: &str) -> String {
+    format!(
+      "@badges=;color=;display-name={login};emotes=;id={id};room-id=1;tmi-sent-ts={timestamp};user-id={user_id} :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #forsen :{text}"
+    )
+  }
+
+  fn event(line: String) -> ChatEvent {
+    ChatEvent::parse(&twitch::parse(line).unwrap()).unwrap()
+  }
+
+  fn ids(history: &History) -> Vec<(String, bool, Option<Moderation>)> {
+    let channel = history.channel("forsen").unwrap();
+    channel
+      .iter()
+      .map(|e| (e.message.id().unwrap().into(), e.historical, e.moderation))
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn backfills_history() {
+    let lines = vec![
+      privmsg("a", "ronni", "2", 1642715690000, "first"),
+      privmsg("b", "pajlada", "3", 1642715691000, "second"),
+      // the service may repeat a message
+      privmsg("b", "pajlada", "3", 1642715691000, "second"),
+      "@room-id=1;target-user-id=2;tmi-sent-ts=1642715695000 :tmi.twitch.tv CLEARCHAT #forsen :ronni"
+        .to_string(),
+      privmsg("c", "ronni", "2", 1642715696000, "third"),
+      // already received live
+      privmsg("d", "pajlada", "3", 1642715700000, "live"),
+    ];
+    let server = Server::start(move |request| match request.path.as_str() {
+      "/recent-messages/forsen" => Response::json(json!({ "messages": lines, "error": null })),
+      _ => Response::json(json!({ "messages": [], "error": "channel not found" })),
+    })
+    .await;
+    let recent = RecentMessages::new(reqwest::Client::new())
+      .with_base_url(server.url())
+      .with_limit(50);
+
+    let mut history = History::new(100);
+    history.handle(&event(privmsg("d", "pajlada", "3", 1642715700000, "live")));
+    let live = history.channel("forsen").unwrap().by_id("d").unwrap().seq;
+
+    let messages = recent.get("forsen").await.unwrap();
+    assert_eq!(server.requests()[0].query("limit"), ["50"]);
+    let events = messages
+      .iter()
+      .filter_map(ChatEvent::parse)
+      .collect::<Vec<_>>();
+    history.backfill("forsen", &events);
+
+    assert_eq!(
+      ids(&history),
+      [
+        ("a".into(), true, Some(Moderation::Banned)),
+        ("b".into(), true, None),
+        ("c".into(), true, None),
+        ("d".into(), false, None),
+      ]
+    );
+    // the live message keeps its sequence number
+    let channel = history.channel("forsen").unwrap();
+    assert_eq!(channel.get(live).unwrap().message.id(), Some("d"));
+    assert!(matches!(
+      recent.get("nymn").await,
+      Err(Error::Service(e)) if e == "channel not found"
+    ));
+  }
+}
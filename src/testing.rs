@@ -0,0 +1,205 @@
+//! Stand-ins for the services the app talks to, so that clients can be tested against a local
+//! base URL.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+#[derive(Clone, Debug)]
+pub struct Request {
+  pub method: String,
+  /// Without the query.
+  pub path: String,
+  pub query: Vec<(String, String)>,
+  /// Header names are lowercase.
+  pub headers: Vec<(String, String)>,
+  pub body: String,
+}
+
+impl Request {
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .find(|(k, _)| k == name)
+      .map(|(_, v)| v.as_str())
+  }
+
+  pub fn query(&self, key: &str) -> Vec<&str> {
+    self
+      .query
+      .iter()
+      .filter(|(k, _)| k == key)
+      .map(|(_, v)| v.as_str())
+      .collect()
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+impl Response {
+  pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+    Self {
+      status,
+      headers: Vec::new(),
+      body: body.into(),
+    }
+  }
+
+  pub fn json(body: serde_json::Value) -> Self {
+    Self::new(200, body.to_string()).with_header("content-type", "application/json")
+  }
+
+  pub fn with_header(mut self, name: &str, value: impl ToString) -> Self {
+    self.headers.push((name.into(), value.to_string()));
+    self
+  }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// An HTTP server on a random local port which answers every request with `handler`, and keeps
+/// the requests it received.
+pub struct Server {
+  url: String,
+  requests: Arc<Mutex<Vec<Request>>>,
+  task: JoinHandle<()>,
+}
+
+impl Server {
+  pub async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let handler: Arc<Handler> = Arc::new(handler);
+    let task = tokio::spawn({
+      let requests = requests.clone();
+      async move {
+        while let Ok((stream, _)) = listener.accept().await {
+          let requests = requests.clone();
+          let handler = handler.clone();
+          tokio::spawn(async move {
+            let mut stream = BufReader::new(stream);
+            let Some(request) = read_request(&mut stream).await else {
+              return;
+            };
+            let response = handler(&request);
+            requests.lock().unwrap().push(request);
+            let _ = stream.get_mut().write_all(&encode(&response)).await;
+          });
+        }
+      }
+    });
+    Self {
+      url,
+      requests,
+      task,
+    }
+  }
+
+  /// `http://127.0.0.1:<port>`, without a trailing slash.
+  pub fn url(&self) -> &str {
+    &self.url
+  }
+
+  pub fn requests(&self) -> Vec<Request> {
+    self.requests.lock().unwrap().clone()
+  }
+}
+
+impl Drop for Server {
+  fn drop(&mut self) {
+    self.task.abort();
+  }
+}
+
+async fn read_request(stream: &mut BufReader<tokio::net::TcpStream>) -> Option<Request> {
+  let mut line = String::new();
+  stream.read_line(&mut line).await.ok()?;
+  let mut parts = line.split_whitespace();
+  let method = parts.next()?.to_string();
+  let target = parts.next()?;
+  let (path, query) = target.split_once('?').unwrap_or((target, ""));
+  let query = query
+    .split('&')
+    .filter(|pair| !pair.is_empty())
+    .map(|pair| {
+      let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+      (decode(k), decode(v))
+    })
+    .collect();
+
+  let mut headers = Vec::new();
+  loop {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = line.split_once(':') {
+      headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+    }
+  }
+  let length = headers
+    .iter()
+    .find(|(k, _)| k == "content-length")
+    .and_then(|(_, v)| v.parse().ok())
+    .unwrap_or(0);
+  let mut body = vec![0; length];
+  stream.read_exact(&mut body).await.ok()?;
+
+  Some(Request {
+    method,
+    path: path.into(),
+    query,
+    headers,
+    body: String::from_utf8_lossy(&body).into(),
+  })
+}
+
+fn encode(response: &Response) -> Vec<u8> {
+  let mut out = format!(
+    "HTTP/1.1 {} Stand-in\r\ncontent-length: {}\r\nconnection: close\r\n",
+    response.status,
+    response.body.len()
+  );
+  for (name, value) in &response.headers {
+    out += &format!("{name}: {value}\r\n");
+  }
+  out += "\r\n";
+  let mut out = out.into_bytes();
+  out.extend_from_slice(&response.body);
+  out
+}
+
+/// Percent-decodes a query component.
+fn decode(s: &str) -> String {
+  let bytes = s.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'%' if i + 2 < bytes.len() => {
+        let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+        match u8::from_str_radix(hex, 16) {
+          Ok(b) => out.push(b),
+          Err(_) => out.extend_from_slice(&bytes[i..i + 3]),
+        }
+        i += 3;
+        continue;
+      }
+      b'+' => out.push(b' '),
+      b => out.push(b),
+    }
+    i += 1;
+  }
+  String::from_utf8_lossy(&out).into()
+}
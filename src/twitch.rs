@@ -1,4 +1,5 @@
 pub mod conn;
+mod dual;
 mod read;
 mod write;
 
@@ -21,9 +22,13 @@ use crate::util::Timeout;
 
 use self::conn::tls::{TlsConfig, TlsConfigError};
 use self::conn::OpenStreamError;
-use self::read::{ReadError, ReadStream};
+use self::read::ReadStream;
 use self::write::WriteStream;
 
+pub use self::dual::DualClient;
+pub use self::read::ReadError;
+pub use self::write::WriteError;
+
 pub struct ChatConfig {
   pub nick: String,
   pub pass: String,
@@ -44,9 +49,22 @@ impl ChatConfig {
     }
   }
 
+  /// The OAuth token embedded in `pass`, if this is not an anonymous login.
+  pub fn token(&self) -> Option<&str> {
+    self.pass.strip_prefix("oauth:")
+  }
+
   pub fn connect(self, timeout: Duration) -> impl Future<Output = Result<Client, ConnectionError>> {
     Client::connect(self, timeout)
   }
+
+  /// Like `connect`, but reads through a separate anonymous connection.
+  pub fn connect_dual(
+    self,
+    timeout: Duration,
+  ) -> impl Future<Output = Result<DualClient, ConnectionError>> {
+    DualClient::connect(self, timeout)
+  }
 }
 
 pub struct Client {
@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::read::ReadError;
+use super::write::WriteError;
+use super::{ChatConfig, Client, ConnectionError};
+
+/// The account's join rate limit, see <https://dev.twitch.tv/docs/irc/#rate-limits>.
+const MAX_JOINS: usize = 20;
+const JOIN_WINDOW: Duration = Duration::from_secs(10);
+
+/// Reads through an anonymous connection and writes through an authenticated one.
+///
+/// Chat is only read from the anonymous connection, so reading is unaffected by the writer
+/// reconnecting. Channels are joined on both, because Twitch only sends the
+/// account's `USERSTATE`, e.g. whether it is a moderator, to a connection which joined the
+/// channel. The writer's joins count towards the account's join rate limit, so they are paced
+/// to stay under it and may lag behind the reader when joining many channels at once.
+pub struct DualClient {
+  reader: Client,
+  writer: Client,
+  /// The connection which failed or was asked to reconnect, `None` if both were or neither was.
+  broken: Option<Side>,
+  /// Channels the writer joined or is about to join.
+  channels: Vec<String>,
+  joins: JoinQueue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+  Reader,
+  Writer,
+}
+
+impl DualClient {
+  pub async fn connect(
+    config: ChatConfig,
+    timeout: Duration,
+  ) -> Result<DualClient, ConnectionError> {
+    tracing::debug!("connecting reader and writer");
+    let (reader, writer) = tokio::try_join!(
+      Client::connect(ChatConfig::anon(), timeout),
+      Client::connect(config, timeout),
+    )?;
+    Ok(DualClient {
+      reader,
+      writer,
+      broken: None,
+      channels: Vec::new(),
+      joins: JoinQueue::default(),
+    })
+  }
+
+  /// Returns the next message from either connection.
+  ///
+  /// `PING`s are answered on the connection they arrived on. From the writer, only
+  /// messages about our own user or our own messages are returned, e.g. `USERSTATE` and `NOTICE`,
+  /// and the reader's `USERSTATE`s, which are about the anonymous user, are dropped.
+  pub async fn message(&mut self) -> Result<twitch::Message, ReadError> {
+    loop {
+      let next_join = self.joins.next_slot(Instant::now());
+      let (side, message) = tokio::select! {
+        message = self.reader.message() => (Side::Reader, message),
+        message = self.writer.message() => (Side::Writer, message),
+        _ = tokio::time::sleep_until(next_join.unwrap_or_else(Instant::now)), if next_join.is_some() => {
+          if let Err(WriteError::Io(e)) = self.send_joins().await {
+            return Err(ReadError::Io(e));
+          }
+          continue;
+        }
+      };
+      let message = match message {
+        Ok(message) => message,
+        Err(e) => {
+          self.mark_broken(side);
+          return Err(e);
+        }
+      };
+      if let twitch::Command::Reconnect = message.command() {
+        self.mark_broken(side);
+      }
+
+      if let twitch::Command::Ping = message.command() {
+        let pong = format!("PONG {}\r\n", message.params().unwrap_or(":tmi.twitch.tv"));
+        let result = match side {
+          Side::Reader => self.reader.send(&pong).await,
+          Side::Writer => self.writer.send(&pong).await,
+        };
+        if let Err(WriteError::Io(e)) = result {
+          self.mark_broken(side);
+          return Err(ReadError::Io(e));
+        }
+        continue;
+      }
+
+      if keep(side, &message) {
+        return Ok(message);
+      }
+    }
+  }
+
+  /// `JOIN` and `PART` go to both connections, everything else goes to the writer.
+  pub async fn send(&mut self, s: &str) -> Result<(), WriteError> {
+    if let Some(channel) = s.strip_prefix("JOIN #") {
+      self.send_to(Side::Reader, s).await?;
+      let channel = channel.trim_end();
+      if !self.channels.iter().any(|c| c == channel) {
+        self.channels.push(channel.into());
+        self.joins.push(channel);
+      }
+      return self.send_joins().await;
+    }
+    if let Some(channel) = s.strip_prefix("PART #") {
+      self.send_to(Side::Reader, s).await?;
+      let channel = channel.trim_end();
+      self.channels.retain(|c| c != channel);
+      if !self.joins.remove(channel) {
+        self.send_to(Side::Writer, s).await?;
+      }
+      return Ok(());
+    }
+    self.send_to(Side::Writer, s).await
+  }
+
+  async fn send_to(&mut self, side: Side, s: &str) -> Result<(), WriteError> {
+    let result = match side {
+      Side::Reader => self.reader.send(s).await,
+      Side::Writer => self.writer.send(s).await,
+    };
+    if result.is_err() {
+      self.mark_broken(side);
+    }
+    result
+  }
+
+  fn mark_broken(&mut self, side: Side) {
+    self.broken = match self.broken {
+      Some(broken) if broken != side => None,
+      _ => Some(side),
+    };
+  }
+
+  /// Sends the writer's pending joins which fit into the rate limit.
+  async fn send_joins(&mut self) -> Result<(), WriteError> {
+    while let Some(channel) = self.joins.pop(Instant::now()) {
+      self
+        .send_to(Side::Writer, &format!("JOIN #{channel}\r\n"))
+        .await?;
+    }
+    Ok(())
+  }
+
+  /// Reconnects whichever connection failed or received a `RECONNECT`, or both.
+  ///
+  /// The caller joins its channels again afterwards, which the writer only needs if it was
+  /// reconnected, so it rejoins them by itself.
+  pub async fn reconnect(&mut self, timeout: Duration) -> Result<(), ConnectionError> {
+    match self.broken.take() {
+      Some(Side::Reader) => self.reader.reconnect(timeout).await,
+      Some(Side::Writer) => {
+        self.writer.reconnect(timeout).await?;
+        self.rejoin();
+        Ok(())
+      }
+      None => {
+        self.reader.reconnect(timeout).await?;
+        self.writer.reconnect(timeout).await?;
+        self.rejoin();
+        Ok(())
+      }
+    }
+  }
+
+  fn rejoin(&mut self) {
+    self.joins.clear();
+    for channel in &self.channels {
+      self.joins.push(channel);
+    }
+  }
+}
+
+/// The writer's `JOIN`s which haven't been sent yet, and when the recent ones were sent.
+#[derive(Default)]
+struct JoinQueue {
+  pending: VecDeque<String>,
+  sent: VecDeque<Instant>,
+}
+
+impl JoinQueue {
+  fn push(&mut self, channel: &str) {
+    self.pending.push_back(channel.into());
+  }
+
+  /// Returns whether `channel` was still pending.
+  fn remove(&mut self, channel: &str) -> bool {
+    let len = self.pending.len();
+    self.pending.retain(|c| c != channel);
+    self.pending.len() != len
+  }
+
+  fn clear(&mut self) {
+    self.pending.clear();
+  }
+
+  /// When the next pending join may be sent, or `None` if none are pending.
+  fn next_slot(&mut self, now: Instant) -> Option<Instant> {
+    if self.pending.is_empty() {
+      return None;
+    }
+    while self
+      .sent
+      .front()
+      .is_some_and(|sent| now.duration_since(*sent) >= JOIN_WINDOW)
+    {
+      self.sent.pop_front();
+    }
+    match self.sent.len() < MAX_JOINS {
+      true => Some(now),
+      false => self.sent.front().map(|sent| *sent + JOIN_WINDOW),
+    }
+  }
+
+  /// The next pending join, if it may be sent at `now`.
+  fn pop(&mut self, now: Instant) -> Option<String> {
+    if self.next_slot(now)? > now {
+      return None;
+    }
+    self.sent.push_back(now);
+    self.pending.pop_front()
+  }
+}
+
+/// Whether a message from `side` is passed on.
+fn keep(side: Side, message: &twitch::Message) -> bool {
+  match side {
+    Side::Reader => !is_user_state(message),
+    Side::Writer => is_writer_message(message),
+  }
+}
+
+fn is_user_state(message: &twitch::Message) -> bool {
+  use twitch::Command::*;
+
+  matches!(message.command(), UserState | GlobalUserState)
+}
+
+fn is_writer_message(message: &twitch::Message) -> bool {
+  use twitch::Command::*;
+
+  matches!(
+    message.command(),
+    Notice | UserState | GlobalUserState | Whisper | Reconnect
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn message(line: &str) -> twitch::Message {
+    twitch::parse(line.into()).unwrap()
+  }
+
+  #[test]
+  fn paces_joins() {
+    let start = Instant::now();
+    let mut joins = JoinQueue::default();
+    assert_eq!(joins.next_slot(start), None);
+    for i in 0..25 {
+      joins.push(&format!("c{i}"));
+    }
+    assert!(joins.remove("c24"));
+    assert!(!joins.remove("c24"));
+
+    let sent = std::iter::from_fn(|| joins.pop(start)).collect::<Vec<_>>();
+    assert_eq!(sent.len(), MAX_JOINS);
+    assert_eq!(sent[0], "c0");
+    // the rest wait until the first joins are out of the window
+    assert_eq!(joins.next_slot(start), Some(start + JOIN_WINDOW));
+    let later = start + JOIN_WINDOW / 2;
+    assert_eq!(joins.pop(later), None);
+    let later = start + JOIN_WINDOW;
+    let sent = std::iter::from_fn(|| joins.pop(later)).collect::<Vec<_>>();
+    assert_eq!(sent, ["c20", "c21", "c22", "c23"]);
+    assert_eq!(joins.next_slot(later), None);
+
+    joins.push("c25");
+    joins.clear();
+    assert_eq!(joins.pop(later), None);
+  }
+
+  #[test]
+  fn splits_messages() {
+    let privmsg = message("@id=1;user-id=2 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi");
+    let user_state = message("@badges=moderator/1;mod=1 :tmi.twitch.tv USERSTATE #dallas");
+    let notice =
+      message("@msg-id=slow_on :tmi.twitch.tv NOTICE #dallas :This room is now in slow mode.");
+    let reconnect = message(":tmi.twitch.tv RECONNECT");
+
+    // chat is read from the reader only, and our own state from the writer only
+    assert!(keep(Side::Reader, &privmsg));
+    assert!(!keep(Side::Writer, &privmsg));
+    assert!(!keep(Side::Reader, &user_state));
+    assert!(keep(Side::Writer, &user_state));
+    assert!(keep(Side::Writer, &notice));
+    assert!(keep(Side::Reader, &reconnect));
+    assert!(keep(Side::Writer, &reconnect));
+  }
+}
@@ -14,3 +14,22 @@ where
     tokio::time::timeout(duration, self)
   }
 }
+
+/// `~/.nanochat`, where caches and other local data are stored.
+pub fn data_dir() -> Option<std::path::PathBuf> {
+  home::home_dir().map(|home| home.join(".nanochat"))
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it, so that a crash can't leave
+/// a truncated file behind.
+pub fn write_atomic(path: &std::path::Path, bytes: &[u8]) -> std::io::Result<()> {
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  let tmp = path.with_extension("tmp");
+  let result = std::fs::write(&tmp, bytes).and_then(|()| std::fs::rename(&tmp, path));
+  if result.is_err() {
+    let _ = std::fs::remove_file(&tmp);
+  }
+  result
+}
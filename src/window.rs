@@ -25,14 +25,21 @@ pub struct Context<'a, E: 'static> {
   id: Id,
   ui: &'a egui::Context,
   event_queue: &'a EventQueue<E>,
+  repaint_signal: &'a RepaintSignal,
 }
 
 impl<'a, E: 'static> Context<'a, E> {
-  fn new(id: Id, ui: &'a egui::Context, event_queue: &'a EventQueue<E>) -> Self {
+  fn new(
+    id: Id,
+    ui: &'a egui::Context,
+    event_queue: &'a EventQueue<E>,
+    repaint_signal: &'a RepaintSignal,
+  ) -> Self {
     Self {
       id,
       ui,
       event_queue,
+      repaint_signal,
     }
   }
 
@@ -44,6 +51,14 @@ impl<'a, E: 'static> Context<'a, E> {
     self.ui
   }
 
+  /// A handle which redraws this window when woken, usable from any thread.
+  pub fn waker(&self) -> Waker {
+    Waker {
+      id: self.id,
+      signal: self.repaint_signal.clone(),
+    }
+  }
+
   pub fn notify(&self, recipient: Id, event: E) {
     self
       .event_queue
@@ -59,6 +74,27 @@ impl<'a, E: 'static> Context<'a, E> {
   }
 }
 
+#[derive(Clone)]
+pub struct Waker {
+  id: Id,
+  signal: RepaintSignal,
+}
+
+impl Waker {
+  pub fn id(&self) -> Id {
+    self.id
+  }
+
+  pub fn wake(&self) {
+    let _ = self
+      .signal
+      .0
+      .lock()
+      .unwrap()
+      .send_event(UserEvent::Wake(self.id));
+  }
+}
+
 fn next_id() -> Id {
   static ID: AtomicU64 = AtomicU64::new(0);
 
@@ -150,6 +186,9 @@ impl<E: 'static> WindowManager<E> {
       WinitEvent::UserEvent(UserEvent::RequestRedraw(window_id)) => {
         exit_if!(manager.on_user_event(window_id), control_flow)
       }
+      WinitEvent::UserEvent(UserEvent::Wake(id)) => {
+        exit_if!(manager.on_wake(id), control_flow)
+      }
       WinitEvent::MainEventsCleared => {
         exit_if!(manager.on_main_events_cleared(), control_flow)
       }
@@ -208,6 +247,13 @@ impl<E: 'static> WindowManager<E> {
     Ok(())
   }
 
+  fn on_wake(&mut self, id: Id) -> Result<()> {
+    if let Some(window) = self.windows.get_mut(&id) {
+      window.on_user_event()?;
+    }
+    Ok(())
+  }
+
   fn on_main_events_cleared(&mut self) -> Result<()> {
     for (_, window) in self.windows.iter_mut() {
       window.on_main_events_cleared()?;
@@ -296,6 +342,8 @@ impl<E: 'static> WindowManager<E> {
 #[derive(Debug)]
 enum UserEvent {
   RequestRedraw(WindowId),
+  /// Sent by a `Waker`, which outlives the winit window if it is recreated.
+  Wake(Id),
 }
 
 #[derive(Clone)]
@@ -391,7 +439,7 @@ impl<E: 'static> Window<E> {
       let output = self.ctx.run(raw_input, |ui| {
         self
           .handler
-          .update_and_draw(Context::new(self.id, ui, event_queue))
+          .update_and_draw(Context::new(self.id, ui, event_queue, &self.repaint_signal))
       });
 
       self